@@ -0,0 +1,681 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use crate::util;
+use crate::{
+	Action,
+	AuthCallback,
+	Bool,
+	Handle,
+	Int,
+	OptionDescriptor,
+	Parameters,
+	Status,
+	StringConst,
+};
+
+// Backend {{{
+
+/// A SANE backend implemented in Rust.
+///
+/// Use [`export_backend!`](crate::export_backend) to export a backend from a
+/// shared library, so that it can be loaded by SANE frontends.
+pub trait Backend: Sized {
+	type Device;
+
+	/// `sane_init()`
+	fn init(authorize: Option<AuthCallback>) -> Result<Self, Status>;
+
+	/// The version code returned from `sane_init()`.
+	fn version_code(&self) -> u32 {
+		crate::version_code(crate::CURRENT_MAJOR, crate::CURRENT_MINOR, 0)
+	}
+
+	/// `sane_get_devices()`
+	fn get_devices(
+		&mut self,
+		local_only: bool,
+	) -> Result<Vec<util::DeviceBuf>, Status>;
+
+	/// `sane_open()`
+	fn open(&mut self, device_name: &CStr) -> Result<Self::Device, Status>;
+
+	/// `sane_close()`
+	fn close(&mut self, device: Self::Device);
+
+	/// `sane_get_option_descriptor()`
+	///
+	/// Called when the device is opened, and again whenever
+	/// [`Backend::control_option`] returns `SANE_INFO_RELOAD_OPTIONS`.
+	fn get_option_descriptors(
+		&mut self,
+		device: &mut Self::Device,
+	) -> Vec<util::OptionDescriptorBuf>;
+
+	/// `sane_control_option()`
+	///
+	/// `value` is the option's value in its native representation, with the
+	/// length given by the option descriptor's size. On success, returns the
+	/// `SANE_INFO_*` flags.
+	fn control_option(
+		&mut self,
+		device: &mut Self::Device,
+		option: u32,
+		action: Action,
+		value: &mut [u8],
+	) -> Result<u32, Status>;
+
+	/// `sane_get_parameters()`
+	fn get_parameters(
+		&mut self,
+		device: &mut Self::Device,
+	) -> Result<Parameters, Status>;
+
+	/// `sane_start()`
+	fn start(&mut self, device: &mut Self::Device) -> Result<(), Status>;
+
+	/// `sane_read()`
+	///
+	/// Returns `Err(Status::EOF)` when there is no more image data.
+	fn read(
+		&mut self,
+		device: &mut Self::Device,
+		buf: &mut [u8],
+	) -> Result<usize, Status>;
+
+	/// `sane_cancel()`
+	fn cancel(&mut self, device: &mut Self::Device);
+
+	/// `sane_set_io_mode()`
+	fn set_io_mode(
+		&mut self,
+		device: &mut Self::Device,
+		non_blocking: bool,
+	) -> Result<(), Status> {
+		let _ = (device, non_blocking);
+		Err(Status::UNSUPPORTED)
+	}
+
+	/// `sane_get_select_fd()`
+	fn get_select_fd(
+		&mut self,
+		device: &mut Self::Device,
+	) -> Result<i32, Status> {
+		let _ = device;
+		Err(Status::UNSUPPORTED)
+	}
+}
+
+// }}}
+
+// export_backend {{{
+
+/// Exports the SANE API entry points of a [`Backend`].
+///
+/// With only a type, the entry points have the plain `sane_*` names. If a
+/// backend name is given they are named `sane_<name>_*`, as expected by the
+/// `dll` backend when loading `libsane-<name>.so`.
+///
+/// ```ignore
+/// sane::export_backend!(MyBackend, "mybackend");
+/// ```
+#[macro_export]
+macro_rules! export_backend {
+	($backend:ty) => {
+		$crate::export_backend!(@export $backend, "sane_");
+	};
+	($backend:ty, $name:literal) => {
+		$crate::export_backend!(@export $backend, concat!("sane_", $name, "_"));
+	};
+	(@export $backend:ty, $prefix:expr) => {
+		const _: () = {
+			use core::mem::MaybeUninit;
+			use $crate::{
+				Action,
+				AuthCallback,
+				Bool,
+				Device,
+				Handle,
+				Int,
+				OptionDescriptor,
+				Parameters,
+				Status,
+				StringConst,
+			};
+
+			static EXPORTER: $crate::backend::Exporter<$backend> =
+				$crate::backend::Exporter::new();
+
+			#[export_name = concat!($prefix, "init")]
+			unsafe extern "C" fn init(
+				version_code: *mut MaybeUninit<Int>,
+				authorize: Option<AuthCallback>,
+			) -> Status {
+				EXPORTER.init(version_code, authorize)
+			}
+
+			#[export_name = concat!($prefix, "exit")]
+			unsafe extern "C" fn exit() {
+				EXPORTER.exit()
+			}
+
+			#[export_name = concat!($prefix, "get_devices")]
+			unsafe extern "C" fn get_devices(
+				device_list: *mut MaybeUninit<*const *const Device>,
+				local_only: Bool,
+			) -> Status {
+				EXPORTER.get_devices(device_list, local_only)
+			}
+
+			#[export_name = concat!($prefix, "open")]
+			unsafe extern "C" fn open(
+				device_name: StringConst,
+				handle: *mut MaybeUninit<Handle>,
+			) -> Status {
+				EXPORTER.open(device_name, handle)
+			}
+
+			#[export_name = concat!($prefix, "close")]
+			unsafe extern "C" fn close(handle: Handle) {
+				EXPORTER.close(handle)
+			}
+
+			#[export_name = concat!($prefix, "get_option_descriptor")]
+			unsafe extern "C" fn get_option_descriptor(
+				handle: Handle,
+				option: Int,
+			) -> *const OptionDescriptor {
+				EXPORTER.get_option_descriptor(handle, option)
+			}
+
+			#[export_name = concat!($prefix, "control_option")]
+			unsafe extern "C" fn control_option(
+				handle: Handle,
+				option: Int,
+				action: Action,
+				value: *mut MaybeUninit<()>,
+				info: *mut MaybeUninit<Int>,
+			) -> Status {
+				EXPORTER.control_option(handle, option, action, value, info)
+			}
+
+			#[export_name = concat!($prefix, "get_parameters")]
+			unsafe extern "C" fn get_parameters(
+				handle: Handle,
+				params: *mut MaybeUninit<Parameters>,
+			) -> Status {
+				EXPORTER.get_parameters(handle, params)
+			}
+
+			#[export_name = concat!($prefix, "start")]
+			unsafe extern "C" fn start(handle: Handle) -> Status {
+				EXPORTER.start(handle)
+			}
+
+			#[export_name = concat!($prefix, "read")]
+			unsafe extern "C" fn read(
+				handle: Handle,
+				data: *mut MaybeUninit<u8>,
+				max_length: Int,
+				length: *mut MaybeUninit<Int>,
+			) -> Status {
+				EXPORTER.read(handle, data, max_length, length)
+			}
+
+			#[export_name = concat!($prefix, "cancel")]
+			unsafe extern "C" fn cancel(handle: Handle) {
+				EXPORTER.cancel(handle)
+			}
+
+			#[export_name = concat!($prefix, "set_io_mode")]
+			unsafe extern "C" fn set_io_mode(
+				handle: Handle,
+				non_blocking: Bool,
+			) -> Status {
+				EXPORTER.set_io_mode(handle, non_blocking)
+			}
+
+			#[export_name = concat!($prefix, "get_select_fd")]
+			unsafe extern "C" fn get_select_fd(
+				handle: Handle,
+				fd: *mut MaybeUninit<Int>,
+			) -> Status {
+				EXPORTER.get_select_fd(handle, fd)
+			}
+		};
+	};
+}
+
+// }}}
+
+// Exporter {{{
+
+/// State of an exported [`Backend`], shared by its entry points.
+///
+/// Each method implements the SANE API function of the same name. The
+/// device list and option descriptors returned to the caller are owned by
+/// the `Exporter`, and stay valid for as long as the SANE API requires.
+///
+/// Panics in the backend are caught and reported as
+/// `SANE_STATUS_IO_ERROR`.
+pub struct Exporter<B: Backend> {
+	instance: Mutex<Option<Instance<B>>>,
+	// Set by `sane_cancel()` without waiting for other calls to return.
+	cancelled: [AtomicBool; MAX_HANDLES],
+}
+
+// Handles have a fixed table of cancellation flags, so that looking one up
+// doesn't require a lock.
+const MAX_HANDLES: usize = 64;
+
+struct Instance<B: Backend> {
+	backend: B,
+	devices: util::DevicesBuf,
+	handles: Vec<Option<OpenDevice<B::Device>>>,
+}
+
+struct OpenDevice<D> {
+	device: D,
+	options: Vec<util::OptionDescriptorBuf>,
+	// Descriptors from before a `SANE_INFO_RELOAD_OPTIONS`, which must stay
+	// valid until the device is closed.
+	old_options: Vec<util::OptionDescriptorBuf>,
+}
+
+// The buffers own all of the memory their pointers refer to, so they can be
+// moved between threads along with the backend.
+unsafe impl<B> Send for Instance<B>
+where
+	B: Backend + Send,
+	B::Device: Send,
+{
+}
+
+impl<B: Backend> Exporter<B> {
+	pub const fn new() -> Exporter<B> {
+		#[allow(clippy::declare_interior_mutable_const)]
+		const NOT_CANCELLED: AtomicBool = AtomicBool::new(false);
+		Exporter {
+			instance: Mutex::new(None),
+			cancelled: [NOT_CANCELLED; MAX_HANDLES],
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Option<Instance<B>>> {
+		match self.instance.lock() {
+			Ok(guard) => guard,
+			Err(err) => err.into_inner(),
+		}
+	}
+
+	fn with_instance(
+		&self,
+		f: impl FnOnce(&mut Instance<B>) -> Result<(), Status>,
+	) -> Status {
+		catch_unwind(|| {
+			let mut guard = self.lock();
+			let instance = guard.as_mut().ok_or(Status::INVAL)?;
+			self.cancel_pending(instance);
+			let result = f(instance);
+			self.cancel_pending(instance);
+			result
+		})
+	}
+
+	// Passes cancellations requested while the instance was locked on to
+	// the backend.
+	fn cancel_pending(&self, instance: &mut Instance<B>) {
+		for (idx, slot) in instance.handles.iter_mut().enumerate() {
+			if !self.cancelled[idx].swap(false, Ordering::SeqCst) {
+				continue;
+			}
+			if let Some(device) = slot {
+				instance.backend.cancel(&mut device.device);
+			}
+		}
+	}
+
+	fn with_device(
+		&self,
+		handle: Handle,
+		f: impl FnOnce(
+			&mut B,
+			&mut OpenDevice<B::Device>,
+		) -> Result<(), Status>,
+	) -> Status {
+		self.with_instance(|instance| {
+			match instance.handles.get_mut(handle_index(handle)) {
+				Some(Some(device)) => f(&mut instance.backend, device),
+				_ => Err(Status::INVAL),
+			}
+		})
+	}
+
+	/// `sane_init()`
+	///
+	/// If the backend is already initialized, it will be exited first.
+	///
+	/// # Safety
+	///
+	/// `version_code` must be null or valid for writes.
+	pub unsafe fn init(
+		&self,
+		version_code: *mut MaybeUninit<Int>,
+		authorize: Option<AuthCallback>,
+	) -> Status {
+		self.exit();
+		catch_unwind(|| {
+			let backend = B::init(authorize)?;
+			if let Some(version_code) = version_code.as_mut() {
+				let version = backend.version_code();
+				version_code.write(Int::new(version as i32));
+			}
+			*self.lock() = Some(Instance {
+				backend,
+				devices: util::DevicesBuf::new(),
+				handles: Vec::new(),
+			});
+			Ok(())
+		})
+	}
+
+	/// `sane_exit()`
+	///
+	/// Any devices that are still open will be closed.
+	pub fn exit(&self) {
+		catch_unwind(|| {
+			let instance = self.lock().take();
+			for cancelled in &self.cancelled {
+				cancelled.store(false, Ordering::SeqCst);
+			}
+			if let Some(mut instance) = instance {
+				for device in instance.handles.drain(..).flatten() {
+					instance.backend.close(device.device);
+				}
+			}
+			Ok(())
+		});
+	}
+
+	/// `sane_get_devices()`
+	///
+	/// # Safety
+	///
+	/// `device_list` must be valid for writes.
+	pub unsafe fn get_devices(
+		&self,
+		device_list: *mut MaybeUninit<*const *const crate::Device>,
+		local_only: Bool,
+	) -> Status {
+		self.with_instance(|instance| {
+			let local_only = local_only != Bool::FALSE;
+			let mut devices = util::DevicesBuf::new();
+			for device in instance.backend.get_devices(local_only)? {
+				devices.push(device);
+			}
+			instance.devices = devices;
+			(*device_list).write(instance.devices.as_ptr());
+			Ok(())
+		})
+	}
+
+	/// `sane_open()`
+	///
+	/// At most 64 devices may be open at once. Opening more fails with
+	/// `SANE_STATUS_NO_MEM`.
+	///
+	/// # Safety
+	///
+	/// `device_name` must be null or a valid C string, and `handle` must be
+	/// valid for writes.
+	pub unsafe fn open(
+		&self,
+		device_name: StringConst,
+		handle: *mut MaybeUninit<Handle>,
+	) -> Status {
+		self.with_instance(|instance| {
+			let handles = &instance.handles;
+			let idx = handles.iter().position(Option::is_none);
+			let idx = idx.unwrap_or(handles.len());
+			if idx >= MAX_HANDLES {
+				return Err(Status::NO_MEM);
+			}
+			let device_name = device_name.to_c_str();
+			let device_name = device_name.unwrap_or(util::CSTR_EMPTY);
+			let mut device = instance.backend.open(device_name)?;
+			let options = instance.backend.get_option_descriptors(&mut device);
+			let device = Some(OpenDevice {
+				device,
+				options,
+				old_options: Vec::new(),
+			});
+			self.cancelled[idx].store(false, Ordering::SeqCst);
+			if idx == instance.handles.len() {
+				instance.handles.push(device);
+			} else {
+				instance.handles[idx] = device;
+			}
+			(*handle).write(Handle::new((idx + 1) as *mut ()));
+			Ok(())
+		})
+	}
+
+	/// `sane_close()`
+	pub fn close(&self, handle: Handle) {
+		self.with_instance(|instance| {
+			let idx = handle_index(handle);
+			let slot = instance.handles.get_mut(idx).and_then(Option::take);
+			if let Some(device) = slot {
+				instance.backend.close(device.device);
+			}
+			Ok(())
+		});
+	}
+
+	/// `sane_get_option_descriptor()`
+	pub fn get_option_descriptor(
+		&self,
+		handle: Handle,
+		option: Int,
+	) -> *const OptionDescriptor {
+		let mut ptr = ptr::null();
+		self.with_device(handle, |_, device| {
+			let option = device.options.get(option.as_i32() as usize);
+			if let Some(option) = option {
+				ptr = option.as_ptr();
+			}
+			Ok(())
+		});
+		ptr
+	}
+
+	/// `sane_control_option()`
+	///
+	/// # Safety
+	///
+	/// `value` must be null or valid for reads and writes of the option's
+	/// size, and `info` must be null or valid for writes.
+	pub unsafe fn control_option(
+		&self,
+		handle: Handle,
+		option: Int,
+		action: Action,
+		value: *mut MaybeUninit<()>,
+		info: *mut MaybeUninit<Int>,
+	) -> Status {
+		self.with_device(handle, |backend, device| {
+			let option = option.as_i32() as u32;
+			let size = match device.options.get(option as usize) {
+				Some(descriptor) => descriptor.as_ref().size(),
+				None => return Err(Status::INVAL),
+			};
+			let value: &mut [u8] = if value.is_null() {
+				&mut []
+			} else {
+				core::slice::from_raw_parts_mut(value.cast(), size)
+			};
+			let info_bits = backend.control_option(
+				&mut device.device,
+				option,
+				action,
+				value,
+			)?;
+			if info_bits & crate::INFO_RELOAD_OPTIONS != 0 {
+				let options =
+					backend.get_option_descriptors(&mut device.device);
+				let old = core::mem::replace(&mut device.options, options);
+				device.old_options.extend(old);
+			}
+			if let Some(info) = info.as_mut() {
+				info.write(Int::new(info_bits as i32));
+			}
+			Ok(())
+		})
+	}
+
+	/// `sane_get_parameters()`
+	///
+	/// # Safety
+	///
+	/// `params` must be valid for writes.
+	pub unsafe fn get_parameters(
+		&self,
+		handle: Handle,
+		params: *mut MaybeUninit<Parameters>,
+	) -> Status {
+		self.with_device(handle, |backend, device| {
+			(*params).write(backend.get_parameters(&mut device.device)?);
+			Ok(())
+		})
+	}
+
+	/// `sane_start()`
+	pub fn start(&self, handle: Handle) -> Status {
+		self.with_device(handle, |backend, device| {
+			backend.start(&mut device.device)
+		})
+	}
+
+	/// `sane_read()`
+	///
+	/// Returns `SANE_STATUS_GOOD` without reading if `max_length` is not
+	/// positive, and `SANE_STATUS_INVAL` if `data` is null.
+	///
+	/// # Safety
+	///
+	/// `data` must be null or valid for writes of `max_length` bytes, and
+	/// `length` must be valid for writes.
+	pub unsafe fn read(
+		&self,
+		handle: Handle,
+		data: *mut MaybeUninit<u8>,
+		max_length: Int,
+		length: *mut MaybeUninit<Int>,
+	) -> Status {
+		(*length).write(Int::new(0));
+		self.with_device(handle, |backend, device| {
+			if max_length.as_i32() <= 0 {
+				return Ok(());
+			}
+			if data.is_null() {
+				return Err(Status::INVAL);
+			}
+			let max_length = max_length.as_i32() as usize;
+			let buf = core::slice::from_raw_parts_mut(data.cast(), max_length);
+			let count = backend.read(&mut device.device, buf)?;
+			(*length).write(Int::new(count.min(max_length) as i32));
+			Ok(())
+		})
+	}
+
+	/// `sane_cancel()`
+	///
+	/// This may be called while another call is in progress, such as a
+	/// `sane_read()` blocked in another thread or interrupted by a signal
+	/// handler. The backend is then cancelled when that call returns, or
+	/// before the next call if it has already finished.
+	pub fn cancel(&self, handle: Handle) {
+		let cancelled = match self.cancelled.get(handle_index(handle)) {
+			Some(cancelled) => cancelled,
+			None => return,
+		};
+		cancelled.store(true, Ordering::SeqCst);
+		let mut guard = match self.instance.try_lock() {
+			Ok(guard) => guard,
+			Err(TryLockError::Poisoned(err)) => err.into_inner(),
+			Err(TryLockError::WouldBlock) => return,
+		};
+		catch_unwind(|| {
+			if let Some(instance) = guard.as_mut() {
+				self.cancel_pending(instance);
+			}
+			Ok(())
+		});
+	}
+
+	/// `sane_set_io_mode()`
+	pub fn set_io_mode(&self, handle: Handle, non_blocking: Bool) -> Status {
+		self.with_device(handle, |backend, device| {
+			let non_blocking = non_blocking != Bool::FALSE;
+			backend.set_io_mode(&mut device.device, non_blocking)
+		})
+	}
+
+	/// `sane_get_select_fd()`
+	///
+	/// # Safety
+	///
+	/// `fd` must be valid for writes.
+	pub unsafe fn get_select_fd(
+		&self,
+		handle: Handle,
+		fd: *mut MaybeUninit<Int>,
+	) -> Status {
+		self.with_device(handle, |backend, device| {
+			let select_fd = backend.get_select_fd(&mut device.device)?;
+			(*fd).write(Int::new(select_fd));
+			Ok(())
+		})
+	}
+}
+
+impl<B: Backend> Default for Exporter<B> {
+	fn default() -> Self {
+		Exporter::new()
+	}
+}
+
+fn handle_index(handle: Handle) -> usize {
+	(handle.as_ptr() as usize).wrapping_sub(1)
+}
+
+// Unwinding out of an `extern "C"` function is undefined behavior.
+fn catch_unwind(f: impl FnOnce() -> Result<(), Status>) -> Status {
+	match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+		Ok(Ok(())) => Status::GOOD,
+		Ok(Err(status)) => status,
+		Err(_) => Status::IO_ERROR,
+	}
+}
+
+// }}}
@@ -0,0 +1,194 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use sane::backend::{Backend, Exporter};
+use sane::util;
+use sane::{
+	Action,
+	AuthCallback,
+	Handle,
+	Int,
+	Parameters,
+	Status,
+	StringConst,
+};
+
+const fn cstr(bytes: &[u8]) -> &CStr {
+	unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+}
+
+const CSTR_DEV_NAME: &CStr = cstr(b"test-device\x00");
+
+// TestBackend {{{
+
+// Reads block until `READ_RELEASED` is set. Only `exporter_cancel_read`
+// reaches the backend's `read()`, so tests can still run in parallel.
+static READ_STARTED: AtomicBool = AtomicBool::new(false);
+static READ_RELEASED: AtomicBool = AtomicBool::new(false);
+
+struct TestBackend;
+
+struct TestDevice {
+	cancelled: bool,
+}
+
+impl Backend for TestBackend {
+	type Device = TestDevice;
+
+	fn init(_authorize: Option<AuthCallback>) -> Result<Self, Status> {
+		Ok(TestBackend)
+	}
+
+	fn get_devices(
+		&mut self,
+		_local_only: bool,
+	) -> Result<Vec<util::DeviceBuf>, Status> {
+		Ok(vec![util::DeviceBuf::new(CSTR_DEV_NAME)])
+	}
+
+	fn open(&mut self, device_name: &CStr) -> Result<TestDevice, Status> {
+		if device_name != CSTR_DEV_NAME {
+			return Err(Status::INVAL);
+		}
+		Ok(TestDevice { cancelled: false })
+	}
+
+	fn close(&mut self, _device: TestDevice) {}
+
+	fn get_option_descriptors(
+		&mut self,
+		_device: &mut TestDevice,
+	) -> Vec<util::OptionDescriptorBuf> {
+		Vec::new()
+	}
+
+	fn control_option(
+		&mut self,
+		_device: &mut TestDevice,
+		_option: u32,
+		_action: Action,
+		_value: &mut [u8],
+	) -> Result<u32, Status> {
+		Err(Status::INVAL)
+	}
+
+	fn get_parameters(
+		&mut self,
+		_device: &mut TestDevice,
+	) -> Result<Parameters, Status> {
+		Ok(Parameters::new())
+	}
+
+	fn start(&mut self, device: &mut TestDevice) -> Result<(), Status> {
+		device.cancelled = false;
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		device: &mut TestDevice,
+		buf: &mut [u8],
+	) -> Result<usize, Status> {
+		if device.cancelled {
+			return Err(Status::CANCELLED);
+		}
+		READ_STARTED.store(true, Ordering::SeqCst);
+		while !READ_RELEASED.load(Ordering::SeqCst) {
+			std::thread::sleep(Duration::from_millis(1));
+		}
+		buf[0] = b'x';
+		Ok(1)
+	}
+
+	fn cancel(&mut self, device: &mut TestDevice) {
+		device.cancelled = true;
+	}
+}
+
+// }}}
+
+fn open_device(exporter: &Exporter<TestBackend>) -> Handle {
+	let status = unsafe { exporter.init(ptr::null_mut(), None) };
+	assert_eq!(status, Status::GOOD);
+	let mut handle = MaybeUninit::uninit();
+	let device_name = StringConst::from_c_str(CSTR_DEV_NAME);
+	let status = unsafe { exporter.open(device_name, &mut handle) };
+	assert_eq!(status, Status::GOOD);
+	unsafe { handle.assume_init() }
+}
+
+fn read(
+	exporter: &Exporter<TestBackend>,
+	handle: Handle,
+	data: *mut MaybeUninit<u8>,
+	max_length: i32,
+) -> (Status, i32) {
+	let mut length = MaybeUninit::uninit();
+	let status = unsafe {
+		exporter.read(handle, data, Int::new(max_length), &mut length)
+	};
+	(status, unsafe { length.assume_init() }.as_i32())
+}
+
+#[test]
+fn exporter_read_empty() {
+	static EXPORTER: Exporter<TestBackend> = Exporter::new();
+	let handle = open_device(&EXPORTER);
+	assert_eq!(EXPORTER.start(handle), Status::GOOD);
+
+	// The backend isn't called for reads of zero bytes, so `data` may be
+	// null.
+	let null = ptr::null_mut();
+	assert_eq!(read(&EXPORTER, handle, null, 0), (Status::GOOD, 0));
+	assert_eq!(read(&EXPORTER, handle, null, -1), (Status::GOOD, 0));
+	assert_eq!(read(&EXPORTER, handle, null, 4), (Status::INVAL, 0));
+
+	EXPORTER.exit();
+}
+
+#[test]
+fn exporter_cancel_read() {
+	static EXPORTER: Exporter<TestBackend> = Exporter::new();
+	let handle = open_device(&EXPORTER);
+	assert_eq!(EXPORTER.start(handle), Status::GOOD);
+
+	let handle_addr = handle.as_ptr() as usize;
+	let reader = std::thread::spawn(move || {
+		let handle = Handle::new(handle_addr as *mut ());
+		let mut buf = [MaybeUninit::uninit(); 4];
+		read(&EXPORTER, handle, buf.as_mut_ptr(), 4)
+	});
+	while !READ_STARTED.load(Ordering::SeqCst) {
+		std::thread::sleep(Duration::from_millis(1));
+	}
+
+	// Cancelling doesn't wait for the blocked read, and the backend is
+	// cancelled once the read returns.
+	EXPORTER.cancel(handle);
+	READ_RELEASED.store(true, Ordering::SeqCst);
+	assert_eq!(reader.join().unwrap(), (Status::GOOD, 1));
+
+	let mut buf = [MaybeUninit::uninit(); 4];
+	let result = read(&EXPORTER, handle, buf.as_mut_ptr(), 4);
+	assert_eq!(result, (Status::CANCELLED, 0));
+
+	EXPORTER.exit();
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::path::PathBuf;
+
+mod dll;
+pub use dll::*;
+
+mod net;
+pub use net::*;
+
+mod pass;
+pub use pass::*;
+
+mod saned;
+pub use saned::*;
+
+// search_dirs {{{
+
+const DEFAULT_DIRS: [&str; 2] = [".", "/etc/sane.d"];
+
+/// Directories searched for configuration files, in order.
+///
+/// Like libsane, these are the directories listed in `SANE_CONFIG_DIR`,
+/// separated by `:`. If `SANE_CONFIG_DIR` is unset, or ends with `:`, the
+/// default directories `.` and `/etc/sane.d` are searched after it.
+pub fn search_dirs() -> Vec<PathBuf> {
+	let env_dirs = match std::env::var_os("SANE_CONFIG_DIR") {
+		Some(env_dirs) => env_dirs,
+		None => return DEFAULT_DIRS.iter().map(PathBuf::from).collect(),
+	};
+	let mut dirs: Vec<PathBuf> = std::env::split_paths(&env_dirs)
+		.filter(|dir| !dir.as_os_str().is_empty())
+		.collect();
+	if env_dirs.to_string_lossy().ends_with(':') {
+		dirs.extend(DEFAULT_DIRS.iter().map(PathBuf::from));
+	}
+	dirs
+}
+
+// }}}
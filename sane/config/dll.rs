@@ -0,0 +1,139 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+// DllConf {{{
+
+/// The backends loaded by the `dll` meta-backend, from `dll.conf` and the
+/// files of the `dll.d` directory.
+///
+/// Each line is the name of a backend. Anything after the name, or after a
+/// `#`, is ignored.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DllConf {
+	backends: Vec<String>,
+}
+
+impl DllConf {
+	pub fn new() -> DllConf {
+		DllConf {
+			backends: Vec::new(),
+		}
+	}
+
+	/// Parses the contents of a `dll.conf` file. Lines that aren't valid
+	/// UTF-8 are ignored.
+	pub fn parse(content: &[u8]) -> DllConf {
+		let mut conf = DllConf::new();
+		conf.parse_into(content);
+		conf
+	}
+
+	/// Loads `dll.conf` and `dll.d` from `dir`. Either may be missing.
+	pub fn load(dir: impl AsRef<Path>) -> io::Result<DllConf> {
+		DllConf::load_dirs(&[dir.as_ref()])
+	}
+
+	/// Loads `dll.conf` and `dll.d` from the
+	/// [`search_dirs`](super::search_dirs), like libsane.
+	pub fn load_default() -> io::Result<DllConf> {
+		DllConf::load_dirs(&super::search_dirs())
+	}
+
+	/// Loads the first `dll.conf` file and the first `dll.d` directory
+	/// found in `dirs`.
+	///
+	/// The files of `dll.d` are read in order of their names, skipping
+	/// hidden files and backups ending with `~`. Backends listed more than
+	/// once are only loaded once.
+	pub fn load_dirs(dirs: &[impl AsRef<Path>]) -> io::Result<DllConf> {
+		let mut conf = DllConf::new();
+		for dir in dirs {
+			match std::fs::read(dir.as_ref().join("dll.conf")) {
+				Ok(content) => {
+					conf.parse_into(&content);
+					break;
+				},
+				Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+				Err(err) => return Err(err),
+			}
+		}
+		for dir in dirs {
+			let paths = match dll_d_paths(&dir.as_ref().join("dll.d")) {
+				Ok(paths) => paths,
+				Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+				Err(err) => return Err(err),
+			};
+			for path in paths {
+				conf.parse_into(&std::fs::read(path)?);
+			}
+			break;
+		}
+		Ok(conf)
+	}
+
+	pub fn backends(&self) -> &[String] {
+		&self.backends
+	}
+
+	/// Adds `backend`, unless it has already been added.
+	pub fn push(&mut self, backend: impl Into<String>) {
+		let backend = backend.into();
+		if !self.backends.contains(&backend) {
+			self.backends.push(backend);
+		}
+	}
+
+	fn parse_into(&mut self, content: &[u8]) {
+		for line in content.split(|&b| b == b'\n') {
+			let line = match core::str::from_utf8(line) {
+				Ok(line) => line,
+				Err(_) => continue,
+			};
+			let line = line.split('#').next().unwrap_or("");
+			if let Some(backend) = line.split_whitespace().next() {
+				self.push(backend);
+			}
+		}
+	}
+}
+
+impl Default for DllConf {
+	fn default() -> Self {
+		DllConf::new()
+	}
+}
+
+fn dll_d_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut paths = Vec::new();
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		let name = entry.file_name();
+		let name = name.to_string_lossy();
+		if name.starts_with('.') || name.ends_with('~') {
+			continue;
+		}
+		if entry.file_type()?.is_dir() {
+			continue;
+		}
+		paths.push(entry.path());
+	}
+	paths.sort();
+	Ok(paths)
+}
+
+// }}}
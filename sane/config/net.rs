@@ -0,0 +1,120 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::net;
+
+// NetConf {{{
+
+/// The `net.conf` file, which lists the hosts whose devices are made
+/// available by the `net` backend.
+///
+/// Each line is either an option of the form `name = value` or a host, as
+/// parsed by [`RemoteHost::parse`](net::RemoteHost::parse). Lines starting
+/// with `#` are comments.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetConf {
+	hosts: Vec<net::RemoteHost>,
+	connect_timeout: Option<Duration>,
+}
+
+impl NetConf {
+	pub fn new() -> NetConf {
+		NetConf {
+			hosts: Vec::new(),
+			connect_timeout: None,
+		}
+	}
+
+	/// `/etc/sane.d/net.conf`
+	pub fn default_path() -> &'static Path {
+		Path::new("/etc/sane.d/net.conf")
+	}
+
+	/// Parses the contents of a `net.conf` file. Invalid hosts, unknown
+	/// options, and lines that aren't valid UTF-8 are ignored.
+	pub fn parse(content: &[u8]) -> NetConf {
+		let mut conf = NetConf::new();
+		for line in content.split(|&b| b == b'\n') {
+			let line = match core::str::from_utf8(line) {
+				Ok(line) => line.trim(),
+				Err(_) => continue,
+			};
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			if let Some((name, value)) = line.split_once('=') {
+				if name.trim() == "connect_timeout" {
+					conf.connect_timeout = value
+						.trim()
+						.parse()
+						.ok()
+						.filter(|&secs| secs > 0)
+						.map(Duration::from_secs);
+				}
+				continue;
+			}
+			if let Some(host) = net::RemoteHost::parse(line) {
+				conf.hosts.push(host);
+			}
+		}
+		conf
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> io::Result<NetConf> {
+		let content = std::fs::read(path)?;
+		Ok(NetConf::parse(&content))
+	}
+
+	pub fn hosts(&self) -> &[net::RemoteHost] {
+		&self.hosts
+	}
+
+	pub fn push_host(&mut self, host: net::RemoteHost) {
+		self.hosts.push(host);
+	}
+
+	/// The `connect_timeout` option, or `None` if connections use the
+	/// system timeout.
+	pub fn connect_timeout(&self) -> Option<Duration> {
+		self.connect_timeout
+	}
+
+	pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+		self.connect_timeout = timeout;
+	}
+
+	/// Returns a [`DeviceAggregator`](net::DeviceAggregator) for the hosts
+	/// and connection timeout of this file.
+	pub fn device_aggregator(&self) -> net::DeviceAggregator {
+		let mut aggregator = net::DeviceAggregator::new();
+		aggregator.set_connect_timeout(self.connect_timeout);
+		for host in &self.hosts {
+			aggregator.add_host(host.clone());
+		}
+		aggregator
+	}
+}
+
+impl Default for NetConf {
+	fn default() -> Self {
+		NetConf::new()
+	}
+}
+
+// }}}
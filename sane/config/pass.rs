@@ -0,0 +1,254 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::fmt;
+
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::net;
+
+// PassEntry {{{
+
+/// A `user:password:resource` line of a [`PassFile`].
+#[derive(Clone, Eq, PartialEq)]
+pub struct PassEntry {
+	username: CString,
+	password: CString,
+	resource: CString,
+}
+
+impl fmt::Debug for PassEntry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("PassEntry")
+			.field("username", &self.username)
+			.field("resource", &self.resource)
+			.finish_non_exhaustive()
+	}
+}
+
+impl PassEntry {
+	pub fn new(
+		username: impl Into<CString>,
+		password: impl Into<CString>,
+		resource: impl Into<CString>,
+	) -> PassEntry {
+		PassEntry {
+			username: username.into(),
+			password: password.into(),
+			resource: resource.into(),
+		}
+	}
+
+	pub fn username(&self) -> &CStr {
+		&self.username
+	}
+
+	pub fn password(&self) -> &CStr {
+		&self.password
+	}
+
+	pub fn resource(&self) -> &CStr {
+		&self.resource
+	}
+}
+
+// }}}
+
+// PassFile {{{
+
+/// Credentials for authorization requests, in the format of the
+/// `$HOME/.sane/pass` file read by SANE frontends.
+///
+/// Each line has the form `user:password:resource`. Entries are looked up
+/// by the resource name, ignoring any `$MD5$` salt.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PassFile {
+	entries: Vec<PassEntry>,
+}
+
+impl PassFile {
+	pub fn new() -> PassFile {
+		PassFile {
+			entries: Vec::new(),
+		}
+	}
+
+	/// `$HOME/.sane/pass`
+	pub fn default_path() -> Option<PathBuf> {
+		let home = std::env::var_os("HOME")?;
+		Some(Path::new(&home).join(".sane").join("pass"))
+	}
+
+	/// Parses the contents of a pass file. Lines that aren't of the form
+	/// `user:password:resource` are ignored.
+	pub fn parse(content: &[u8]) -> PassFile {
+		let entries = content
+			.split(|&b| b == b'\n')
+			.filter_map(parse_entry)
+			.collect();
+		PassFile { entries }
+	}
+
+	/// Loads the pass file at `path`.
+	///
+	/// Like libsane, files that can be read by the group or other users are
+	/// refused, since they might expose passwords.
+	pub fn load(path: impl AsRef<Path>) -> io::Result<PassFile> {
+		let path = path.as_ref();
+		let mut file = std::fs::File::open(path)?;
+		check_permissions(path, &file)?;
+		let mut content = Vec::new();
+		file.read_to_end(&mut content)?;
+		Ok(PassFile::parse(&content))
+	}
+
+	/// Writes the entries in the pass file format.
+	pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+		for entry in &self.entries {
+			let username = entry.username.as_bytes();
+			let password = entry.password.as_bytes();
+			let resource = entry.resource.as_bytes();
+			let invalid = username.contains(&b':')
+				|| password.contains(&b':')
+				|| [username, password, resource]
+					.iter()
+					.any(|field| field.contains(&b'\n'));
+			if invalid {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"pass file entry contains a field separator",
+				));
+			}
+			w.write_all(username)?;
+			w.write_all(b":")?;
+			w.write_all(password)?;
+			w.write_all(b":")?;
+			w.write_all(resource)?;
+			w.write_all(b"\n")?;
+		}
+		Ok(())
+	}
+
+	/// Writes the entries to a file at `path`, which can only be read by
+	/// its owner.
+	pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut content = Vec::new();
+		self.write_to(&mut content)?;
+
+		let mut options = std::fs::OpenOptions::new();
+		options.write(true).create(true).truncate(true);
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::OpenOptionsExt;
+			options.mode(0o600);
+		}
+		let mut file = options.open(path.as_ref())?;
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let permissions = std::fs::Permissions::from_mode(0o600);
+			file.set_permissions(permissions)?;
+		}
+		file.write_all(&content)
+	}
+
+	pub fn entries(&self) -> &[PassEntry] {
+		&self.entries
+	}
+
+	pub fn push(&mut self, entry: PassEntry) {
+		self.entries.push(entry);
+	}
+
+	/// Returns the first entry for `resource`. Any `$MD5$` salt in
+	/// `resource` is ignored.
+	pub fn find(&self, resource: &CStr) -> Option<&PassEntry> {
+		let name = net::AuthResource::parse(resource).name();
+		self.entries
+			.iter()
+			.find(|entry| entry.resource.as_bytes() == name)
+	}
+
+	/// Builds a `SANE_NET_AUTHORIZE` request for `resource`, such as the
+	/// value of [`OpenReply::resource`](net::OpenReply::resource).
+	///
+	/// Passwords are only sent as MD5 digests, so `None` is returned if
+	/// `resource` has no `$MD5$` salt or there is no entry for it.
+	pub fn authorize_request(
+		&self,
+		resource: &CStr,
+	) -> Option<net::AuthorizeRequestBuf> {
+		let entry = self.find(resource)?;
+		let resource = net::AuthResource::parse(resource);
+		let password = entry.password.as_bytes();
+		resource.md5_authorize_request(entry.username.clone(), password)
+	}
+}
+
+impl Default for PassFile {
+	fn default() -> Self {
+		PassFile::new()
+	}
+}
+
+impl net::CredentialProvider for PassFile {
+	fn credentials(
+		&mut self,
+		resource: &net::AuthResource,
+	) -> Option<(CString, CString)> {
+		let entry = self.find(resource.as_c_str())?;
+		Some((entry.username.clone(), entry.password.clone()))
+	}
+}
+
+fn parse_entry(line: &[u8]) -> Option<PassEntry> {
+	let line = line.strip_suffix(b"\r").unwrap_or(line);
+	let mut fields = line.splitn(3, |&b| b == b':');
+	let username = CString::new(fields.next()?).ok()?;
+	let password = CString::new(fields.next()?).ok()?;
+	let resource = CString::new(fields.next()?).ok()?;
+	Some(PassEntry {
+		username,
+		password,
+		resource,
+	})
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path, file: &std::fs::File) -> io::Result<()> {
+	use std::os::unix::fs::PermissionsExt;
+	let mode = file.metadata()?.permissions().mode();
+	if mode & 0o077 != 0 {
+		return Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!(
+				"{} is accessible by other users (mode {:o}), refusing to read",
+				path.display(),
+				mode & 0o777,
+			),
+		));
+	}
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path, _file: &std::fs::File) -> io::Result<()> {
+	Ok(())
+}
+
+// }}}
@@ -0,0 +1,453 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::fmt;
+use core::ops::RangeInclusive;
+
+use std::ffi::CString;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::Path;
+
+// HostPattern {{{
+
+/// A line of the `saned.conf` host allow-list.
+#[non_exhaustive]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HostPattern {
+	/// `+`, which allows any host.
+	Any,
+	/// An IPv4 or IPv6 address. IPv6 addresses may be written in brackets.
+	Addr(IpAddr),
+	/// A subnet in CIDR notation, such as `192.168.0.0/24`.
+	Subnet { addr: IpAddr, prefix_len: u8 },
+	/// A hostname, which is resolved when checking a peer.
+	Name(String),
+}
+
+impl HostPattern {
+	/// Parses a host pattern, or returns `None` if `pattern` is empty or
+	/// is not a valid address, subnet, or hostname.
+	pub fn parse(pattern: &str) -> Option<HostPattern> {
+		if pattern == "+" {
+			return Some(HostPattern::Any);
+		}
+		if let Some((addr, prefix_len)) = pattern.split_once('/') {
+			let addr = parse_addr(addr)?;
+			let prefix_len: u8 = prefix_len.parse().ok()?;
+			let max_len = match addr {
+				IpAddr::V4(_) => 32,
+				IpAddr::V6(_) => 128,
+			};
+			if prefix_len > max_len {
+				return None;
+			}
+			return Some(HostPattern::Subnet { addr, prefix_len });
+		}
+		if let Some(addr) = parse_addr(pattern) {
+			return Some(HostPattern::Addr(addr));
+		}
+		let valid_name = !pattern.is_empty()
+			&& pattern.bytes().all(|b| {
+				b.is_ascii_alphanumeric() || b == b'-' || b == b'.'
+			});
+		if valid_name {
+			return Some(HostPattern::Name(pattern.to_owned()));
+		}
+		None
+	}
+
+	/// Returns whether `peer` is allowed by this pattern.
+	///
+	/// IPv4-mapped IPv6 addresses are treated as IPv4 addresses. Hostnames
+	/// are resolved with the system resolver.
+	pub fn matches(&self, peer: IpAddr) -> bool {
+		let peer = canonical_addr(peer);
+		match self {
+			HostPattern::Any => true,
+			HostPattern::Addr(addr) => canonical_addr(*addr) == peer,
+			HostPattern::Subnet { addr, prefix_len } => {
+				subnet_contains(*addr, *prefix_len, peer)
+			},
+			HostPattern::Name(name) => {
+				let addrs = match (name.as_str(), 0).to_socket_addrs() {
+					Ok(addrs) => addrs,
+					Err(_) => return false,
+				};
+				addrs.into_iter().any(|addr| canonical_addr(addr.ip()) == peer)
+			},
+		}
+	}
+}
+
+fn parse_addr(addr: &str) -> Option<IpAddr> {
+	let addr = addr
+		.strip_prefix('[')
+		.and_then(|addr| addr.strip_suffix(']'))
+		.unwrap_or(addr);
+	addr.parse().ok()
+}
+
+fn canonical_addr(addr: IpAddr) -> IpAddr {
+	match addr {
+		IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+			Some(v4) => IpAddr::V4(v4),
+			None => addr,
+		},
+		IpAddr::V4(_) => addr,
+	}
+}
+
+fn subnet_contains(addr: IpAddr, prefix_len: u8, peer: IpAddr) -> bool {
+	match (canonical_addr(addr), peer) {
+		(IpAddr::V4(addr), IpAddr::V4(peer)) => {
+			let mask = u32::MAX.checked_shl(32 - u32::from(prefix_len));
+			let mask = mask.unwrap_or(0);
+			u32::from(addr) & mask == u32::from(peer) & mask
+		},
+		(IpAddr::V6(addr), IpAddr::V6(peer)) => {
+			let mask = u128::MAX.checked_shl(128 - u32::from(prefix_len));
+			let mask = mask.unwrap_or(0);
+			u128::from(addr) & mask == u128::from(peer) & mask
+		},
+		_ => false,
+	}
+}
+
+// }}}
+
+// SanedConf {{{
+
+/// The `saned.conf` file, which lists the hosts allowed to connect to a
+/// server and the ports it may use for data connections.
+///
+/// Each line is either an option of the form `name = value` or a host
+/// pattern. Lines starting with `#` are comments.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SanedConf {
+	hosts: Vec<HostPattern>,
+	data_port_range: Option<RangeInclusive<u16>>,
+}
+
+impl SanedConf {
+	pub fn new() -> SanedConf {
+		SanedConf {
+			hosts: Vec::new(),
+			data_port_range: None,
+		}
+	}
+
+	/// `/etc/sane.d/saned.conf`
+	pub fn default_path() -> &'static Path {
+		Path::new("/etc/sane.d/saned.conf")
+	}
+
+	/// Parses the contents of a `saned.conf` file. Invalid host patterns,
+	/// unknown options, and lines that aren't valid UTF-8 are ignored.
+	pub fn parse(content: &[u8]) -> SanedConf {
+		let mut conf = SanedConf::new();
+		for line in content.split(|&b| b == b'\n') {
+			let line = match core::str::from_utf8(line) {
+				Ok(line) => line.trim(),
+				Err(_) => continue,
+			};
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			if let Some((name, value)) = line.split_once('=') {
+				if name.trim() == "data_portrange" {
+					conf.data_port_range = parse_port_range(value.trim());
+				}
+				continue;
+			}
+			if let Some(host) = HostPattern::parse(line) {
+				conf.hosts.push(host);
+			}
+		}
+		conf
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> io::Result<SanedConf> {
+		let content = std::fs::read(path)?;
+		Ok(SanedConf::parse(&content))
+	}
+
+	pub fn hosts(&self) -> &[HostPattern] {
+		&self.hosts
+	}
+
+	pub fn push_host(&mut self, host: HostPattern) {
+		self.hosts.push(host);
+	}
+
+	/// The `data_portrange` option, or `None` if data connections may use
+	/// any port.
+	pub fn data_port_range(&self) -> Option<RangeInclusive<u16>> {
+		self.data_port_range.clone()
+	}
+
+	pub fn set_data_port_range(&mut self, range: Option<RangeInclusive<u16>>) {
+		self.data_port_range = range;
+	}
+
+	/// Returns whether `peer` may connect to the server.
+	///
+	/// Like saned, connections from loopback addresses are always allowed.
+	pub fn host_allowed(&self, peer: IpAddr) -> bool {
+		if canonical_addr(peer).is_loopback() {
+			return true;
+		}
+		self.hosts.iter().any(|host| host.matches(peer))
+	}
+}
+
+impl Default for SanedConf {
+	fn default() -> Self {
+		SanedConf::new()
+	}
+}
+
+fn parse_port_range(value: &str) -> Option<RangeInclusive<u16>> {
+	let (start, end) = value.split_once('-')?;
+	let start: u16 = start.trim().parse().ok()?;
+	let end: u16 = end.trim().parse().ok()?;
+	if start == 0 || start > end {
+		return None;
+	}
+	Some(start..=end)
+}
+
+// }}}
+
+// SanedUsers {{{
+
+/// A `user:password:backend` line of a [`SanedUsers`] file.
+#[derive(Clone, Eq, PartialEq)]
+pub struct SanedUser {
+	username: CString,
+	password: CString,
+	backend: CString,
+}
+
+impl fmt::Debug for SanedUser {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("SanedUser")
+			.field("username", &self.username)
+			.field("backend", &self.backend)
+			.finish_non_exhaustive()
+	}
+}
+
+impl SanedUser {
+	pub fn new(
+		username: impl Into<CString>,
+		password: impl Into<CString>,
+		backend: impl Into<CString>,
+	) -> SanedUser {
+		SanedUser {
+			username: username.into(),
+			password: password.into(),
+			backend: backend.into(),
+		}
+	}
+
+	pub fn username(&self) -> &CStr {
+		&self.username
+	}
+
+	pub fn password(&self) -> &CStr {
+		&self.password
+	}
+
+	pub fn backend(&self) -> &CStr {
+		&self.backend
+	}
+}
+
+/// The `saned.users` file, which lists the users allowed to access each
+/// backend.
+///
+/// Each line has the form `user:password:backend`. Backends that are not
+/// listed in the file can be accessed without authorization.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SanedUsers {
+	users: Vec<SanedUser>,
+}
+
+impl SanedUsers {
+	pub fn new() -> SanedUsers {
+		SanedUsers { users: Vec::new() }
+	}
+
+	/// `/etc/sane.d/saned.users`
+	pub fn default_path() -> &'static Path {
+		Path::new("/etc/sane.d/saned.users")
+	}
+
+	/// Parses the contents of a `saned.users` file. Lines that aren't of
+	/// the form `user:password:backend` are ignored.
+	pub fn parse(content: &[u8]) -> SanedUsers {
+		let users = content
+			.split(|&b| b == b'\n')
+			.filter_map(parse_user)
+			.collect();
+		SanedUsers { users }
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> io::Result<SanedUsers> {
+		let content = std::fs::read(path)?;
+		Ok(SanedUsers::parse(&content))
+	}
+
+	pub fn users(&self) -> &[SanedUser] {
+		&self.users
+	}
+
+	pub fn push(&mut self, user: SanedUser) {
+		self.users.push(user);
+	}
+
+	/// Returns whether any users are listed for `backend`.
+	pub fn requires_auth(&self, backend: &[u8]) -> bool {
+		self.users
+			.iter()
+			.any(|user| user.backend.as_bytes() == backend)
+	}
+
+	/// Returns the password of `username` for `backend`, or `None` if the
+	/// user may not access it.
+	pub fn password(&self, backend: &[u8], username: &CStr) -> Option<&CStr> {
+		self.users
+			.iter()
+			.find(|user| {
+				user.backend.as_bytes() == backend
+					&& user.username.as_c_str() == username
+			})
+			.map(|user| user.password.as_c_str())
+	}
+}
+
+impl Default for SanedUsers {
+	fn default() -> Self {
+		SanedUsers::new()
+	}
+}
+
+fn parse_user(line: &[u8]) -> Option<SanedUser> {
+	let line = line.strip_suffix(b"\r").unwrap_or(line);
+	if line.starts_with(b"#") {
+		return None;
+	}
+	let mut fields = line.splitn(3, |&b| b == b':');
+	let username = CString::new(fields.next()?).ok()?;
+	let password = CString::new(fields.next()?).ok()?;
+	let backend = CString::new(fields.next()?).ok()?;
+	Some(SanedUser {
+		username,
+		password,
+		backend,
+	})
+}
+
+// }}}
+
+// SanedPolicy {{{
+
+/// Access policy of a saned-compatible server, combining the host
+/// allow-list of `saned.conf` with the users of `saned.users`.
+///
+/// Authorization is required per backend: the resource for a device name
+/// such as `"pixma:04A91749"` is the backend name `"pixma"`. The
+/// [`auth_resource`](SanedPolicy::auth_resource) and
+/// [`password`](SanedPolicy::password) methods match the corresponding
+/// methods of [`net::Backend`](crate::net::Backend).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SanedPolicy {
+	conf: SanedConf,
+	users: SanedUsers,
+}
+
+impl SanedPolicy {
+	pub fn new(conf: SanedConf, users: SanedUsers) -> SanedPolicy {
+		SanedPolicy { conf, users }
+	}
+
+	/// Loads `saned.conf` and `saned.users` from their default paths. A
+	/// missing `saned.users` file is treated as empty.
+	pub fn load_default() -> io::Result<SanedPolicy> {
+		let conf = SanedConf::load(SanedConf::default_path())?;
+		let users = match SanedUsers::load(SanedUsers::default_path()) {
+			Ok(users) => users,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => {
+				SanedUsers::new()
+			},
+			Err(err) => return Err(err),
+		};
+		Ok(SanedPolicy::new(conf, users))
+	}
+
+	pub fn conf(&self) -> &SanedConf {
+		&self.conf
+	}
+
+	pub fn users(&self) -> &SanedUsers {
+		&self.users
+	}
+
+	/// Returns whether `peer` may connect to the server.
+	pub fn host_allowed(&self, peer: IpAddr) -> bool {
+		self.conf.host_allowed(peer)
+	}
+
+	/// The range of ports that data connections may listen on, as used by
+	/// [`Server::set_data_port_range`].
+	///
+	/// [`Server::set_data_port_range`]: crate::net::Server::set_data_port_range
+	pub fn data_port_range(&self) -> Option<RangeInclusive<u16>> {
+		self.conf.data_port_range()
+	}
+
+	/// Returns the resource that a client must be authorized for before
+	/// opening `device_name`, or `None` if no authorization is required.
+	pub fn auth_resource(&self, device_name: &CStr) -> Option<CString> {
+		let backend = device_backend(device_name);
+		if !self.users.requires_auth(backend) {
+			return None;
+		}
+		CString::new(backend).ok()
+	}
+
+	/// Returns the password that `username` must provide to access
+	/// `resource`, or `None` if the user may not access it.
+	pub fn password(
+		&self,
+		resource: &CStr,
+		username: &CStr,
+	) -> Option<CString> {
+		let password = self.users.password(resource.to_bytes(), username)?;
+		Some(password.to_owned())
+	}
+}
+
+fn device_backend(device_name: &CStr) -> &[u8] {
+	let name = device_name.to_bytes();
+	match name.iter().position(|&b| b == b':') {
+		Some(idx) => &name[..idx],
+		None => name,
+	}
+}
+
+// }}}
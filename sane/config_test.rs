@@ -0,0 +1,412 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::ffi::{CStr, CString};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use sane::config::{
+	DllConf,
+	HostPattern,
+	NetConf,
+	PassEntry,
+	PassFile,
+	SanedConf,
+	SanedPolicy,
+	SanedUser,
+	SanedUsers,
+};
+use sane::net;
+
+const fn cstr(bytes: &[u8]) -> &CStr {
+	unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!(
+		"sane_config_test.{}",
+		std::process::id(),
+	));
+	std::fs::create_dir_all(&dir).unwrap();
+	dir.join(name)
+}
+
+#[test]
+fn pass_file_parse() {
+	let pass_file = PassFile::parse(concat!(
+		"user:secret:test\n",
+		"malformed line\n",
+		"\n",
+		"user2:secret2:net:host:test\r\n",
+	).as_bytes());
+
+	assert_eq!(pass_file.entries(), &[
+		PassEntry::new(
+			cstr(b"user\x00"),
+			cstr(b"secret\x00"),
+			cstr(b"test\x00"),
+		),
+		PassEntry::new(
+			cstr(b"user2\x00"),
+			cstr(b"secret2\x00"),
+			cstr(b"net:host:test\x00"),
+		),
+	]);
+
+	// Passwords are not included in debug output.
+	assert_eq!(
+		format!("{:?}", pass_file.entries()[0]),
+		"PassEntry { username: \"user\", resource: \"test\", .. }",
+	);
+
+	let entry = pass_file.find(cstr(b"test$MD5$1234\x00")).unwrap();
+	assert_eq!(entry.username(), cstr(b"user\x00"));
+	let entry = pass_file.find(cstr(b"net:host:test\x00")).unwrap();
+	assert_eq!(entry.username(), cstr(b"user2\x00"));
+	assert!(pass_file.find(cstr(b"other\x00")).is_none());
+}
+
+#[test]
+fn pass_file_authorize_request() {
+	let pass_file = PassFile::parse(b"user:secret:test\n");
+
+	let request = pass_file.authorize_request(cstr(b"test$MD5$1234\x00"));
+	let request = request.unwrap();
+	assert_eq!(request.resource(), cstr(b"test$MD5$1234\x00"));
+	assert_eq!(request.username(), cstr(b"user\x00"));
+	assert_eq!(
+		request.password(),
+		cstr(b"$MD5$10b168cd4f742410888c3c110f7a7e71\x00"),
+	);
+
+	// Passwords are never sent in plaintext.
+	assert!(pass_file.authorize_request(cstr(b"test\x00")).is_none());
+	assert!(pass_file.authorize_request(cstr(b"other$MD5$1\x00")).is_none());
+
+	let mut provider = pass_file;
+	let resource = net::AuthResource::parse(cstr(b"test$MD5$1234\x00"));
+	let credentials = net::CredentialProvider::credentials(
+		&mut provider,
+		&resource,
+	);
+	assert_eq!(
+		credentials,
+		Some((CString::new("user").unwrap(), CString::new("secret").unwrap())),
+	);
+}
+
+#[test]
+fn pass_file_write() {
+	let mut pass_file = PassFile::new();
+	pass_file.push(PassEntry::new(
+		cstr(b"user\x00"),
+		cstr(b"secret\x00"),
+		cstr(b"net:host:test\x00"),
+	));
+	let mut content = Vec::new();
+	pass_file.write_to(&mut content).unwrap();
+	assert_eq!(content, b"user:secret:net:host:test\n");
+	assert_eq!(PassFile::parse(&content), pass_file);
+
+	pass_file.push(PassEntry::new(
+		cstr(b"user\x00"),
+		cstr(b"pass:word\x00"),
+		cstr(b"test\x00"),
+	));
+	let err = pass_file.write_to(&mut Vec::new()).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(unix)]
+#[test]
+fn pass_file_load() {
+	use std::os::unix::fs::PermissionsExt;
+
+	let path = temp_path("pass");
+	let mut pass_file = PassFile::new();
+	pass_file.push(PassEntry::new(
+		cstr(b"user\x00"),
+		cstr(b"secret\x00"),
+		cstr(b"test\x00"),
+	));
+	pass_file.save(&path).unwrap();
+	let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+	assert_eq!(mode & 0o777, 0o600);
+	assert_eq!(PassFile::load(&path).unwrap(), pass_file);
+
+	// Files readable by other users are refused.
+	let permissions = std::fs::Permissions::from_mode(0o644);
+	std::fs::set_permissions(&path, permissions).unwrap();
+	let err = PassFile::load(&path).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+
+	// Saving restricts the permissions again.
+	pass_file.save(&path).unwrap();
+	assert_eq!(PassFile::load(&path).unwrap(), pass_file);
+}
+
+fn ip(addr: &str) -> IpAddr {
+	addr.parse().unwrap()
+}
+
+#[test]
+fn host_pattern_parse() {
+	assert_eq!(HostPattern::parse("+"), Some(HostPattern::Any));
+	assert_eq!(
+		HostPattern::parse("192.168.0.1"),
+		Some(HostPattern::Addr(ip("192.168.0.1"))),
+	);
+	assert_eq!(
+		HostPattern::parse("[2001:db8::1]"),
+		Some(HostPattern::Addr(ip("2001:db8::1"))),
+	);
+	assert_eq!(
+		HostPattern::parse("2001:db8::1"),
+		Some(HostPattern::Addr(ip("2001:db8::1"))),
+	);
+	assert_eq!(
+		HostPattern::parse("192.168.0.0/24"),
+		Some(HostPattern::Subnet {
+			addr: ip("192.168.0.0"),
+			prefix_len: 24,
+		}),
+	);
+	assert_eq!(
+		HostPattern::parse("[2001:db8::]/32"),
+		Some(HostPattern::Subnet {
+			addr: ip("2001:db8::"),
+			prefix_len: 32,
+		}),
+	);
+	assert_eq!(
+		HostPattern::parse("scanner.example.com"),
+		Some(HostPattern::Name("scanner.example.com".into())),
+	);
+
+	assert_eq!(HostPattern::parse(""), None);
+	assert_eq!(HostPattern::parse("192.168.0.0/33"), None);
+	assert_eq!(HostPattern::parse("host/24"), None);
+	assert_eq!(HostPattern::parse("bad host"), None);
+}
+
+#[test]
+fn host_pattern_matches() {
+	assert!(HostPattern::Any.matches(ip("10.0.0.1")));
+
+	let addr = HostPattern::parse("10.0.0.1").unwrap();
+	assert!(addr.matches(ip("10.0.0.1")));
+	assert!(addr.matches(ip("::ffff:10.0.0.1")));
+	assert!(!addr.matches(ip("10.0.0.2")));
+
+	let subnet = HostPattern::parse("10.1.0.0/16").unwrap();
+	assert!(subnet.matches(ip("10.1.2.3")));
+	assert!(subnet.matches(ip("::ffff:10.1.2.3")));
+	assert!(!subnet.matches(ip("10.2.0.1")));
+	assert!(!subnet.matches(ip("2001:db8::1")));
+
+	let subnet = HostPattern::parse("0.0.0.0/0").unwrap();
+	assert!(subnet.matches(ip("192.0.2.1")));
+
+	let subnet = HostPattern::parse("[2001:db8::]/32").unwrap();
+	assert!(subnet.matches(ip("2001:db8:1::1")));
+	assert!(!subnet.matches(ip("2001:db9::1")));
+
+	let name = HostPattern::parse("localhost").unwrap();
+	assert!(name.matches(ip("127.0.0.1")));
+	assert!(!name.matches(ip("192.0.2.1")));
+}
+
+#[test]
+fn saned_conf_parse() {
+	let conf = SanedConf::parse(concat!(
+		"# comment\n",
+		"\n",
+		"data_portrange = 10000 - 10100\n",
+		"data_connect_timeout = 4000\n",
+		"192.168.0.0/24\r\n",
+		"  [::1]  \n",
+		"bad host\n",
+	).as_bytes());
+
+	assert_eq!(conf.hosts(), &[
+		HostPattern::Subnet {
+			addr: ip("192.168.0.0"),
+			prefix_len: 24,
+		},
+		HostPattern::Addr(ip("::1")),
+	]);
+	assert_eq!(conf.data_port_range(), Some(10000..=10100));
+
+	assert!(conf.host_allowed(ip("192.168.0.10")));
+	assert!(!conf.host_allowed(ip("192.168.1.10")));
+
+	// Loopback connections are always allowed.
+	assert!(conf.host_allowed(ip("127.0.0.1")));
+	assert!(SanedConf::new().host_allowed(ip("::1")));
+
+	let conf = SanedConf::parse(b"data_portrange = 10100 - 10000\n");
+	assert_eq!(conf.data_port_range(), None);
+}
+
+#[test]
+fn saned_users_parse() {
+	let users = SanedUsers::parse(concat!(
+		"user:secret:pixma\n",
+		"# comment\n",
+		"malformed line\n",
+		"user2:secret2:test\r\n",
+	).as_bytes());
+
+	assert_eq!(users.users(), &[
+		SanedUser::new(
+			cstr(b"user\x00"),
+			cstr(b"secret\x00"),
+			cstr(b"pixma\x00"),
+		),
+		SanedUser::new(
+			cstr(b"user2\x00"),
+			cstr(b"secret2\x00"),
+			cstr(b"test\x00"),
+		),
+	]);
+
+	assert!(users.requires_auth(b"pixma"));
+	assert!(!users.requires_auth(b"other"));
+	assert_eq!(
+		users.password(b"pixma", cstr(b"user\x00")),
+		Some(cstr(b"secret\x00")),
+	);
+	assert_eq!(users.password(b"pixma", cstr(b"user2\x00")), None);
+}
+
+#[test]
+fn saned_policy() {
+	let conf = SanedConf::parse(b"10.0.0.0/8\ndata_portrange = 10000-10100\n");
+	let users = SanedUsers::parse(b"user:secret:pixma\n");
+	let policy = SanedPolicy::new(conf, users);
+
+	assert!(policy.host_allowed(ip("10.1.2.3")));
+	assert!(!policy.host_allowed(ip("192.0.2.1")));
+	assert_eq!(policy.data_port_range(), Some(10000..=10100));
+
+	assert_eq!(
+		policy.auth_resource(cstr(b"pixma:04A91749\x00")),
+		Some(CString::new("pixma").unwrap()),
+	);
+	assert_eq!(
+		policy.auth_resource(cstr(b"pixma\x00")),
+		Some(CString::new("pixma").unwrap()),
+	);
+	assert_eq!(policy.auth_resource(cstr(b"test:0\x00")), None);
+
+	assert_eq!(
+		policy.password(cstr(b"pixma\x00"), cstr(b"user\x00")),
+		Some(CString::new("secret").unwrap()),
+	);
+	assert_eq!(policy.password(cstr(b"pixma\x00"), cstr(b"other\x00")), None);
+	assert_eq!(policy.password(cstr(b"test\x00"), cstr(b"user\x00")), None);
+}
+
+#[test]
+fn net_conf_parse() {
+	let conf = NetConf::parse(concat!(
+		"# comment\n",
+		"connect_timeout = 30\n",
+		"scanner\n",
+		"scanner:1234\r\n",
+		"[::1]\n",
+		"bad host:port\n",
+	).as_bytes());
+
+	assert_eq!(conf.hosts(), &[
+		net::RemoteHost::new("scanner", None),
+		net::RemoteHost::new("scanner", Some(1234)),
+		net::RemoteHost::new("::1", None),
+	]);
+	assert_eq!(conf.connect_timeout(), Some(Duration::from_secs(30)));
+
+	let aggregator = conf.device_aggregator();
+	assert_eq!(aggregator.hosts().count(), 3);
+
+	let conf = NetConf::parse(b"connect_timeout = 0\n");
+	assert_eq!(conf.connect_timeout(), None);
+}
+
+#[test]
+fn dll_conf_parse() {
+	let conf = DllConf::parse(concat!(
+		"# comment\n",
+		"pixma\n",
+		"  net  \r\n",
+		"\n",
+		"pixma\n",
+		"epson2 # inline comment\n",
+		"\tgenesys\tunused\n",
+		"hp#comment\n",
+		"  # indented comment\n",
+	).as_bytes());
+	assert_eq!(conf.backends(), ["pixma", "net", "epson2", "genesys", "hp"]);
+}
+
+#[test]
+fn dll_conf_load_dirs() {
+	let dir_1 = temp_path("dll_1");
+	let dir_2 = temp_path("dll_2");
+	let dir_3 = temp_path("dll_3");
+	for dir in [&dir_1, &dir_2, &dir_3] {
+		let _ = std::fs::remove_dir_all(dir);
+		std::fs::create_dir_all(dir).unwrap();
+	}
+
+	std::fs::write(dir_2.join("dll.conf"), b"pixma\nnet\n").unwrap();
+	std::fs::write(dir_3.join("dll.conf"), b"ignored\n").unwrap();
+	let dll_d = dir_3.join("dll.d");
+	std::fs::create_dir(&dll_d).unwrap();
+	std::fs::write(dll_d.join("b"), b"epson2\n").unwrap();
+	std::fs::write(dll_d.join("a"), b"hp\nnet\n").unwrap();
+	std::fs::write(dll_d.join("a~"), b"backup\n").unwrap();
+	std::fs::write(dll_d.join(".hidden"), b"hidden\n").unwrap();
+
+	// The first `dll.conf` and the first `dll.d` are used.
+	let conf = DllConf::load_dirs(&[&dir_1, &dir_2, &dir_3]).unwrap();
+	assert_eq!(conf.backends(), ["pixma", "net", "hp", "epson2"]);
+
+	let conf = DllConf::load(&dir_1).unwrap();
+	assert_eq!(conf.backends(), &[] as &[String]);
+}
+
+#[test]
+fn search_dirs() {
+	let default_dirs = [PathBuf::from("."), PathBuf::from("/etc/sane.d")];
+
+	std::env::remove_var("SANE_CONFIG_DIR");
+	assert_eq!(sane::config::search_dirs(), default_dirs);
+
+	std::env::set_var("SANE_CONFIG_DIR", "/opt/app/sane.d");
+	assert_eq!(sane::config::search_dirs(), [
+		PathBuf::from("/opt/app/sane.d"),
+	]);
+
+	std::env::set_var("SANE_CONFIG_DIR", "/opt/app/sane.d:/etc/app:");
+	assert_eq!(sane::config::search_dirs(), [
+		PathBuf::from("/opt/app/sane.d"),
+		PathBuf::from("/etc/app"),
+		PathBuf::from("."),
+		PathBuf::from("/etc/sane.d"),
+	]);
+
+	std::env::remove_var("SANE_CONFIG_DIR");
+}
@@ -0,0 +1,428 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::{c_char, c_int, c_void, CStr};
+use core::fmt;
+use core::mem;
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::config::DllConf;
+use crate::frontend::{Device, Sane};
+use crate::util;
+use crate::{AuthCallback, Status, Vtable};
+
+// `dll` tries the library with the major version of the SANE API first.
+const BACKEND_LIBRARY_SUFFIXES: [&str; 2] = [".so.1", ".so"];
+
+const RTLD_NOW: c_int = 2;
+
+#[cfg(target_os = "macos")]
+const RTLD_LOCAL: c_int = 4;
+
+#[cfg(not(target_os = "macos"))]
+const RTLD_LOCAL: c_int = 0;
+
+#[cfg_attr(target_os = "linux", link(name = "dl"))]
+extern "C" {
+	fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+	fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+	fn dlclose(handle: *mut c_void) -> c_int;
+	fn dlerror() -> *mut c_char;
+}
+
+// Library {{{
+
+/// A SANE backend loaded from a shared library.
+///
+/// If the backend has been initialized, `sane_exit()` is called when the
+/// library is dropped.
+pub struct Library {
+	handle: *mut c_void,
+	vtable: Vtable,
+	initialized: bool,
+}
+
+impl fmt::Debug for Library {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Library")
+			.field("handle", &self.handle)
+			.field("initialized", &self.initialized)
+			.finish()
+	}
+}
+
+impl Library {
+	/// Loads the backend library at `path`.
+	///
+	/// If the library's file name has the form `libsane-<backend>.so`, the
+	/// versioned `sane_<backend>_*` symbols are used when present, with any
+	/// `-` in the backend name replaced by `_`. Otherwise the plain `sane_*`
+	/// symbols are used.
+	///
+	/// # Safety
+	///
+	/// Loading a library runs its initialization routines, and the symbols
+	/// it exports must have the signatures defined by the SANE API.
+	pub unsafe fn open(
+		path: impl AsRef<Path>,
+	) -> Result<Library, LibraryError> {
+		let path = path.as_ref();
+		let path_cstr = match CString::new(path.as_os_str().as_bytes()) {
+			Ok(path_cstr) => path_cstr,
+			Err(_) => {
+				return Err(LibraryError::new(format!(
+					"invalid library path {:?}",
+					path,
+				)));
+			},
+		};
+
+		let handle = dlopen(path_cstr.as_ptr(), RTLD_NOW | RTLD_LOCAL);
+		if handle.is_null() {
+			return Err(LibraryError::from_dlerror());
+		}
+
+		// Symbol names can't contain `-`, so libsane's dll backend maps it to
+		// `_` when looking up the versioned symbols.
+		let backend = path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.and_then(|name| name.strip_prefix("libsane-"))
+			.and_then(|name| name.split('.').next())
+			.map(|name| name.replace('-', "_"));
+
+		match load_vtable(handle, backend.as_deref()) {
+			Ok(vtable) => Ok(Library {
+				handle,
+				vtable,
+				initialized: false,
+			}),
+			Err(err) => {
+				dlclose(handle);
+				Err(err)
+			},
+		}
+	}
+
+	pub fn vtable(&self) -> &Vtable {
+		&self.vtable
+	}
+
+	/// `sane_init()`
+	///
+	/// If the backend is already initialized, it will be exited first.
+	pub fn init(
+		&mut self,
+		authorize: Option<AuthCallback>,
+	) -> Result<u32, Status> {
+		self.exit();
+		let mut version_code = mem::MaybeUninit::uninit();
+		let status =
+			unsafe { (self.vtable.init)(&mut version_code, authorize) };
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		self.initialized = true;
+		let version_code = unsafe { version_code.assume_init() };
+		Ok(version_code.as_word().as_u32())
+	}
+
+	/// `sane_exit()`
+	///
+	/// Does nothing if the backend has not been initialized.
+	pub fn exit(&mut self) {
+		if self.initialized {
+			self.initialized = false;
+			unsafe { (self.vtable.exit)() };
+		}
+	}
+}
+
+impl Drop for Library {
+	fn drop(&mut self) {
+		self.exit();
+		unsafe { dlclose(self.handle) };
+	}
+}
+
+unsafe fn load_vtable(
+	handle: *mut c_void,
+	backend: Option<&str>,
+) -> Result<Vtable, LibraryError> {
+	Ok(Vtable {
+		init: load_symbol(handle, backend, "init")?,
+		exit: load_symbol(handle, backend, "exit")?,
+		get_devices: load_symbol(handle, backend, "get_devices")?,
+		open: load_symbol(handle, backend, "open")?,
+		close: load_symbol(handle, backend, "close")?,
+		get_option_descriptor: load_symbol(
+			handle,
+			backend,
+			"get_option_descriptor",
+		)?,
+		control_option: load_symbol(handle, backend, "control_option")?,
+		get_parameters: load_symbol(handle, backend, "get_parameters")?,
+		start: load_symbol(handle, backend, "start")?,
+		read: load_symbol(handle, backend, "read")?,
+		cancel: load_symbol(handle, backend, "cancel")?,
+		set_io_mode: load_symbol(handle, backend, "set_io_mode")?,
+		get_select_fd: load_symbol(handle, backend, "get_select_fd")?,
+	})
+}
+
+unsafe fn load_symbol<T: Copy>(
+	handle: *mut c_void,
+	backend: Option<&str>,
+	name: &str,
+) -> Result<T, LibraryError> {
+	assert_eq!(mem::size_of::<T>(), mem::size_of::<*mut c_void>());
+
+	let mut ptr = core::ptr::null_mut();
+	if let Some(backend) = backend {
+		let symbol = symbol_name(&format!("sane_{}_{}", backend, name))?;
+		ptr = dlsym(handle, symbol.as_ptr());
+	}
+	if ptr.is_null() {
+		let symbol = symbol_name(&format!("sane_{}", name))?;
+		ptr = dlsym(handle, symbol.as_ptr());
+	}
+	if ptr.is_null() {
+		return Err(LibraryError::from_dlerror());
+	}
+	Ok(mem::transmute_copy(&ptr))
+}
+
+fn symbol_name(name: &str) -> Result<CString, LibraryError> {
+	CString::new(name).map_err(|_| {
+		LibraryError::new(format!("invalid symbol name {:?}", name))
+	})
+}
+
+// }}}
+
+// Dll {{{
+
+/// SANE backend libraries loaded like the `dll` meta-backend.
+///
+/// Once initialized, the devices of each backend are listed as
+/// `backend:device`, and opening a device by that name opens `device` with
+/// the backend's library.
+pub struct Dll {
+	backends: Vec<(String, Library)>,
+}
+
+impl fmt::Debug for Dll {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Dll")
+			.field("backends", &self.backends().collect::<Vec<_>>())
+			.finish()
+	}
+}
+
+impl Dll {
+	pub fn new() -> Dll {
+		Dll {
+			backends: Vec::new(),
+		}
+	}
+
+	/// Loads the backends listed in `conf` from `lib_dirs`. Like libsane,
+	/// backends that can't be loaded are skipped.
+	///
+	/// # Safety
+	///
+	/// Same as [`Library::open`].
+	pub unsafe fn load(conf: &DllConf, lib_dirs: &[impl AsRef<Path>]) -> Dll {
+		let mut dll = Dll::new();
+		for backend in conf.backends() {
+			let _ = dll.load_backend(backend, lib_dirs);
+		}
+		dll
+	}
+
+	/// Loads `libsane-<backend>.so.1` or `libsane-<backend>.so` from the
+	/// first directory of `lib_dirs` that contains it.
+	///
+	/// # Safety
+	///
+	/// Same as [`Library::open`].
+	pub unsafe fn load_backend(
+		&mut self,
+		backend: &str,
+		lib_dirs: &[impl AsRef<Path>],
+	) -> Result<(), LibraryError> {
+		if backend.is_empty() || backend.contains(['/', ':']) {
+			return Err(LibraryError::new(format!(
+				"invalid backend name {:?}",
+				backend,
+			)));
+		}
+		for dir in lib_dirs {
+			for suffix in BACKEND_LIBRARY_SUFFIXES {
+				let file_name = format!("libsane-{}{}", backend, suffix);
+				let path = dir.as_ref().join(file_name);
+				if path.exists() {
+					self.push(backend, Library::open(&path)?);
+					return Ok(());
+				}
+			}
+		}
+		Err(LibraryError::new(format!(
+			"library for backend {:?} not found",
+			backend,
+		)))
+	}
+
+	/// Adds `library` as the backend named `backend`.
+	pub fn push(&mut self, backend: impl Into<String>, library: Library) {
+		self.backends.push((backend.into(), library));
+	}
+
+	pub fn backends(&self) -> impl Iterator<Item = &str> {
+		self.backends.iter().map(|(name, _)| name.as_str())
+	}
+
+	/// `sane_init()` for each backend.
+	///
+	/// Backends that fail to initialize are skipped. Each backend is exited
+	/// when the returned [`DllSession`] is dropped.
+	pub fn init(&mut self, authorize: Option<AuthCallback>) -> DllSession<'_> {
+		for (_, library) in self.backends.iter_mut() {
+			library.exit();
+		}
+		let dll: &Dll = self;
+		let mut backends = Vec::new();
+		for (name, library) in dll.backends.iter() {
+			// The libraries can't be initialized again while the session
+			// borrows them, and they were loaded by `Library::open()`.
+			let sane = unsafe { Sane::init(library.vtable(), authorize) };
+			if let Ok(sane) = sane {
+				backends.push((name.as_str(), sane));
+			}
+		}
+		DllSession { backends }
+	}
+}
+
+impl Default for Dll {
+	fn default() -> Self {
+		Dll::new()
+	}
+}
+
+/// Initialized backends of a [`Dll`].
+pub struct DllSession<'a> {
+	backends: Vec<(&'a str, Sane<'a>)>,
+}
+
+impl fmt::Debug for DllSession<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("DllSession")
+			.field("backends", &self.backends().collect::<Vec<_>>())
+			.finish()
+	}
+}
+
+impl<'a> DllSession<'a> {
+	/// The backends that were initialized.
+	pub fn backends(&self) -> impl Iterator<Item = &str> {
+		self.backends.iter().map(|(name, _)| *name)
+	}
+
+	/// `sane_get_devices()`
+	///
+	/// Returns the devices of all backends, with each device name prefixed
+	/// by the name of its backend. Backends that fail to list their
+	/// devices are skipped.
+	pub fn get_devices(&self, local_only: bool) -> Vec<util::DeviceBuf> {
+		let mut devices = Vec::new();
+		for (backend, sane) in self.backends.iter() {
+			let backend_devices = match sane.get_devices(local_only) {
+				Ok(backend_devices) => backend_devices,
+				Err(_) => continue,
+			};
+			for mut device in backend_devices {
+				let name = [backend.as_bytes(), b":", device.name().to_bytes()];
+				if let Ok(name) = CString::new(name.concat()) {
+					device.set_name(name);
+					devices.push(device);
+				}
+			}
+		}
+		devices
+	}
+
+	/// `sane_open()`
+	///
+	/// The backend is selected by the part of `device_name` before the
+	/// first `:`. A name without a `:` opens the default device of that
+	/// backend, and an empty name opens the default device of the first
+	/// backend.
+	pub fn open(&self, device_name: &CStr) -> Result<Device<'_>, Status> {
+		let name = device_name.to_bytes_with_nul();
+		let (backend, device_name) = match name.iter().position(|&b| b == b':')
+		{
+			Some(idx) => (&name[..idx], &name[idx + 1..]),
+			None => (&name[..name.len() - 1], &name[name.len() - 1..]),
+		};
+		let sane = if backend.is_empty() {
+			self.backends.first().map(|(_, sane)| sane)
+		} else {
+			self.backends
+				.iter()
+				.find(|(name, _)| name.as_bytes() == backend)
+				.map(|(_, sane)| sane)
+		};
+		let device_name = CStr::from_bytes_with_nul(device_name).unwrap();
+		sane.ok_or(Status::INVAL)?.open(device_name)
+	}
+}
+
+// }}}
+
+// LibraryError {{{
+
+#[derive(Debug)]
+pub struct LibraryError {
+	message: String,
+}
+
+impl LibraryError {
+	fn new(message: String) -> LibraryError {
+		LibraryError { message }
+	}
+
+	unsafe fn from_dlerror() -> LibraryError {
+		let message = dlerror();
+		if message.is_null() {
+			return LibraryError::new(String::from("unknown dlopen error"));
+		}
+		let message = CStr::from_ptr(message).to_string_lossy();
+		LibraryError::new(message.into_owned())
+	}
+}
+
+impl fmt::Display for LibraryError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.message)
+	}
+}
+
+impl std::error::Error for LibraryError {}
+
+// }}}
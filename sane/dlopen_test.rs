@@ -0,0 +1,220 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+
+use sane::config::DllConf;
+use sane::dlopen::{Dll, Library};
+use sane::frontend::Sane;
+
+// The test backend is a dev-dependency, so it will have been built into the
+// same directory as the test binary.
+fn test_backend_path() -> PathBuf {
+	if let Some(path) = std::env::var_os("SANE_TEST_BACKEND") {
+		return PathBuf::from(path);
+	}
+	let exe = std::env::current_exe().unwrap();
+	let dir = exe.parent().unwrap();
+	let file_name = format!(
+		"{}sane_test_backend{}",
+		std::env::consts::DLL_PREFIX,
+		std::env::consts::DLL_SUFFIX,
+	);
+	dir.join(file_name)
+}
+
+// A copy of the test backend in a temporary directory, which is removed when
+// this is dropped.
+struct InstalledBackend {
+	dir: PathBuf,
+	path: PathBuf,
+}
+
+impl InstalledBackend {
+	fn path(&self) -> &Path {
+		&self.path
+	}
+}
+
+impl Drop for InstalledBackend {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_dir_all(&self.dir);
+	}
+}
+
+// Copy the test backend to a file named like an installed SANE backend, so
+// that the versioned symbols are found.
+fn install_test_backend(name: &str) -> InstalledBackend {
+	install_test_backend_as(name, "test")
+}
+
+fn install_test_backend_as(name: &str, backend: &str) -> InstalledBackend {
+	let dir = std::env::temp_dir().join(format!(
+		"sane_dlopen_test.{}.{}",
+		std::process::id(),
+		name,
+	));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join(format!("libsane-{}.so", backend));
+	std::fs::copy(test_backend_path(), &path).unwrap();
+	InstalledBackend { dir, path }
+}
+
+fn get_devices_status(library: &Library) -> sane::Status {
+	let mut devices = MaybeUninit::uninit();
+	unsafe { (library.vtable().get_devices)(&mut devices, sane::Bool::FALSE) }
+}
+
+#[test]
+fn library_open() {
+	let backend = install_test_backend("open");
+	let path = backend.path();
+	let mut library = unsafe { Library::open(path) }.unwrap();
+	assert_eq!(get_devices_status(&library), sane::Status::INVAL);
+
+	let version_code = library.init(None).unwrap();
+	assert_eq!(version_code, sane::version_code(1, 0, 1));
+	assert_eq!(get_devices_status(&library), sane::Status::GOOD);
+
+	library.exit();
+	assert_eq!(get_devices_status(&library), sane::Status::INVAL);
+}
+
+#[test]
+fn library_drop_calls_exit() {
+	let backend = install_test_backend("drop");
+	let path = backend.path();
+
+	// Both libraries share a handle, and therefore the backend's state.
+	let mut library_1 = unsafe { Library::open(path) }.unwrap();
+	let library_2 = unsafe { Library::open(path) }.unwrap();
+
+	library_1.init(None).unwrap();
+	assert_eq!(get_devices_status(&library_2), sane::Status::GOOD);
+
+	drop(library_1);
+	assert_eq!(get_devices_status(&library_2), sane::Status::INVAL);
+}
+
+#[test]
+fn library_backend_name_with_dash() {
+	// The symbols of `libsane-test-x.so` are named `sane_test_x_*`.
+	let backend = install_test_backend_as("dash", "test-x");
+	let mut library = unsafe { Library::open(backend.path()) }.unwrap();
+	library.init(None).unwrap();
+	assert_eq!(get_devices_status(&library), sane::Status::GOOD);
+}
+
+#[test]
+fn library_unversioned_symbols() {
+	// Without a `libsane-<backend>` file name, only the plain `sane_*`
+	// symbols are searched for.
+	let err = unsafe { Library::open(test_backend_path()) }.unwrap_err();
+	assert!(err.to_string().contains("sane_init"));
+}
+
+#[test]
+fn library_not_found() {
+	let path = std::env::temp_dir().join("libsane-nonexistent.so");
+	let err = unsafe { Library::open(path) }.unwrap_err();
+	assert!(err.to_string().contains("libsane-nonexistent.so"));
+}
+
+#[test]
+fn library_exported_backend() {
+	use std::io::Read;
+
+	const CSTR_DEV_NAME: &std::ffi::CStr = unsafe {
+		std::ffi::CStr::from_bytes_with_nul_unchecked(b"test-device\x00")
+	};
+
+	let backend = install_test_backend("exported");
+	let path = backend.path();
+	let library = unsafe { Library::open(path) }.unwrap();
+	let sane = unsafe { Sane::init(library.vtable(), None) }.unwrap();
+	assert_eq!(sane.version_code(), sane::version_code(1, 0, 1));
+
+	let devices = sane.get_devices(false).unwrap();
+	assert_eq!(devices.len(), 1);
+	assert_eq!(devices[0].name(), CSTR_DEV_NAME);
+
+	let mut device = sane.open(CSTR_DEV_NAME).unwrap();
+	assert_eq!(device.option_count(), Ok(2));
+	let descriptor = device.option_descriptor(1).unwrap();
+	assert_eq!(descriptor.name().to_bytes(), b"resolution");
+
+	let mut value = 1200i32.to_ne_bytes();
+	assert_eq!(device.set_option(1, &mut value), Ok(sane::INFO_INEXACT));
+	assert_eq!(i32::from_ne_bytes(value), 600);
+
+	let mut scan = device.start().unwrap();
+	let mut image = Vec::new();
+	scan.read_to_end(&mut image).unwrap();
+	assert_eq!(image, b"image data");
+	assert_eq!(scan.read_data(&mut [0u8; 4]), Ok(0));
+}
+
+#[test]
+fn dll_session() {
+	const CSTR_DEV_NAME: &std::ffi::CStr = unsafe {
+		std::ffi::CStr::from_bytes_with_nul_unchecked(b"test:test-device\x00")
+	};
+	const CSTR_OTHER_NAME: &std::ffi::CStr = unsafe {
+		std::ffi::CStr::from_bytes_with_nul_unchecked(b"other:test-device\x00")
+	};
+
+	let backend = install_test_backend("dll");
+	let path = backend.path();
+	let lib_dirs = [path.parent().unwrap()];
+
+	// Backends that can't be loaded are skipped.
+	let conf = DllConf::parse(b"missing\ntest\n");
+	let mut dll = unsafe { Dll::load(&conf, &lib_dirs) };
+	assert_eq!(dll.backends().collect::<Vec<_>>(), ["test"]);
+
+	let session = dll.init(None);
+	assert_eq!(session.backends().collect::<Vec<_>>(), ["test"]);
+
+	let devices = session.get_devices(false);
+	assert_eq!(devices.len(), 1);
+	assert_eq!(devices[0].name(), CSTR_DEV_NAME);
+
+	let device = session.open(CSTR_DEV_NAME).unwrap();
+	assert_eq!(device.option_count(), Ok(2));
+	drop(device);
+
+	let err = session.open(CSTR_OTHER_NAME).unwrap_err();
+	assert_eq!(err, sane::Status::INVAL);
+	drop(session);
+
+	// Backends are exited when the session is dropped.
+	let library = unsafe { Library::open(path) }.unwrap();
+	assert_eq!(get_devices_status(&library), sane::Status::INVAL);
+}
+
+#[test]
+fn dll_load_backend_not_found() {
+	let backend = install_test_backend("dll_not_found");
+	let path = backend.path();
+	let lib_dirs = [path.parent().unwrap()];
+
+	let mut dll = Dll::new();
+	let err = unsafe { dll.load_backend("missing", &lib_dirs) }.unwrap_err();
+	assert!(err.to_string().contains("missing"));
+	let err = unsafe { dll.load_backend("../test", &lib_dirs) }.unwrap_err();
+	assert!(err.to_string().contains("invalid backend name"));
+	assert_eq!(dll.backends().count(), 0);
+}
@@ -0,0 +1,693 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use core::ffi::CStr;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+
+#[cfg(all(any(doc, feature = "std"), unix))]
+use std::os::fd::BorrowedFd;
+#[cfg(any(doc, feature = "std"))]
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::util;
+use crate::{
+	Action,
+	AuthCallback,
+	Bool,
+	Handle,
+	Int,
+	Parameters,
+	Status,
+	StringConst,
+	Vtable,
+};
+
+// Sane {{{
+
+/// An initialized SANE backend.
+///
+/// `sane_exit()` is called when the `Sane` is dropped. Devices opened from
+/// it borrow the `Sane`, so they must be closed first.
+pub struct Sane<'a> {
+	vtable: &'a Vtable,
+	version_code: u32,
+	#[cfg(any(doc, feature = "std"))]
+	_auth_handler: Option<AuthHandler>,
+	// SANE backends are not required to be thread-safe.
+	_not_sync: PhantomData<*mut ()>,
+}
+
+impl fmt::Debug for Sane<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Sane")
+			.field("version_code", &self.version_code)
+			.finish()
+	}
+}
+
+impl<'a> Sane<'a> {
+	/// `sane_init()`
+	///
+	/// # Safety
+	///
+	/// The functions in `vtable` must implement the SANE API, and the
+	/// backend must not be initialized again until this `Sane` has been
+	/// dropped.
+	pub unsafe fn init(
+		vtable: &'a Vtable,
+		authorize: Option<AuthCallback>,
+	) -> Result<Sane<'a>, Status> {
+		let mut version_code = MaybeUninit::uninit();
+		let status = (vtable.init)(&mut version_code, authorize);
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		let version_code = version_code.assume_init();
+		Ok(Sane {
+			vtable,
+			version_code: version_code.as_word().as_u32(),
+			#[cfg(any(doc, feature = "std"))]
+			_auth_handler: None,
+			_not_sync: PhantomData,
+		})
+	}
+
+	/// `sane_init()`, with `authorize` registered as the auth callback.
+	///
+	/// The callback is unregistered when the `Sane` is dropped.
+	///
+	/// # Safety
+	///
+	/// Same as [`Sane::init`].
+	#[cfg(any(doc, feature = "std"))]
+	pub unsafe fn init_with_authorize(
+		vtable: &'a Vtable,
+		authorize: impl Authorize + 'static,
+	) -> Result<Sane<'a>, Status> {
+		let auth_handler = AuthHandler::new(authorize);
+		let mut sane = Sane::init(vtable, Some(auth_handler.callback()))?;
+		sane._auth_handler = Some(auth_handler);
+		Ok(sane)
+	}
+
+	/// The version code reported by `sane_init()`.
+	pub fn version_code(&self) -> u32 {
+		self.version_code
+	}
+
+	/// `sane_get_devices()`
+	///
+	/// The device list is only valid until the next call, so it is copied.
+	pub fn get_devices(
+		&self,
+		local_only: bool,
+	) -> Result<Vec<util::DeviceBuf>, Status> {
+		let mut device_list = MaybeUninit::uninit();
+		let status = unsafe {
+			(self.vtable.get_devices)(&mut device_list, Bool::new(local_only))
+		};
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		let device_list = unsafe { device_list.assume_init() };
+		if device_list.is_null() {
+			return Ok(Vec::new());
+		}
+		let devices = unsafe { util::DevicesRef::from_ptr(device_list) };
+		Ok(devices.iter().map(util::DeviceBuf::from).collect())
+	}
+
+	/// `sane_open()`
+	pub fn open(&self, device_name: &CStr) -> Result<Device<'_>, Status> {
+		let mut handle = MaybeUninit::uninit();
+		let status = unsafe {
+			let device_name = StringConst::from_c_str(device_name);
+			(self.vtable.open)(device_name, &mut handle)
+		};
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		Ok(Device {
+			sane: self,
+			handle: unsafe { handle.assume_init() },
+		})
+	}
+}
+
+impl Drop for Sane<'_> {
+	fn drop(&mut self) {
+		unsafe { (self.vtable.exit)() };
+	}
+}
+
+// }}}
+
+// Device {{{
+
+/// A device opened with `sane_open()`.
+///
+/// `sane_close()` is called when the `Device` is dropped.
+pub struct Device<'a> {
+	sane: &'a Sane<'a>,
+	handle: Handle,
+}
+
+impl fmt::Debug for Device<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Device")
+			.field("handle", &self.handle)
+			.finish()
+	}
+}
+
+impl<'a> Device<'a> {
+	pub fn handle(&self) -> Handle {
+		self.handle
+	}
+
+	/// The number of options, which is the value of option 0.
+	pub fn option_count(&self) -> Result<u32, Status> {
+		let mut value = [0u8; mem::size_of::<Int>()];
+		self.get_option(0, &mut value)?;
+		Ok(i32::from_ne_bytes(value) as u32)
+	}
+
+	/// `sane_get_option_descriptor()`
+	pub fn option_descriptor(
+		&self,
+		option: u32,
+	) -> Option<util::OptionDescriptorRef<'_>> {
+		let vtable = self.sane.vtable;
+		unsafe {
+			let ptr = (vtable.get_option_descriptor)(
+				self.handle,
+				Int::new(option as i32),
+			);
+			if ptr.is_null() {
+				return None;
+			}
+			Some(util::OptionDescriptorRef::from_ptr(ptr))
+		}
+	}
+
+	/// `sane_control_option(SANE_ACTION_GET_VALUE)`
+	///
+	/// The value is written to the start of `value` in the backend's native
+	/// representation. Returns `Status::INVAL` if the option doesn't exist
+	/// or `value` is shorter than the option's size.
+	pub fn get_option(
+		&self,
+		option: u32,
+		value: &mut [u8],
+	) -> Result<(), Status> {
+		let mut buf = self.value_buf(option)?;
+		if value.len() < buf.size {
+			return Err(Status::INVAL);
+		}
+		self.control_option(option, Action::GET_VALUE, &mut buf)?;
+		value[..buf.size].copy_from_slice(buf.as_bytes());
+		Ok(())
+	}
+
+	/// `sane_control_option(SANE_ACTION_SET_VALUE)`
+	///
+	/// If `value` is shorter than the option's size then the remainder is
+	/// filled with zeros. The backend may adjust the value, in which case
+	/// `value` is updated to match.
+	///
+	/// Returns the `SANE_INFO_*` flags set by the backend.
+	pub fn set_option(
+		&mut self,
+		option: u32,
+		value: &mut [u8],
+	) -> Result<u32, Status> {
+		let mut buf = self.value_buf(option)?;
+		if value.len() > buf.size {
+			return Err(Status::INVAL);
+		}
+		buf.as_bytes_mut()[..value.len()].copy_from_slice(value);
+		let info = self.control_option(option, Action::SET_VALUE, &mut buf)?;
+		value.copy_from_slice(&buf.as_bytes()[..value.len()]);
+		Ok(info)
+	}
+
+	/// `sane_control_option(SANE_ACTION_SET_AUTO)`
+	///
+	/// Returns the `SANE_INFO_*` flags set by the backend.
+	pub fn set_option_auto(&mut self, option: u32) -> Result<u32, Status> {
+		let mut buf = self.value_buf(option)?;
+		self.control_option(option, Action::SET_AUTO, &mut buf)
+	}
+
+	/// `sane_get_parameters()`
+	pub fn get_parameters(&self) -> Result<Parameters, Status> {
+		get_parameters(self.sane.vtable, self.handle)
+	}
+
+	/// `sane_start()`
+	///
+	/// The device can't be modified until the returned `Scan` is dropped.
+	pub fn start(&mut self) -> Result<Scan<'_>, Status> {
+		let status = unsafe { (self.sane.vtable.start)(self.handle) };
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		Ok(Scan {
+			device: self,
+			non_blocking: false,
+		})
+	}
+
+	fn value_buf(&self, option: u32) -> Result<ValueBuf, Status> {
+		match self.option_descriptor(option) {
+			Some(descriptor) => Ok(ValueBuf::new(descriptor.size())),
+			None => Err(Status::INVAL),
+		}
+	}
+
+	fn control_option(
+		&self,
+		option: u32,
+		action: Action,
+		value: &mut ValueBuf,
+	) -> Result<u32, Status> {
+		let mut info = MaybeUninit::new(Int::new(0));
+		let status = unsafe {
+			(self.sane.vtable.control_option)(
+				self.handle,
+				Int::new(option as i32),
+				action,
+				value.words.as_mut_ptr().cast(),
+				&mut info,
+			)
+		};
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		let info = unsafe { info.assume_init() };
+		Ok(info.as_word().as_u32())
+	}
+}
+
+impl Drop for Device<'_> {
+	fn drop(&mut self) {
+		unsafe { (self.sane.vtable.close)(self.handle) };
+	}
+}
+
+fn get_parameters(
+	vtable: &Vtable,
+	handle: Handle,
+) -> Result<Parameters, Status> {
+	let mut params = MaybeUninit::uninit();
+	let status = unsafe { (vtable.get_parameters)(handle, &mut params) };
+	if status != Status::GOOD {
+		return Err(status);
+	}
+	Ok(unsafe { params.assume_init() })
+}
+
+// Backends access option values as arrays of `SANE_Word`, so the value is
+// staged in a buffer with the alignment of a word.
+struct ValueBuf {
+	words: Vec<u32>,
+	size: usize,
+}
+
+impl ValueBuf {
+	fn new(size: usize) -> ValueBuf {
+		ValueBuf {
+			words: vec![0; size.div_ceil(mem::size_of::<u32>())],
+			size,
+		}
+	}
+
+	fn as_bytes(&self) -> &[u8] {
+		let ptr = self.words.as_ptr().cast::<u8>();
+		unsafe { core::slice::from_raw_parts(ptr, self.size) }
+	}
+
+	fn as_bytes_mut(&mut self) -> &mut [u8] {
+		let ptr = self.words.as_mut_ptr().cast::<u8>();
+		unsafe { core::slice::from_raw_parts_mut(ptr, self.size) }
+	}
+}
+
+// }}}
+
+// Scan {{{
+
+/// A scan started with `sane_start()`.
+///
+/// `sane_cancel()` is called when the `Scan` is dropped.
+///
+/// After [`set_io_mode(true)`](Scan::set_io_mode), reads that would block
+/// return immediately: [`Scan::try_read_data`] returns `Ok(None)`, and the
+/// [`Read`](std::io::Read) implementation returns an error of kind
+/// [`WouldBlock`](std::io::ErrorKind::WouldBlock). The
+/// [`select_fd`](Scan::select_fd) can be polled to wait for data.
+pub struct Scan<'a> {
+	device: &'a Device<'a>,
+	non_blocking: bool,
+}
+
+impl fmt::Debug for Scan<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Scan")
+			.field("handle", &self.device.handle)
+			.field("non_blocking", &self.non_blocking)
+			.finish()
+	}
+}
+
+impl<'a> Scan<'a> {
+	pub fn device(&self) -> &Device<'a> {
+		self.device
+	}
+
+	/// `sane_get_parameters()`
+	pub fn get_parameters(&self) -> Result<Parameters, Status> {
+		get_parameters(self.device.sane.vtable, self.device.handle)
+	}
+
+	/// `sane_read()`
+	///
+	/// Returns `Ok(0)` when the backend reports `SANE_STATUS_EOF` for the
+	/// current frame. In non-blocking mode, `Ok(0)` is also returned if no
+	/// data is available yet; use [`Scan::try_read_data`] to tell these
+	/// apart.
+	pub fn read_data(&mut self, buf: &mut [u8]) -> Result<usize, Status> {
+		Ok(self.try_read_data(buf)?.unwrap_or(0))
+	}
+
+	/// `sane_read()`
+	///
+	/// Returns `Ok(Some(0))` when the backend reports `SANE_STATUS_EOF` for
+	/// the current frame, and `Ok(None)` if the backend returned no data
+	/// without reaching the end of the frame. This only happens in
+	/// non-blocking mode.
+	pub fn try_read_data(
+		&mut self,
+		buf: &mut [u8],
+	) -> Result<Option<usize>, Status> {
+		if buf.is_empty() {
+			return Ok(Some(0));
+		}
+		let max_len = buf.len().min(i32::MAX as usize);
+		let mut len = MaybeUninit::new(Int::new(0));
+		let status = unsafe {
+			(self.device.sane.vtable.read)(
+				self.device.handle,
+				buf.as_mut_ptr().cast(),
+				Int::new(max_len as i32),
+				&mut len,
+			)
+		};
+		if status == Status::EOF {
+			return Ok(Some(0));
+		}
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		let len = unsafe { len.assume_init() }.as_i32();
+		match (len.max(0) as usize).min(max_len) {
+			0 => Ok(None),
+			len => Ok(Some(len)),
+		}
+	}
+
+	/// `sane_set_io_mode()`
+	///
+	/// Backends that don't support non-blocking I/O return
+	/// `SANE_STATUS_UNSUPPORTED`.
+	pub fn set_io_mode(&mut self, non_blocking: bool) -> Result<(), Status> {
+		let vtable = self.device.sane.vtable;
+		let status = unsafe {
+			(vtable.set_io_mode)(self.device.handle, Bool::new(non_blocking))
+		};
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		self.non_blocking = non_blocking;
+		Ok(())
+	}
+
+	pub fn is_non_blocking(&self) -> bool {
+		self.non_blocking
+	}
+
+	/// `sane_get_select_fd()`
+	///
+	/// The file descriptor becomes readable when image data is available.
+	/// The backend keeps it open until the frame ends, so it's borrowed
+	/// until the scan is next read from or restarted.
+	#[cfg(all(any(doc, feature = "std"), unix))]
+	pub fn select_fd(&self) -> Result<BorrowedFd<'_>, Status> {
+		let vtable = self.device.sane.vtable;
+		let mut fd = MaybeUninit::uninit();
+		let status =
+			unsafe { (vtable.get_select_fd)(self.device.handle, &mut fd) };
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		let fd = unsafe { fd.assume_init() }.as_i32();
+		if fd < 0 {
+			return Err(Status::IO_ERROR);
+		}
+		Ok(unsafe { BorrowedFd::borrow_raw(fd) })
+	}
+
+	/// `sane_start()`
+	///
+	/// Starts the next frame of a multi-frame scan, or the next page of a
+	/// batch scan.
+	pub fn start_next(&mut self) -> Result<(), Status> {
+		let vtable = self.device.sane.vtable;
+		let status = unsafe { (vtable.start)(self.device.handle) };
+		if status != Status::GOOD {
+			return Err(status);
+		}
+		Ok(())
+	}
+
+	/// `sane_cancel()`
+	pub fn cancel(self) {}
+}
+
+impl Drop for Scan<'_> {
+	fn drop(&mut self) {
+		let vtable = self.device.sane.vtable;
+		unsafe { (vtable.cancel)(self.device.handle) };
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+impl std::io::Read for Scan<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		use std::io::ErrorKind;
+		loop {
+			let result = self.try_read_data(buf).map_err(|status| {
+				let kind = match status {
+					Status::NO_MEM => ErrorKind::OutOfMemory,
+					Status::ACCESS_DENIED => ErrorKind::PermissionDenied,
+					_ => ErrorKind::Other,
+				};
+				let message = format!("sane_read() failed: {:?}", status);
+				std::io::Error::new(kind, message)
+			});
+			match result? {
+				Some(len) => return Ok(len),
+				None if self.non_blocking => {
+					return Err(ErrorKind::WouldBlock.into());
+				},
+				None => continue,
+			}
+		}
+	}
+}
+
+// }}}
+
+// Authorization {{{
+
+/// A username and password, sent in response to an authorization request.
+///
+/// Values longer than the `SANE_MAX_USERNAME_LEN` or `SANE_MAX_PASSWORD_LEN`
+/// buffers are truncated, as are values containing a NUL byte.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Credentials {
+	username: Vec<u8>,
+	password: Vec<u8>,
+}
+
+impl fmt::Debug for Credentials {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Credentials")
+			.field("username", &self.username)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Credentials {
+	pub fn new(
+		username: impl Into<Vec<u8>>,
+		password: impl Into<Vec<u8>>,
+	) -> Credentials {
+		Credentials {
+			username: username.into(),
+			password: password.into(),
+		}
+	}
+
+	pub fn username(&self) -> &[u8] {
+		&self.username
+	}
+
+	pub fn password(&self) -> &[u8] {
+		&self.password
+	}
+}
+
+/// Handler for `SANE_Auth_Callback` requests.
+///
+/// Returning `None` cancels the request, which is reported to the backend as
+/// an empty username and password.
+pub trait Authorize: Send {
+	fn authorize(&mut self, resource: &CStr) -> Option<Credentials>;
+}
+
+impl<F> Authorize for F
+where
+	F: FnMut(&CStr) -> Option<Credentials> + Send,
+{
+	fn authorize(&mut self, resource: &CStr) -> Option<Credentials> {
+		self(resource)
+	}
+}
+
+/// An [`Authorize`] registered as the process's `SANE_Auth_Callback`.
+///
+/// The C callback has no user data, so only one handler can be registered at
+/// a time. Registering a handler replaces the previous one, and dropping a
+/// handler unregisters it if it hasn't been replaced.
+///
+/// The callback may be invoked from any thread. Requests are passed to the
+/// handler one at a time.
+#[cfg(any(doc, feature = "std"))]
+pub struct AuthHandler {
+	id: u64,
+}
+
+#[cfg(any(doc, feature = "std"))]
+type SharedAuthorize = Arc<Mutex<dyn Authorize>>;
+
+#[cfg(any(doc, feature = "std"))]
+static AUTH_HANDLER: Mutex<Option<(u64, SharedAuthorize)>> = Mutex::new(None);
+
+#[cfg(any(doc, feature = "std"))]
+static AUTH_HANDLER_ID: core::sync::atomic::AtomicU64 =
+	core::sync::atomic::AtomicU64::new(0);
+
+#[cfg(any(doc, feature = "std"))]
+impl fmt::Debug for AuthHandler {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("AuthHandler").field("id", &self.id).finish()
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+impl AuthHandler {
+	pub fn new(authorize: impl Authorize + 'static) -> AuthHandler {
+		use core::sync::atomic::Ordering;
+		let id = AUTH_HANDLER_ID.fetch_add(1, Ordering::Relaxed);
+		let authorize: SharedAuthorize = Arc::new(Mutex::new(authorize));
+		*lock(&AUTH_HANDLER) = Some((id, authorize));
+		AuthHandler { id }
+	}
+
+	/// The callback to pass to `sane_init()`.
+	pub fn callback(&self) -> AuthCallback {
+		auth_callback
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+impl Drop for AuthHandler {
+	fn drop(&mut self) {
+		let mut current = lock(&AUTH_HANDLER);
+		if matches!(*current, Some((id, _)) if id == self.id) {
+			*current = None;
+		}
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	match mutex.lock() {
+		Ok(guard) => guard,
+		Err(err) => err.into_inner(),
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+extern "C" fn auth_callback(
+	resource: StringConst,
+	username: *mut MaybeUninit<[core::ffi::c_char; crate::MAX_USERNAME_LEN]>,
+	password: *mut MaybeUninit<[core::ffi::c_char; crate::MAX_PASSWORD_LEN]>,
+) {
+	// The registry isn't locked while the handler runs, so that a handler
+	// prompting the user doesn't block other threads from registering.
+	let credentials = std::panic::catch_unwind(|| {
+		let authorize = lock(&AUTH_HANDLER).as_ref()?.1.clone();
+		let resource = unsafe { resource.to_c_str() }?;
+		let mut authorize = lock(&authorize);
+		authorize.authorize(resource)
+	});
+	let (username_bytes, password_bytes) = match &credentials {
+		Ok(Some(credentials)) => {
+			(credentials.username(), credentials.password())
+		},
+		_ => (&[][..], &[][..]),
+	};
+	unsafe {
+		write_auth_buf(username, username_bytes);
+		write_auth_buf(password, password_bytes);
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+unsafe fn write_auth_buf<const N: usize>(
+	buf: *mut MaybeUninit<[core::ffi::c_char; N]>,
+	value: &[u8],
+) {
+	let buf = match buf.as_mut() {
+		Some(buf) => buf.write([0; N]),
+		None => return,
+	};
+	let value = match value.iter().position(|&b| b == 0) {
+		Some(nul_idx) => &value[..nul_idx],
+		None => value,
+	};
+	for (dst, &src) in buf[..N - 1].iter_mut().zip(value) {
+		*dst = src as core::ffi::c_char;
+	}
+}
+
+// }}}
@@ -0,0 +1,492 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::io::Read;
+use std::mem::{size_of, MaybeUninit};
+use std::ptr;
+
+use sane::frontend::{Credentials, Sane};
+use sane::util;
+use sane::{
+	Action,
+	AuthCallback,
+	Bool,
+	Handle,
+	Int,
+	OptionDescriptor,
+	Parameters,
+	Status,
+	StringConst,
+};
+
+const fn cstr(bytes: &[u8]) -> &CStr {
+	unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+}
+
+const CSTR_EMPTY: &CStr = cstr(b"\x00");
+const CSTR_DEV_NAME: &CStr = cstr(b"test-device\x00");
+const CSTR_OPT_RESOLUTION: &CStr = cstr(b"resolution\x00");
+
+const TEST_IMAGE: &[u8] = b"image data";
+
+// TestBackend {{{
+
+// The backend's state is thread-local so that tests can run in parallel.
+struct TestBackend {
+	initialized: bool,
+	authorize: Option<AuthCallback>,
+	devices: util::DevicesBuf,
+	options: Vec<util::OptionDescriptorBuf>,
+	open: bool,
+	resolution: i32,
+	image_pos: Option<usize>,
+	cancelled: bool,
+	non_blocking: bool,
+	data_ready: bool,
+	select_fd: Option<i32>,
+}
+
+impl TestBackend {
+	fn new() -> TestBackend {
+		let mut devices = util::DevicesBuf::new();
+		devices.push(util::DeviceBuf::new(CSTR_DEV_NAME));
+		TestBackend {
+			initialized: false,
+			authorize: None,
+			devices,
+			options: vec![
+				util::IntOptionBuilder::new(CSTR_EMPTY).build(),
+				util::IntOptionBuilder::new(CSTR_OPT_RESOLUTION)
+					.range(75, 600, 1)
+					.build(),
+			],
+			open: false,
+			resolution: 300,
+			image_pos: None,
+			cancelled: false,
+			non_blocking: false,
+			data_ready: false,
+			select_fd: None,
+		}
+	}
+}
+
+thread_local! {
+	static BACKEND: RefCell<TestBackend> = RefCell::new(TestBackend::new());
+}
+
+fn with_backend<T>(f: impl FnOnce(&mut TestBackend) -> T) -> T {
+	BACKEND.with(|backend| f(&mut backend.borrow_mut()))
+}
+
+const VTABLE: sane::Vtable = sane::Vtable {
+	init: test_init,
+	exit: test_exit,
+	get_devices: test_get_devices,
+	open: test_open,
+	close: test_close,
+	get_option_descriptor: test_get_option_descriptor,
+	control_option: test_control_option,
+	get_parameters: test_get_parameters,
+	start: test_start,
+	read: test_read,
+	cancel: test_cancel,
+	set_io_mode: test_set_io_mode,
+	get_select_fd: test_get_select_fd,
+};
+
+unsafe extern "C" fn test_init(
+	version_code: *mut MaybeUninit<Int>,
+	authorize: Option<AuthCallback>,
+) -> Status {
+	let version = sane::version_code(1, 0, 1);
+	(*version_code).write(Int::new(version as i32));
+	with_backend(|backend| {
+		backend.initialized = true;
+		backend.authorize = authorize;
+	});
+	Status::GOOD
+}
+
+unsafe extern "C" fn test_exit() {
+	with_backend(|backend| backend.initialized = false);
+}
+
+unsafe extern "C" fn test_get_devices(
+	device_list: *mut MaybeUninit<*const *const sane::Device>,
+	_local_only: Bool,
+) -> Status {
+	let devices = with_backend(|backend| backend.devices.as_ptr());
+	(*device_list).write(devices);
+	Status::GOOD
+}
+
+unsafe extern "C" fn test_open(
+	device_name: StringConst,
+	handle: *mut MaybeUninit<Handle>,
+) -> Status {
+	if device_name.to_c_str() != Some(CSTR_DEV_NAME) {
+		return Status::INVAL;
+	}
+	with_backend(|backend| backend.open = true);
+	(*handle).write(Handle::new(ptr::NonNull::dangling().as_ptr()));
+	Status::GOOD
+}
+
+unsafe extern "C" fn test_close(_handle: Handle) {
+	with_backend(|backend| backend.open = false);
+}
+
+unsafe extern "C" fn test_get_option_descriptor(
+	_handle: Handle,
+	option: Int,
+) -> *const OptionDescriptor {
+	with_backend(|backend| match backend.options.get(option.as_i32() as usize) {
+		Some(descriptor) => descriptor.as_ptr(),
+		None => ptr::null(),
+	})
+}
+
+unsafe extern "C" fn test_control_option(
+	_handle: Handle,
+	option: Int,
+	action: Action,
+	value: *mut MaybeUninit<()>,
+	info: *mut MaybeUninit<Int>,
+) -> Status {
+	let value = value.cast::<i32>();
+	let mut info_bits = 0;
+	let status = with_backend(|backend| {
+		match (option.as_i32(), action) {
+			(0, Action::GET_VALUE) => {
+				*value = backend.options.len() as i32;
+			},
+			(1, Action::GET_VALUE) => {
+				*value = backend.resolution;
+			},
+			(1, Action::SET_VALUE) => {
+				backend.resolution = (*value).clamp(75, 600);
+				if backend.resolution != *value {
+					*value = backend.resolution;
+					info_bits |= sane::INFO_INEXACT;
+				}
+			},
+			(1, Action::SET_AUTO) => {
+				backend.resolution = 300;
+			},
+			_ => return Status::INVAL,
+		}
+		Status::GOOD
+	});
+	(*info).write(Int::new(info_bits as i32));
+	status
+}
+
+unsafe extern "C" fn test_get_parameters(
+	_handle: Handle,
+	params: *mut MaybeUninit<Parameters>,
+) -> Status {
+	let mut parameters = Parameters::new();
+	parameters.last_frame = Bool::TRUE;
+	parameters.bytes_per_line = Int::new(TEST_IMAGE.len() as i32);
+	parameters.pixels_per_line = Int::new(TEST_IMAGE.len() as i32);
+	parameters.lines = Int::new(1);
+	parameters.depth = Int::new(8);
+	(*params).write(parameters);
+	Status::GOOD
+}
+
+unsafe extern "C" fn test_start(_handle: Handle) -> Status {
+	with_backend(|backend| {
+		backend.image_pos = Some(0);
+		backend.cancelled = false;
+		backend.non_blocking = false;
+	});
+	Status::GOOD
+}
+
+// Data is returned in small chunks, to check that callers read until EOF.
+// In non-blocking mode, every other read returns no data.
+unsafe extern "C" fn test_read(
+	_handle: Handle,
+	data: *mut MaybeUninit<u8>,
+	max_length: Int,
+	length: *mut MaybeUninit<Int>,
+) -> Status {
+	(*length).write(Int::new(0));
+	with_backend(|backend| {
+		if backend.cancelled {
+			return Status::CANCELLED;
+		}
+		let pos = match backend.image_pos {
+			Some(pos) => pos,
+			None => return Status::INVAL,
+		};
+		let remaining = &TEST_IMAGE[pos..];
+		if remaining.is_empty() {
+			return Status::EOF;
+		}
+		if backend.non_blocking {
+			backend.data_ready = !backend.data_ready;
+			if !backend.data_ready {
+				return Status::GOOD;
+			}
+		}
+		let count = remaining.len().min(max_length.as_i32() as usize).min(4);
+		ptr::copy_nonoverlapping(remaining.as_ptr(), data.cast(), count);
+		backend.image_pos = Some(pos + count);
+		(*length).write(Int::new(count as i32));
+		Status::GOOD
+	})
+}
+
+unsafe extern "C" fn test_cancel(_handle: Handle) {
+	with_backend(|backend| {
+		backend.image_pos = None;
+		backend.cancelled = true;
+	});
+}
+
+unsafe extern "C" fn test_set_io_mode(
+	_handle: Handle,
+	non_blocking: Bool,
+) -> Status {
+	with_backend(|backend| {
+		if backend.image_pos.is_none() {
+			return Status::INVAL;
+		}
+		backend.non_blocking = non_blocking == Bool::TRUE;
+		backend.data_ready = false;
+		Status::GOOD
+	})
+}
+
+unsafe extern "C" fn test_get_select_fd(
+	_handle: Handle,
+	fd: *mut MaybeUninit<Int>,
+) -> Status {
+	match with_backend(|backend| backend.select_fd) {
+		Some(select_fd) => {
+			(*fd).write(Int::new(select_fd));
+			Status::GOOD
+		},
+		None => Status::UNSUPPORTED,
+	}
+}
+
+// Calls the auth callback registered by `sane_init()`, as a backend would.
+fn request_auth(resource: &CStr) -> (Vec<u8>, Vec<u8>) {
+	let authorize = with_backend(|backend| backend.authorize).unwrap();
+	let mut username = MaybeUninit::uninit();
+	let mut password = MaybeUninit::uninit();
+	authorize(StringConst::from_c_str(resource), &mut username, &mut password);
+	let username = unsafe { username.assume_init() };
+	let password = unsafe { password.assume_init() };
+	let to_bytes = |buf: &[std::ffi::c_char]| {
+		let cstr = unsafe { CStr::from_ptr(buf.as_ptr()) };
+		cstr.to_bytes().to_vec()
+	};
+	(to_bytes(&username), to_bytes(&password))
+}
+
+// }}}
+
+#[test]
+fn frontend_init() {
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	assert_eq!(sane.version_code(), sane::version_code(1, 0, 1));
+	assert!(with_backend(|backend| backend.initialized));
+
+	drop(sane);
+	assert!(!with_backend(|backend| backend.initialized));
+}
+
+#[test]
+fn frontend_get_devices() {
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	let devices = sane.get_devices(false).unwrap();
+	assert_eq!(devices.len(), 1);
+	assert_eq!(devices[0].name(), CSTR_DEV_NAME);
+}
+
+#[test]
+fn frontend_open() {
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	assert_eq!(sane.open(CSTR_EMPTY).unwrap_err(), Status::INVAL);
+
+	let device = sane.open(CSTR_DEV_NAME).unwrap();
+	assert!(with_backend(|backend| backend.open));
+
+	drop(device);
+	assert!(!with_backend(|backend| backend.open));
+}
+
+#[test]
+fn frontend_options() {
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	let mut device = sane.open(CSTR_DEV_NAME).unwrap();
+	assert_eq!(device.option_count(), Ok(2));
+
+	let descriptor = device.option_descriptor(1).unwrap();
+	assert_eq!(descriptor.name(), CSTR_OPT_RESOLUTION);
+	assert_eq!(descriptor.size(), size_of::<Int>());
+	assert!(device.option_descriptor(2).is_none());
+
+	let mut value = [0u8; 4];
+	device.get_option(1, &mut value).unwrap();
+	assert_eq!(i32::from_ne_bytes(value), 300);
+
+	let mut value = 150i32.to_ne_bytes();
+	assert_eq!(device.set_option(1, &mut value), Ok(0));
+	assert_eq!(with_backend(|backend| backend.resolution), 150);
+
+	let mut value = 1200i32.to_ne_bytes();
+	assert_eq!(device.set_option(1, &mut value), Ok(sane::INFO_INEXACT));
+	assert_eq!(i32::from_ne_bytes(value), 600);
+
+	assert_eq!(device.set_option_auto(1), Ok(0));
+	assert_eq!(with_backend(|backend| backend.resolution), 300);
+
+	// Buffers must have room for the whole value.
+	let mut value = [0u8; 2];
+	assert_eq!(device.get_option(1, &mut value), Err(Status::INVAL));
+	let mut value = [0u8; 8];
+	assert_eq!(device.set_option(1, &mut value), Err(Status::INVAL));
+	assert_eq!(device.get_option(2, &mut value), Err(Status::INVAL));
+}
+
+#[test]
+fn frontend_scan() {
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	let mut device = sane.open(CSTR_DEV_NAME).unwrap();
+
+	let mut scan = device.start().unwrap();
+	let params = scan.get_parameters().unwrap();
+	assert_eq!(params.bytes_per_line.as_i32(), TEST_IMAGE.len() as i32);
+
+	let mut image = Vec::new();
+	scan.read_to_end(&mut image).unwrap();
+	assert_eq!(image, TEST_IMAGE);
+
+	drop(scan);
+	assert!(with_backend(|backend| backend.cancelled));
+
+	let mut scan = device.start().unwrap();
+	let mut buf = [0u8; 2];
+	assert_eq!(scan.read_data(&mut buf), Ok(2));
+	assert_eq!(&buf, b"im");
+	scan.cancel();
+	assert!(with_backend(|backend| backend.image_pos.is_none()));
+}
+
+#[test]
+fn frontend_scan_non_blocking() {
+	use std::io::ErrorKind;
+
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	let mut device = sane.open(CSTR_DEV_NAME).unwrap();
+	let mut scan = device.start().unwrap();
+	assert!(!scan.is_non_blocking());
+
+	scan.set_io_mode(true).unwrap();
+	assert!(scan.is_non_blocking());
+
+	let mut buf = [0u8; 4];
+	assert_eq!(scan.try_read_data(&mut buf), Ok(Some(4)));
+	assert_eq!(scan.try_read_data(&mut buf), Ok(None));
+	assert_eq!(scan.try_read_data(&mut buf), Ok(Some(4)));
+	let err = scan.read(&mut buf).unwrap_err();
+	assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+	let mut image = Vec::new();
+	loop {
+		match scan.read(&mut buf) {
+			Ok(0) => break,
+			Ok(len) => image.extend_from_slice(&buf[..len]),
+			Err(err) if err.kind() == ErrorKind::WouldBlock => continue,
+			Err(err) => panic!("{:?}", err),
+		}
+	}
+	assert_eq!(image, &TEST_IMAGE[8..]);
+	assert_eq!(scan.try_read_data(&mut buf), Ok(Some(0)));
+	drop(scan);
+
+	// Blocking reads retry until data is available.
+	let mut scan = device.start().unwrap();
+	scan.set_io_mode(true).unwrap();
+	assert_eq!(scan.try_read_data(&mut buf), Ok(Some(4)));
+	scan.set_io_mode(false).unwrap();
+	with_backend(|backend| backend.non_blocking = true);
+	let mut image = Vec::new();
+	scan.read_to_end(&mut image).unwrap();
+	assert_eq!(image, &TEST_IMAGE[4..]);
+}
+
+#[test]
+fn frontend_scan_select_fd() {
+	use std::os::fd::AsRawFd;
+
+	let sane = unsafe { Sane::init(&VTABLE, None) }.unwrap();
+	let mut device = sane.open(CSTR_DEV_NAME).unwrap();
+	let scan = device.start().unwrap();
+	assert_eq!(scan.select_fd().unwrap_err(), Status::UNSUPPORTED);
+
+	let (stream, _peer) = std::os::unix::net::UnixStream::pair().unwrap();
+	let raw_fd = stream.as_raw_fd();
+	with_backend(|backend| backend.select_fd = Some(raw_fd));
+	assert_eq!(scan.select_fd().unwrap().as_raw_fd(), raw_fd);
+
+	with_backend(|backend| backend.select_fd = Some(-1));
+	assert_eq!(scan.select_fd().unwrap_err(), Status::IO_ERROR);
+}
+
+#[test]
+fn frontend_authorize() {
+	let long_username = vec![b'u'; sane::MAX_USERNAME_LEN + 10];
+	let sane = unsafe {
+		Sane::init_with_authorize(&VTABLE, move |resource: &CStr| {
+			match resource.to_bytes() {
+				b"test-device" => Some(Credentials::new("user", "pass")),
+				b"long" => Some(Credentials::new(long_username.clone(), "")),
+				b"nul" => Some(Credentials::new("user\0name", "pass\0word")),
+				_ => None,
+			}
+		})
+	}.unwrap();
+
+	let (username, password) = request_auth(CSTR_DEV_NAME);
+	assert_eq!(username, b"user");
+	assert_eq!(password, b"pass");
+
+	// Credentials are truncated to fit the buffers.
+	let (username, _) = request_auth(cstr(b"long\x00"));
+	assert_eq!(username.len(), sane::MAX_USERNAME_LEN - 1);
+
+	let (username, password) = request_auth(cstr(b"nul\x00"));
+	assert_eq!(username, b"user");
+	assert_eq!(password, b"pass");
+
+	// A cancelled request has empty credentials.
+	let (username, password) = request_auth(cstr(b"other\x00"));
+	assert_eq!(username, b"");
+	assert_eq!(password, b"");
+
+	// The handler is unregistered when the `Sane` is dropped.
+	drop(sane);
+	let (username, _) = request_auth(CSTR_DEV_NAME);
+	assert_eq!(username, b"");
+}
@@ -0,0 +1,109 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use sane::net;
+use sane::net::io::{DecodeRef, Decoded, Encode, EncodeErrorKind};
+use sane::{ValueType, Word};
+
+// The first byte selects the procedure of the request being replied to.
+fuzz_target!(|bytes: &[u8]| {
+	let Some((&procedure, bytes)) = bytes.split_first() else {
+		return;
+	};
+	let procedure = net::ProcedureNumber::from_word(Word::new(
+		u32::from(procedure % 11),
+	));
+
+	let mut cursor = std::io::Cursor::new(bytes);
+	let mut reader = net::io::Codec::BINARY_V3.reader(&mut cursor);
+	let decoded = net::Reply::decode_for(procedure, &mut reader);
+
+	let mut push_decoder = net::io::PushDecoder::new();
+	let pushed = push_decoder.push_reply(procedure, bytes);
+	match (&decoded, &pushed) {
+		(Ok(reply), Ok(Decoded::Complete { message, .. })) => {
+			assert_eq!(reply, message);
+		},
+		(Err(_), Ok(Decoded::NeedMoreData)) => {},
+		(Err(_), Err(_)) => {},
+		(decoded, pushed) => {
+			panic!("decode: {:?}, push: {:?}", decoded, pushed);
+		},
+	}
+
+	// Replies that can be borrowed must decode to the same message.
+	if let Ok(reply) = &decoded {
+		match reply {
+			net::Reply::Init(buf) => {
+				check_ref::<net::InitReply, _>(bytes, buf)
+			},
+			net::Reply::Open(buf) => {
+				check_ref::<net::OpenReply, _>(bytes, buf)
+			},
+			net::Reply::Close(buf) => {
+				check_ref::<net::CloseReply, _>(bytes, buf)
+			},
+			net::Reply::ControlOption(buf) => {
+				check_ref::<net::ControlOptionReply, _>(bytes, buf)
+			},
+			net::Reply::GetParameters(buf) => {
+				check_ref::<net::GetParametersReply, _>(bytes, buf)
+			},
+			net::Reply::Start(buf) => {
+				check_ref::<net::StartReply, _>(bytes, buf)
+			},
+			net::Reply::Cancel(buf) => {
+				check_ref::<net::CancelReply, _>(bytes, buf)
+			},
+			net::Reply::Authorize(buf) => {
+				check_ref::<net::AuthorizeReply, _>(bytes, buf)
+			},
+			_ => {},
+		}
+	}
+
+	// Anything that was decoded can be encoded again, except for empty STRING
+	// option values.
+	if let Ok(reply) = decoded {
+		let mut encoded = Vec::new();
+		let mut cursor = std::io::Cursor::new(&mut encoded);
+		let mut writer = net::io::Codec::BINARY_V3.writer(&mut cursor);
+		if let Err(err) = reply.encode(&mut writer) {
+			assert!(
+				matches!(
+					err.kind(),
+					EncodeErrorKind::InvalidValueSize(ValueType::STRING, 0),
+				),
+				"{:?}",
+				err,
+			);
+		}
+	}
+});
+
+fn check_ref<'a, T, Buf>(bytes: &'a [u8], buf: &Buf)
+where
+	T: DecodeRef<'a> + PartialEq<Buf> + std::fmt::Debug,
+	Buf: std::fmt::Debug,
+{
+	match net::io::decode_ref::<T>(bytes) {
+		Ok(Decoded::Complete { message, .. }) => assert_eq!(*message, *buf),
+		decoded => panic!("decode: {:?}, decode_ref: {:?}", buf, decoded),
+	}
+}
@@ -0,0 +1,61 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use sane::net;
+use sane::net::io::{Decode, Decoded, Encode, EncodeErrorKind};
+use sane::ValueType;
+
+fuzz_target!(|bytes: &[u8]| {
+	let mut cursor = std::io::Cursor::new(bytes);
+	let mut reader = net::io::Codec::BINARY_V3.reader(&mut cursor);
+	let decoded = net::Request::decode(&mut reader);
+
+	// The sans-IO decoder must agree with the blocking decoder.
+	match (&decoded, net::io::decode_prefix::<net::Request>(bytes)) {
+		(Ok(request), Ok(Decoded::Complete { message, consumed })) => {
+			assert_eq!(request, &message);
+			assert_eq!(consumed as u64, cursor.position());
+		},
+		(Err(err), Ok(Decoded::NeedMoreData)) => {
+			assert!(err.io_error().is_some());
+		},
+		(Err(_), Err(_)) => {},
+		(decoded, prefix) => {
+			panic!("decode: {:?}, decode_prefix: {:?}", decoded, prefix);
+		},
+	}
+
+	// Anything that was decoded can be encoded again, except for empty STRING
+	// option values.
+	if let Ok(request) = decoded {
+		let mut encoded = Vec::new();
+		let mut cursor = std::io::Cursor::new(&mut encoded);
+		let mut writer = net::io::Codec::BINARY_V3.writer(&mut cursor);
+		if let Err(err) = request.encode(&mut writer) {
+			assert!(
+				matches!(
+					err.kind(),
+					EncodeErrorKind::InvalidValueSize(ValueType::STRING, 0),
+				),
+				"{:?}",
+				err,
+			);
+		}
+	}
+});
@@ -47,10 +47,55 @@ pub use rpc_08_cancel::*;
 mod rpc_09_authorize;
 pub use rpc_09_authorize::*;
 
+mod rpc_10_exit;
+pub use rpc_10_exit::*;
+
+#[cfg(any(doc, feature = "alloc"))]
+mod auth;
+#[cfg(any(doc, feature = "alloc"))]
+pub use auth::*;
+
+#[cfg(any(doc, feature = "alloc"))]
+mod message;
+#[cfg(any(doc, feature = "alloc"))]
+pub use message::*;
+
+#[cfg(any(doc, feature = "std"))]
+mod client;
+#[cfg(any(doc, feature = "std"))]
+pub use client::*;
+
+#[cfg(any(doc, feature = "std"))]
+mod data;
+#[cfg(any(doc, feature = "std"))]
+pub use data::*;
+
+#[cfg(any(doc, feature = "std"))]
+mod remote;
+#[cfg(any(doc, feature = "std"))]
+pub use remote::*;
+
+#[cfg(any(doc, feature = "std"))]
+mod server;
+#[cfg(any(doc, feature = "std"))]
+pub use server::*;
+
 pub mod io;
 
+#[cfg(feature = "futures-io")]
+pub mod futures_io;
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+#[cfg(any(doc, feature = "alloc"))]
+mod md5;
+
 pub const VERSION_CODE: u32 = 0x01010003;
 
+/// The port registered for `sane-port` (6566).
+pub const DEFAULT_PORT: u16 = 6566;
+
 // ByteOrder {{{
 
 /// `SANE_Net_Byte_Order`
@@ -64,6 +109,13 @@ impl ByteOrder {
 	/// `SANE_NET_BIG_ENDIAN`
 	pub const BIG_ENDIAN: ByteOrder = ByteOrder(0x4321);
 
+	/// The byte order of the target platform.
+	pub const NATIVE: ByteOrder = if cfg!(target_endian = "big") {
+		ByteOrder::BIG_ENDIAN
+	} else {
+		ByteOrder::LITTLE_ENDIAN
+	};
+
 	pub const fn from_word(word: Word) -> ByteOrder {
 		ByteOrder(word.as_u32())
 	}
@@ -71,6 +123,23 @@ impl ByteOrder {
 	pub const fn as_word(self) -> Word {
 		Word::new(self.0)
 	}
+
+	/// Converts packed 16-bit samples in `data` from this byte order to the
+	/// native byte order.
+	///
+	/// Nothing is converted if this byte order is unknown. A trailing odd
+	/// byte is left unchanged, so `data` should begin on a sample boundary.
+	pub fn u16_samples_to_native(self, data: &mut [u8]) {
+		let swap = match self {
+			Self::BIG_ENDIAN | Self::LITTLE_ENDIAN => self != Self::NATIVE,
+			_ => false,
+		};
+		if swap {
+			for sample in data.chunks_exact_mut(2) {
+				sample.swap(0, 1);
+			}
+		}
+	}
 }
 
 impl fmt::Debug for ByteOrder {
@@ -0,0 +1,152 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+
+use core::ffi::CStr;
+use core::fmt;
+
+use crate::net;
+use crate::net::md5::Md5;
+
+const MD5_MARKER: &[u8] = b"$MD5$";
+
+// saned formats the digest input with `"%.128s%.128s"`.
+const MD5_INPUT_MAX_LEN: usize = 128;
+
+// AuthResource {{{
+
+/// A resource requiring authorization, from the `resource` field of a reply.
+///
+/// If the server expects the password to be sent as an MD5 digest, the
+/// resource name is followed by `$MD5$` and a salt.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub struct AuthResource<'a> {
+	resource: &'a CStr,
+	name: &'a [u8],
+	md5_salt: Option<&'a [u8]>,
+}
+
+impl fmt::Debug for AuthResource<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("AuthResource")
+			.field("resource", &self.resource)
+			.finish()
+	}
+}
+
+impl<'a> AuthResource<'a> {
+	pub fn parse(resource: &'a CStr) -> AuthResource<'a> {
+		let bytes = resource.to_bytes();
+		let marker_idx = bytes
+			.windows(MD5_MARKER.len())
+			.position(|window| window == MD5_MARKER);
+		let (name, md5_salt) = match marker_idx {
+			Some(idx) => {
+				let salt = &bytes[idx + MD5_MARKER.len()..];
+				(&bytes[..idx], Some(salt))
+			},
+			None => (bytes, None),
+		};
+		AuthResource {
+			resource,
+			name,
+			md5_salt,
+		}
+	}
+
+	/// The full resource, including any `$MD5$` salt.
+	pub fn as_c_str(&self) -> &'a CStr {
+		self.resource
+	}
+
+	/// The resource name, without any `$MD5$` salt.
+	pub fn name(&self) -> &'a [u8] {
+		self.name
+	}
+
+	pub fn md5_salt(&self) -> Option<&'a [u8]> {
+		self.md5_salt
+	}
+
+	/// Builds a `SANE_NET_AUTHORIZE` request with the password sent as an
+	/// MD5 digest.
+	///
+	/// Returns `None` if the resource has no `$MD5$` salt, so that the
+	/// password is never sent in plaintext.
+	pub fn md5_authorize_request(
+		&self,
+		username: impl Into<CString>,
+		password: &[u8],
+	) -> Option<net::AuthorizeRequestBuf> {
+		let salt = self.md5_salt?;
+		let mut request = net::AuthorizeRequestBuf::new();
+		request.set_resource(self.resource);
+		request.set_username(username);
+		request.set_password(md5_password(salt, password));
+		Some(request)
+	}
+}
+
+// }}}
+
+// MD5 passwords {{{
+
+/// Returns `$MD5$` followed by the hex-encoded MD5 digest of `salt` and
+/// `password`, in the form expected by saned.
+pub fn md5_password(salt: &[u8], password: &[u8]) -> CString {
+	let salt = &salt[..salt.len().min(MD5_INPUT_MAX_LEN)];
+	let password = &password[..password.len().min(MD5_INPUT_MAX_LEN)];
+
+	let mut md5 = Md5::new();
+	md5.update(salt);
+	md5.update(password);
+	let digest = md5.finish();
+
+	const HEX: &[u8; 16] = b"0123456789abcdef";
+	let mut encoded = Vec::with_capacity(MD5_MARKER.len() + digest.len() * 2);
+	encoded.extend_from_slice(MD5_MARKER);
+	for byte in digest {
+		encoded.push(HEX[usize::from(byte >> 4)]);
+		encoded.push(HEX[usize::from(byte & 0xF)]);
+	}
+	CString::new(encoded).unwrap()
+}
+
+/// Checks the password of a `SANE_NET_AUTHORIZE` request against the
+/// expected plaintext `password`.
+///
+/// Only MD5 digests are accepted. The comparison takes the same time
+/// regardless of where the digests differ.
+pub fn verify_md5_password(
+	salt: &[u8],
+	password: &[u8],
+	response: &CStr,
+) -> bool {
+	let expected = md5_password(salt, password);
+	let expected = expected.as_bytes();
+	let response = response.to_bytes();
+	if response.len() != expected.len() {
+		return false;
+	}
+	let diff = expected
+		.iter()
+		.zip(response)
+		.fold(0u8, |diff, (a, b)| diff | (a ^ b));
+	diff == 0
+}
+
+// }}}
@@ -0,0 +1,467 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::fmt;
+
+use std::ffi::CString;
+use std::io::{BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use crate::net;
+use crate::net::io::{self, Decode, Encode};
+use crate::Status;
+
+// How many times a request is retried after authorization is denied.
+const MAX_AUTH_ATTEMPTS: u32 = 3;
+
+// CredentialProvider {{{
+
+/// Source of credentials for servers that require authorization.
+pub trait CredentialProvider {
+	/// Returns the username and password for `resource`, or `None` if the
+	/// request should not be authorized.
+	///
+	/// If the credentials are rejected, the provider will be asked again.
+	fn credentials(
+		&mut self,
+		resource: &net::AuthResource,
+	) -> Option<(CString, CString)>;
+}
+
+impl<F> CredentialProvider for F
+where
+	F: FnMut(&net::AuthResource) -> Option<(CString, CString)>,
+{
+	fn credentials(
+		&mut self,
+		resource: &net::AuthResource,
+	) -> Option<(CString, CString)> {
+		self(resource)
+	}
+}
+
+// }}}
+
+// Client {{{
+
+/// Client for the SANE network protocol.
+///
+/// If a reply to `SANE_NET_OPEN`, `SANE_NET_CONTROL_OPTION` or
+/// `SANE_NET_START` asks for authorization, credentials are requested from
+/// the client's [`CredentialProvider`] and sent before the real reply is
+/// returned. Passwords are only sent as MD5 digests. Without a provider, or
+/// after repeated failures, the reply has status `SANE_STATUS_ACCESS_DENIED`.
+pub struct Client {
+	stream: BufReader<TcpStream>,
+	credentials: Option<Box<dyn CredentialProvider>>,
+	codec: io::Codec,
+}
+
+impl fmt::Debug for Client {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Client")
+			.field("stream", self.stream.get_ref())
+			.finish()
+	}
+}
+
+impl Client {
+	pub fn connect(addr: impl ToSocketAddrs) -> std::io::Result<Client> {
+		Ok(Client::new(TcpStream::connect(addr)?))
+	}
+
+	pub fn new(stream: TcpStream) -> Client {
+		Client {
+			stream: BufReader::new(stream),
+			credentials: None,
+			codec: io::Codec::BINARY_V3,
+		}
+	}
+
+	pub fn stream(&self) -> &TcpStream {
+		self.stream.get_ref()
+	}
+
+	pub fn set_credential_provider(
+		&mut self,
+		provider: impl CredentialProvider + 'static,
+	) {
+		self.credentials = Some(Box::new(provider));
+	}
+
+	/// Sets the limits used when decoding replies.
+	pub fn set_limits(&mut self, limits: io::Limits) {
+		self.codec = self.codec.with_limits(limits);
+	}
+
+	/// `SANE_NET_INIT`
+	pub fn init(
+		&mut self,
+		request: &net::InitRequest,
+	) -> Result<net::InitReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::INIT, request)
+	}
+
+	/// `SANE_NET_GET_DEVICES`
+	pub fn get_devices(
+		&mut self,
+	) -> Result<net::GetDevicesReplyBuf, ClientError> {
+		let request = net::GetDevicesRequestBuf::new();
+		self.call(net::ProcedureNumber::GET_DEVICES, &request)
+	}
+
+	/// `SANE_NET_OPEN`
+	pub fn open(
+		&mut self,
+		request: &net::OpenRequest,
+	) -> Result<net::OpenReplyBuf, ClientError> {
+		self.call_authorized(net::ProcedureNumber::OPEN, request)
+	}
+
+	/// `SANE_NET_CLOSE`
+	pub fn close(
+		&mut self,
+		request: &net::CloseRequest,
+	) -> Result<net::CloseReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::CLOSE, request)
+	}
+
+	/// `SANE_NET_GET_OPTION_DESCRIPTORS`
+	pub fn get_option_descriptors(
+		&mut self,
+		request: &net::GetOptionDescriptorsRequest,
+	) -> Result<net::GetOptionDescriptorsReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::GET_OPTION_DESCRIPTORS, request)
+	}
+
+	/// `SANE_NET_CONTROL_OPTION`
+	pub fn control_option(
+		&mut self,
+		request: &net::ControlOptionRequest,
+	) -> Result<net::ControlOptionReplyBuf, ClientError> {
+		self.call_authorized(net::ProcedureNumber::CONTROL_OPTION, request)
+	}
+
+	/// `SANE_NET_GET_PARAMETERS`
+	pub fn get_parameters(
+		&mut self,
+		request: &net::GetParametersRequest,
+	) -> Result<net::GetParametersReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::GET_PARAMETERS, request)
+	}
+
+	/// `SANE_NET_START`
+	pub fn start(
+		&mut self,
+		request: &net::StartRequest,
+	) -> Result<net::StartReplyBuf, ClientError> {
+		self.call_authorized(net::ProcedureNumber::START, request)
+	}
+
+	/// Connects to the data port returned by `SANE_NET_START`.
+	pub fn data_reader(
+		&self,
+		reply: &net::StartReply,
+	) -> std::io::Result<net::DataReader<TcpStream>> {
+		let mut addr = self.stream().peer_addr()?;
+		addr.set_port(reply.port());
+		Ok(net::DataReader::new(TcpStream::connect(addr)?))
+	}
+
+	/// `SANE_NET_CANCEL`
+	pub fn cancel(
+		&mut self,
+		request: &net::CancelRequest,
+	) -> Result<net::CancelReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::CANCEL, request)
+	}
+
+	/// `SANE_NET_AUTHORIZE`
+	pub fn authorize(
+		&mut self,
+		request: &net::AuthorizeRequest,
+	) -> Result<net::AuthorizeReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::AUTHORIZE, request)
+	}
+
+	/// `SANE_NET_EXIT`
+	pub fn exit(mut self) -> Result<(), ClientError> {
+		// The server closes the connection without sending a reply.
+		let request = net::ExitRequestBuf::new();
+		self.send(net::ProcedureNumber::EXIT, &request)?;
+		let _ = self.stream.get_ref().shutdown(std::net::Shutdown::Both);
+		Ok(())
+	}
+
+	fn call<Request, Reply>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<Reply, ClientError>
+	where
+		Request: Encode + ?Sized,
+		Reply: Decode,
+	{
+		self.send(procedure, request)?;
+		self.recv()
+	}
+
+	fn call_authorized<Request, Reply>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<Reply, ClientError>
+	where
+		Request: Encode + ?Sized,
+		Reply: AuthReply,
+	{
+		let mut auth = AuthAttempts::new();
+		let mut reply: Reply = self.call(procedure, request)?;
+		loop {
+			let provider = self.credentials.as_deref_mut();
+			match auth.next_step(&reply, provider) {
+				AuthStep::Authorize(auth_request) => {
+					let _: net::AuthorizeReplyBuf = self
+						.call(net::ProcedureNumber::AUTHORIZE, &auth_request)?;
+					reply = self.recv()?;
+				},
+				AuthStep::Resend => reply = self.call(procedure, request)?,
+				AuthStep::Deny(auth_request) => {
+					let _: net::AuthorizeReplyBuf = self
+						.call(net::ProcedureNumber::AUTHORIZE, &auth_request)?;
+					let _: Reply = self.recv()?;
+					return Ok(Reply::access_denied());
+				},
+				AuthStep::Done => return Ok(reply),
+			}
+		}
+	}
+
+	fn send<Request: Encode + ?Sized>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<(), ClientError> {
+		let mut bytes = Vec::new();
+		let mut cursor = std::io::Cursor::new(&mut bytes);
+		let mut writer = self.codec.writer(&mut cursor);
+		procedure.encode(&mut writer).map_err(ClientError::Encode)?;
+		request.encode(&mut writer).map_err(ClientError::Encode)?;
+		self.stream.get_mut().write_all(&bytes).map_err(ClientError::Io)
+	}
+
+	fn recv<Reply: Decode>(&mut self) -> Result<Reply, ClientError> {
+		let mut reader = self.codec.reader(&mut self.stream);
+		Reply::decode(&mut reader).map_err(ClientError::Decode)
+	}
+}
+
+// }}}
+
+// AuthAttempts {{{
+
+// Authorization of a request sent with `call_authorized()`, which may be
+// interrupted by requests for authorization and resent if the credentials
+// are denied.
+pub(super) struct AuthAttempts {
+	attempts: u32,
+	sent_credentials: bool,
+}
+
+pub(super) enum AuthStep {
+	// Send the `SANE_NET_AUTHORIZE` request, then read the next reply.
+	Authorize(net::AuthorizeRequestBuf),
+
+	// The credentials were denied, so send the request again.
+	Resend,
+
+	// Send the `SANE_NET_AUTHORIZE` request, which has no credentials, and
+	// read the next reply. The request is then reported as denied.
+	Deny(net::AuthorizeRequestBuf),
+
+	// The reply is final.
+	Done,
+}
+
+impl AuthAttempts {
+	pub(super) fn new() -> AuthAttempts {
+		AuthAttempts {
+			attempts: 0,
+			sent_credentials: false,
+		}
+	}
+
+	// Returns what to do after receiving `reply`. A server that keeps asking
+	// for authorization is answered without credentials once the attempts
+	// run out, and the request is denied whatever it replies.
+	pub(super) fn next_step(
+		&mut self,
+		reply: &impl AuthReply,
+		provider: Option<&mut (dyn CredentialProvider + 'static)>,
+	) -> AuthStep {
+		if let Some(resource) = reply.auth_resource() {
+			if self.attempts >= MAX_AUTH_ATTEMPTS {
+				let (request, _) = authorize_request(None, resource);
+				return AuthStep::Deny(request);
+			}
+			self.attempts += 1;
+			let (request, sent_credentials) =
+				authorize_request(provider, resource);
+			self.sent_credentials = sent_credentials;
+			return AuthStep::Authorize(request);
+		}
+		if self.sent_credentials
+			&& reply.auth_status() == Status::ACCESS_DENIED
+			&& self.attempts < MAX_AUTH_ATTEMPTS
+		{
+			self.sent_credentials = false;
+			return AuthStep::Resend;
+		}
+		AuthStep::Done
+	}
+}
+
+// Builds the `SANE_NET_AUTHORIZE` request for `resource`, and returns whether
+// it contains credentials. If there are no credentials to send, an empty
+// username and password are sent so that the server will deny the request.
+fn authorize_request(
+	provider: Option<&mut (dyn CredentialProvider + 'static)>,
+	resource: &CStr,
+) -> (net::AuthorizeRequestBuf, bool) {
+	let resource = net::AuthResource::parse(resource);
+	let mut request = None;
+	if let Some(provider) = provider {
+		request = provider
+			.credentials(&resource)
+			.and_then(|(username, password)| {
+				let password = password.as_bytes();
+				resource.md5_authorize_request(username, password)
+			});
+	}
+	let sent_credentials = request.is_some();
+	let request = request.unwrap_or_else(|| {
+		let mut request = net::AuthorizeRequestBuf::new();
+		request.set_resource(resource.as_c_str());
+		request
+	});
+	(request, sent_credentials)
+}
+
+// }}}
+
+// AuthReply {{{
+
+// Replies that may ask for authorization before the real reply is sent.
+pub(super) trait AuthReply: Decode {
+	fn auth_status(&self) -> Status;
+
+	fn auth_resource(&self) -> Option<&CStr>;
+
+	// A reply with status `SANE_STATUS_ACCESS_DENIED`.
+	fn access_denied() -> Self;
+}
+
+impl AuthReply for net::OpenReplyBuf {
+	fn auth_status(&self) -> Status {
+		self.status()
+	}
+
+	fn auth_resource(&self) -> Option<&CStr> {
+		self.resource().filter(|resource| !resource.is_empty())
+	}
+
+	fn access_denied() -> Self {
+		let mut reply = net::OpenReplyBuf::new();
+		reply.set_status(Status::ACCESS_DENIED);
+		reply
+	}
+}
+
+impl AuthReply for net::ControlOptionReplyBuf {
+	fn auth_status(&self) -> Status {
+		self.status()
+	}
+
+	fn auth_resource(&self) -> Option<&CStr> {
+		self.resource().filter(|resource| !resource.is_empty())
+	}
+
+	fn access_denied() -> Self {
+		let mut reply = net::ControlOptionReplyBuf::new();
+		reply.set_status(Status::ACCESS_DENIED);
+		reply
+	}
+}
+
+impl AuthReply for net::StartReplyBuf {
+	fn auth_status(&self) -> Status {
+		self.status()
+	}
+
+	fn auth_resource(&self) -> Option<&CStr> {
+		self.resource().filter(|resource| !resource.is_empty())
+	}
+
+	fn access_denied() -> Self {
+		let mut reply = net::StartReplyBuf::new();
+		reply.set_status(Status::ACCESS_DENIED);
+		reply
+	}
+}
+
+// }}}
+
+// ClientError {{{
+
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum ClientError {
+	Io(std::io::Error),
+	Encode(io::EncodeError<std::io::Error>),
+	Decode(io::DecodeError<std::io::Error>),
+}
+
+impl fmt::Display for ClientError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ClientError::Io(err) => write!(f, "I/O error: {}", err),
+			ClientError::Encode(err) => {
+				write!(f, "error encoding request: {}", err)
+			},
+			ClientError::Decode(err) => {
+				write!(f, "error decoding reply: {}", err)
+			},
+		}
+	}
+}
+
+impl std::error::Error for ClientError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ClientError::Io(err) => Some(err),
+			ClientError::Encode(err) => Some(err),
+			ClientError::Decode(err) => Some(err),
+		}
+	}
+}
+
+impl From<std::io::Error> for ClientError {
+	fn from(err: std::io::Error) -> ClientError {
+		ClientError::Io(err)
+	}
+}
+
+// }}}
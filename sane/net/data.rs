@@ -0,0 +1,404 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::fmt;
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::net::ByteOrder;
+use crate::{Status, Word};
+
+const RECORD_END: u32 = 0xFFFFFFFF;
+const RECORD_MAX_LEN: usize = (RECORD_END - 1) as usize;
+
+// DataReader {{{
+
+/// Reader for the image data stream of a `SANE_NET_START` connection.
+///
+/// The stream is a sequence of records, each prefixed by its length as a
+/// big-endian 32-bit word. A length of `0xFFFFFFFF` marks the end of the
+/// stream and is followed by a single byte containing the final status.
+pub struct DataReader<R> {
+	reader: R,
+	record_len: u32,
+	status: Option<Status>,
+}
+
+impl<R> fmt::Debug for DataReader<R> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("DataReader")
+			.field("record_len", &self.record_len)
+			.field("status", &self.status)
+			.finish()
+	}
+}
+
+impl<R> DataReader<R> {
+	pub fn new(reader: R) -> DataReader<R> {
+		DataReader {
+			reader,
+			record_len: 0,
+			status: None,
+		}
+	}
+
+	pub fn get_ref(&self) -> &R {
+		&self.reader
+	}
+
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+
+	/// Returns the status sent after the last record, or `None` if the end
+	/// of the stream has not been reached yet.
+	pub fn status(&self) -> Option<Status> {
+		self.status
+	}
+}
+
+impl<R: Read> DataReader<R> {
+	/// Reads and discards any remaining image data, then returns the final
+	/// status of the scan.
+	pub fn finish(&mut self) -> std::io::Result<Status> {
+		let mut buf = [0u8; 4096];
+		loop {
+			if self.read(&mut buf)? == 0 {
+				if let Some(status) = self.status {
+					return Ok(status);
+				}
+			}
+		}
+	}
+
+	/// Reads 16-bit samples sent in the given byte order, which should be
+	/// the [`byte_order()`] of the scan's start reply.
+	///
+	/// Returns the number of samples read, or 0 at the end of the stream.
+	/// Samples split across records are reassembled. As with [`Read::read`],
+	/// 0 is also returned without reading if `samples` is empty.
+	///
+	/// [`byte_order()`]: crate::net::StartReply::byte_order
+	pub fn read_u16_samples(
+		&mut self,
+		byte_order: ByteOrder,
+		samples: &mut [u16],
+	) -> std::io::Result<usize> {
+		match byte_order {
+			ByteOrder::BIG_ENDIAN | ByteOrder::LITTLE_ENDIAN => {},
+			_ => {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					format!("unknown byte order {:?}", byte_order),
+				));
+			},
+		}
+
+		let mut buf = [0u8; 4096];
+		let max_len = buf.len().min(samples.len().saturating_mul(2));
+		let mut len = self.read(&mut buf[..max_len])?;
+		if len % 2 == 1 {
+			self.read_exact(&mut buf[len..len + 1])?;
+			len += 1;
+		}
+		let bytes = &mut buf[..len];
+		byte_order.u16_samples_to_native(bytes);
+		for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+			*sample = u16::from_ne_bytes([bytes[0], bytes[1]]);
+		}
+		Ok(len / 2)
+	}
+
+	fn next_record(&mut self) -> std::io::Result<()> {
+		let mut len_buf = [0u8; 4];
+		self.reader.read_exact(&mut len_buf)?;
+		let len = u32::from_be_bytes(len_buf);
+		if len != RECORD_END {
+			self.record_len = len;
+			return Ok(());
+		}
+
+		let mut status_buf = [0u8; 1];
+		self.reader.read_exact(&mut status_buf)?;
+		let status = Status::from_word(Word::new(u32::from(status_buf[0])));
+		self.status = Some(status);
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for DataReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+		while self.record_len == 0 {
+			if self.status.is_some() {
+				return Ok(0);
+			}
+			self.next_record()?;
+		}
+		let max_len = buf.len().min(self.record_len as usize);
+		let count = self.reader.read(&mut buf[..max_len])?;
+		if count == 0 {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		self.record_len -= count as u32;
+		Ok(count)
+	}
+}
+
+// }}}
+
+// DataWriter {{{
+
+/// Writer for the image data stream of a `SANE_NET_START` connection.
+///
+/// Each write is sent as a single record. If the underlying writer times out
+/// partway through a record, the unsent bytes are kept and the next write
+/// (or [`flush`](Write::flush)) resumes from where it stopped. This allows
+/// the writer to be used with a socket that has a write timeout.
+pub struct DataWriter<W> {
+	writer: W,
+	buf: Vec<u8>,
+	buf_pos: usize,
+}
+
+impl<W> fmt::Debug for DataWriter<W> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("DataWriter")
+			.field("pending", &(self.buf.len() - self.buf_pos))
+			.finish()
+	}
+}
+
+impl<W> DataWriter<W> {
+	pub fn new(writer: W) -> DataWriter<W> {
+		DataWriter {
+			writer,
+			buf: Vec::new(),
+			buf_pos: 0,
+		}
+	}
+
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+}
+
+impl<W: Write> DataWriter<W> {
+	/// Sends `data` as one or more records. Empty records are not sent.
+	pub fn write_record(&mut self, data: &[u8]) -> std::io::Result<()> {
+		self.send_pending()?;
+		for chunk in data.chunks(RECORD_MAX_LEN) {
+			self.buf.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+			self.buf.extend_from_slice(chunk);
+		}
+		self.send_queued()
+	}
+
+	/// Sends each chunk of `chunks` as a record.
+	pub fn write_chunks<I>(&mut self, chunks: I) -> std::io::Result<()>
+	where
+		I: IntoIterator,
+		I::Item: AsRef<[u8]>,
+	{
+		for chunk in chunks {
+			self.write_record(chunk.as_ref())?;
+		}
+		Ok(())
+	}
+
+	/// Sends the contents of `reader` as records, until `reader` reaches
+	/// end-of-file. Returns the number of bytes copied.
+	pub fn copy_from<R: Read>(
+		&mut self,
+		reader: &mut R,
+	) -> std::io::Result<u64> {
+		let mut buf = [0u8; 4096];
+		let mut total = 0u64;
+		loop {
+			let count = match reader.read(&mut buf) {
+				Ok(0) => return Ok(total),
+				Ok(count) => count,
+				Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+					continue;
+				},
+				Err(err) => return Err(err),
+			};
+			self.write_record(&buf[..count])?;
+			total += count as u64;
+		}
+	}
+
+	/// Ends the stream with the given status.
+	///
+	/// No records may be written after the stream has been finished. If the
+	/// underlying writer has a timeout, [`flush`](Write::flush) should be
+	/// called until it succeeds to send any remaining data.
+	pub fn finish(&mut self, status: Status) -> std::io::Result<()> {
+		self.send_pending()?;
+		self.buf.extend_from_slice(&RECORD_END.to_be_bytes());
+		self.buf.push(status.as_word().as_u32() as u8);
+		self.send_queued()
+	}
+
+	// Data that has been queued is not lost if the write times out, so the
+	// caller shouldn't be told to retry it.
+	fn send_queued(&mut self) -> std::io::Result<()> {
+		match self.send_pending() {
+			Err(err) if is_timeout(&err) => Ok(()),
+			result => result,
+		}
+	}
+
+	fn send_pending(&mut self) -> std::io::Result<()> {
+		while self.buf_pos < self.buf.len() {
+			match self.writer.write(&self.buf[self.buf_pos..]) {
+				Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+				Ok(count) => self.buf_pos += count,
+				Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+				Err(err) => return Err(err),
+			}
+		}
+		self.buf.clear();
+		self.buf_pos = 0;
+		Ok(())
+	}
+}
+
+impl<W: Write> Write for DataWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		self.write_record(buf)?;
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.send_pending()?;
+		self.writer.flush()
+	}
+}
+
+pub(crate) fn is_timeout(err: &std::io::Error) -> bool {
+	use std::io::ErrorKind;
+	matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+// }}}
+
+// DataListener {{{
+
+/// Listener for the data connection of a `SANE_NET_START` reply.
+#[derive(Debug)]
+pub struct DataListener {
+	listener: TcpListener,
+	peer_ip: IpAddr,
+	port: u16,
+}
+
+impl DataListener {
+	/// Binds an ephemeral port on the local address of `control`. Only
+	/// connections from the peer of `control` will be accepted.
+	pub fn bind(control: &TcpStream) -> std::io::Result<DataListener> {
+		let listener = TcpListener::bind((control.local_addr()?.ip(), 0))?;
+		DataListener::new(control, listener)
+	}
+
+	/// Like [`DataListener::bind`], but binds the first free port in
+	/// `ports`, as for the `data_portrange` option of `saned.conf`.
+	pub fn bind_range(
+		control: &TcpStream,
+		ports: RangeInclusive<u16>,
+	) -> std::io::Result<DataListener> {
+		let ip = control.local_addr()?.ip();
+		for port in ports {
+			match TcpListener::bind((ip, port)) {
+				Ok(listener) => return DataListener::new(control, listener),
+				Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => {},
+				Err(err) => return Err(err),
+			}
+		}
+		Err(std::io::Error::new(
+			std::io::ErrorKind::AddrInUse,
+			"no free port in the data port range",
+		))
+	}
+
+	fn new(
+		control: &TcpStream,
+		listener: TcpListener,
+	) -> std::io::Result<DataListener> {
+		Ok(DataListener {
+			port: listener.local_addr()?.port(),
+			peer_ip: control.peer_addr()?.ip(),
+			listener,
+		})
+	}
+
+	/// The port to send in a `SANE_NET_START` reply.
+	pub fn port(&self) -> u16 {
+		self.port
+	}
+
+	/// Accepts the data connection, closing connections from other peers.
+	pub fn accept(self) -> std::io::Result<TcpStream> {
+		loop {
+			let (stream, addr) = self.listener.accept()?;
+			if addr.ip() == self.peer_ip {
+				return Ok(stream);
+			}
+		}
+	}
+
+	/// Like [`DataListener::accept`], but fails with
+	/// [`ErrorKind::TimedOut`](std::io::ErrorKind::TimedOut) if the peer
+	/// doesn't connect within `timeout`.
+	pub fn accept_timeout(
+		self,
+		timeout: Duration,
+	) -> std::io::Result<TcpStream> {
+		let deadline = Instant::now() + timeout;
+		self.listener.set_nonblocking(true)?;
+		loop {
+			match self.listener.accept() {
+				Ok((stream, addr)) => {
+					if addr.ip() == self.peer_ip {
+						stream.set_nonblocking(false)?;
+						return Ok(stream);
+					}
+				},
+				Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+					let now = Instant::now();
+					if now >= deadline {
+						return Err(std::io::ErrorKind::TimedOut.into());
+					}
+					std::thread::sleep((deadline - now).min(ACCEPT_POLL));
+				},
+				Err(err) => return Err(err),
+			}
+		}
+	}
+}
+
+// How often `DataListener::accept_timeout()` checks for a connection.
+const ACCEPT_POLL: Duration = Duration::from_millis(10);
+
+// }}}
@@ -0,0 +1,230 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+//! Asynchronous network protocol I/O, using the [`futures_io`] traits.
+//!
+//! These types do not depend on a particular async runtime. Messages are
+//! decoded and encoded with the same [`Decode`] and [`Encode`] impls as the
+//! synchronous [`io`](crate::net::io) module.
+
+use core::fmt;
+use core::future::poll_fn;
+use core::pin::Pin;
+
+use std::io;
+
+use ::futures_io::{AsyncRead, AsyncWrite};
+
+use crate::net;
+use crate::net::io::{
+	encode_to_vec,
+	Codec,
+	Decode,
+	DecodeBuffer,
+	DecodeError,
+	Encode,
+	EncodeError,
+	Limits,
+};
+
+// Decoder {{{
+
+/// Decodes messages from an [`AsyncRead`].
+///
+/// Bytes are read into a buffer until a complete message can be decoded.
+/// Any bytes following the message are kept for the next call.
+///
+/// Unless other [`Limits`] are set, messages longer than 16 MiB are rejected
+/// rather than buffered.
+pub struct Decoder<R> {
+	reader: R,
+	buf: DecodeBuffer,
+}
+
+impl<R> fmt::Debug for Decoder<R> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Decoder")
+			.field("buffered", &self.buf.len())
+			.finish()
+	}
+}
+
+impl<R> Decoder<R> {
+	pub fn new(reader: R) -> Decoder<R> {
+		Decoder {
+			reader,
+			buf: DecodeBuffer::new(),
+		}
+	}
+
+	pub fn get_ref(&self) -> &R {
+		&self.reader
+	}
+
+	pub fn get_mut(&mut self) -> &mut R {
+		&mut self.reader
+	}
+
+	/// Returns the reader. Any buffered bytes are lost.
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+
+	pub fn limits(&self) -> &Limits {
+		self.buf.limits()
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.buf.set_limits(limits);
+	}
+}
+
+impl<R: AsyncRead + Unpin> Decoder<R> {
+	pub async fn decode<T: Decode>(
+		&mut self,
+	) -> Result<T, DecodeError<io::Error>> {
+		loop {
+			if let Some(value) = self.buf.try_decode()? {
+				return Ok(value);
+			}
+			self.fill_buf().await?;
+		}
+	}
+
+	/// Decodes the reply to a request for `procedure`.
+	pub async fn decode_reply(
+		&mut self,
+		procedure: net::ProcedureNumber,
+	) -> Result<net::Reply, DecodeError<io::Error>> {
+		loop {
+			let reply = self
+				.buf
+				.try_decode_with(|r| net::Reply::decode_for(procedure, r))?;
+			if let Some(reply) = reply {
+				return Ok(reply);
+			}
+			self.fill_buf().await?;
+		}
+	}
+
+	async fn fill_buf(&mut self) -> Result<(), DecodeError<io::Error>> {
+		let reader = &mut self.reader;
+		let unfilled = self.buf.unfilled();
+		let count =
+			poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, unfilled))
+				.await
+				.map_err(DecodeError::io_err)?;
+		if count == 0 {
+			return Err(DecodeBuffer::eof_error());
+		}
+		self.buf.filled(count);
+		Ok(())
+	}
+}
+
+// }}}
+
+// Encoder {{{
+
+/// Encodes messages to an [`AsyncWrite`].
+///
+/// Messages are buffered by [`Encoder::encode`] until they are sent by
+/// [`Encoder::flush`], so that a request can be sent with a single write.
+pub struct Encoder<W> {
+	writer: W,
+	codec: Codec,
+	buf: Vec<u8>,
+	buf_pos: usize,
+}
+
+impl<W> fmt::Debug for Encoder<W> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Encoder")
+			.field("buffered", &(self.buf.len() - self.buf_pos))
+			.finish()
+	}
+}
+
+impl<W> Encoder<W> {
+	pub fn new(writer: W) -> Encoder<W> {
+		Encoder {
+			writer,
+			codec: Codec::BINARY_V3,
+			buf: Vec::new(),
+			buf_pos: 0,
+		}
+	}
+
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.writer
+	}
+
+	/// Returns the writer. Any buffered bytes are lost.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+
+	/// Encodes `value` into the buffer.
+	pub fn encode<T: Encode + ?Sized>(
+		&mut self,
+		value: &T,
+	) -> Result<(), EncodeError<io::Error>> {
+		encode_to_vec(&self.codec, &mut self.buf, value)
+	}
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<W> {
+	/// Writes the buffered messages.
+	///
+	/// If the future is dropped before it completes, the unwritten bytes
+	/// are kept and will be written by the next call.
+	pub async fn flush(&mut self) -> io::Result<()> {
+		while self.buf_pos < self.buf.len() {
+			let writer = &mut self.writer;
+			let pending = &self.buf[self.buf_pos..];
+			let count =
+				poll_fn(|cx| Pin::new(&mut *writer).poll_write(cx, pending))
+					.await?;
+			if count == 0 {
+				return Err(io::ErrorKind::WriteZero.into());
+			}
+			self.buf_pos += count;
+		}
+		self.buf.clear();
+		self.buf_pos = 0;
+		poll_fn(|cx| Pin::new(&mut self.writer).poll_flush(cx)).await
+	}
+
+	/// Encodes `value` and writes it, along with any buffered messages.
+	pub async fn send<T: Encode + ?Sized>(
+		&mut self,
+		value: &T,
+	) -> Result<(), EncodeError<io::Error>> {
+		self.encode(value)?;
+		self.flush().await.map_err(EncodeError::io_err)
+	}
+
+	/// Writes the buffered messages, then closes the writer.
+	pub async fn close(&mut self) -> io::Result<()> {
+		self.flush().await?;
+		poll_fn(|cx| Pin::new(&mut self.writer).poll_close(cx)).await
+	}
+}
+
+// }}}
@@ -20,11 +20,14 @@ use alloc::{
 };
 
 use core::{
-	convert::TryFrom,
+	convert::{Infallible, TryFrom},
 	ffi::CStr,
+	fmt,
+	marker::PhantomData,
 };
 
 use crate::Word;
+use crate::net::ByteOrder;
 
 // Read {{{
 
@@ -103,23 +106,179 @@ impl Write for std::io::Cursor<&mut Vec<u8>> {
 // Codec {{{
 
 pub struct Codec {
-	_p: (),
+	little_endian: bool,
+	limits: Limits,
 }
 
 impl Codec {
-	pub const BINARY_V3: Codec = Codec { _p: () };
+	pub const BINARY_V3: Codec = Codec {
+		little_endian: false,
+		limits: Limits::NONE,
+	};
+
+	/// Like [`Codec::BINARY_V3`], but with words in little-endian order.
+	pub const BINARY_V3_LITTLE_ENDIAN: Codec = Codec {
+		little_endian: true,
+		limits: Limits::NONE,
+	};
+
+	/// Returns a codec that encodes words in the given byte order, or `None`
+	/// if the byte order is not one of [`ByteOrder::BIG_ENDIAN`] or
+	/// [`ByteOrder::LITTLE_ENDIAN`].
+	pub const fn with_byte_order(
+		&self,
+		byte_order: ByteOrder,
+	) -> Option<Codec> {
+		let little_endian = match byte_order {
+			ByteOrder::LITTLE_ENDIAN => true,
+			ByteOrder::BIG_ENDIAN => false,
+			_ => return None,
+		};
+		Some(Codec {
+			little_endian,
+			limits: self.limits,
+		})
+	}
+
+	/// Returns a codec that decodes with the given limits.
+	pub const fn with_limits(&self, limits: Limits) -> Codec {
+		Codec {
+			little_endian: self.little_endian,
+			limits,
+		}
+	}
+
+	pub fn byte_order(&self) -> ByteOrder {
+		byte_order(self.little_endian)
+	}
+
+	pub fn limits(&self) -> &Limits {
+		&self.limits
+	}
 
 	pub fn reader<'a, R>(&self, r: &'a mut R) -> Reader<'a, R> {
-		Reader { r }
+		Reader {
+			r,
+			little_endian: self.little_endian,
+			limits: self.limits,
+			len: 0,
+		}
 	}
 
 	pub fn writer<'a, W>(&self, w: &'a mut W) -> Writer<'a, W> {
-		Writer { w }
+		Writer {
+			w,
+			little_endian: self.little_endian,
+		}
+	}
+}
+
+fn byte_order(little_endian: bool) -> ByteOrder {
+	if little_endian {
+		ByteOrder::LITTLE_ENDIAN
+	} else {
+		ByteOrder::BIG_ENDIAN
 	}
 }
 
 // }}}
 
+// Limits {{{
+
+/// Limits on the size of decoded messages.
+///
+/// Lengths in a message are sent by the peer, so without limits a peer can
+/// cause large allocations. Decoding a message that exceeds a limit fails
+/// with [`DecodeErrorKind::LimitExceeded`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+	max_string_len: usize,
+	max_option_count: usize,
+	max_word_list_len: usize,
+	max_message_len: usize,
+}
+
+impl Limits {
+	/// No limits, other than those of the protocol itself.
+	pub const NONE: Limits = Limits {
+		max_string_len: usize::MAX,
+		max_option_count: usize::MAX,
+		max_word_list_len: usize::MAX,
+		max_message_len: usize::MAX,
+	};
+
+	/// Maximum length of a string, including its NUL terminator.
+	pub fn max_string_len(&self) -> usize {
+		self.max_string_len
+	}
+
+	pub fn set_max_string_len(&mut self, max_string_len: usize) {
+		self.max_string_len = max_string_len;
+	}
+
+	/// Maximum number of option descriptors in a
+	/// `SANE_NET_GET_OPTION_DESCRIPTORS` reply.
+	pub fn max_option_count(&self) -> usize {
+		self.max_option_count
+	}
+
+	pub fn set_max_option_count(&mut self, max_option_count: usize) {
+		self.max_option_count = max_option_count;
+	}
+
+	/// Maximum number of elements in a word list or string list, such as an
+	/// option constraint or an `INT` or `FIXED` option value.
+	pub fn max_word_list_len(&self) -> usize {
+		self.max_word_list_len
+	}
+
+	pub fn set_max_word_list_len(&mut self, max_word_list_len: usize) {
+		self.max_word_list_len = max_word_list_len;
+	}
+
+	/// Maximum number of bytes read by a single [`Reader`].
+	pub fn max_message_len(&self) -> usize {
+		self.max_message_len
+	}
+
+	pub fn set_max_message_len(&mut self, max_message_len: usize) {
+		self.max_message_len = max_message_len;
+	}
+
+	fn get(&self, limit: Limit) -> usize {
+		match limit {
+			Limit::StringLen => self.max_string_len,
+			Limit::OptionCount => self.max_option_count,
+			Limit::WordListLen => self.max_word_list_len,
+			Limit::MessageLen => self.max_message_len,
+		}
+	}
+}
+
+/// Identifies one of the [`Limits`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Limit {
+	StringLen,
+	OptionCount,
+	WordListLen,
+	MessageLen,
+}
+
+// Decoders that buffer an incomplete message limit its length by default, so
+// that a peer can't make them buffer without bound.
+#[cfg(any(doc, feature = "alloc"))]
+const BUFFERED_MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+#[cfg(any(doc, feature = "alloc"))]
+fn buffered_codec() -> Codec {
+	let mut limits = Limits::NONE;
+	limits.set_max_message_len(BUFFERED_MAX_MESSAGE_LEN);
+	Codec::BINARY_V3.with_limits(limits)
+}
+
+// }}}
+
 // Decode {{{
 
 pub trait Decode: Sized {
@@ -133,26 +292,153 @@ pub struct DecodeError<IoError> {
 	pub(crate) kind: DecodeErrorKind<IoError>,
 }
 
-#[allow(dead_code)]
+/// The cause of a [`DecodeError`].
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) enum DecodeErrorKind<IoError> {
+#[non_exhaustive]
+pub enum DecodeErrorKind<IoError> {
+	/// A size field doesn't fit in a `usize`.
 	SizeOverflow(u32),
+
+	/// Memory for a buffer of the given length couldn't be allocated.
 	TryReserveError(usize),
+
+	/// A string is missing its NUL terminator.
 	InvalidString,
+
+	/// An option value has a type that can't hold a value.
 	InvalidOptionType,
+
+	/// A `SANE_Bool` is neither `SANE_FALSE` nor `SANE_TRUE`.
 	InvalidBool(Word),
+
+	/// An option has a value type that isn't valid for its constraint.
 	InvalidValueType(crate::ValueType),
+
+	/// An option constraint is not valid for the option's value type.
 	InvalidConstraint(crate::ValueType, crate::ConstraintType),
+
+	/// An option value or descriptor has a size that isn't valid for its
+	/// value type.
+	InvalidValueSize(crate::ValueType, u32),
+
+	/// An option value has an element count that doesn't match its size.
+	InvalidValueCount(crate::ValueType, u32),
+
+	/// A message has an unknown or unexpected procedure number.
+	InvalidProcedureNumber(crate::net::ProcedureNumber),
+
+	/// A pointer that must not be NULL was NULL.
 	NullPtr,
+
+	/// A length exceeds one of the decoder's [`Limits`].
+	LimitExceeded(Limit, usize),
+
+	/// The underlying reader failed.
 	IoError(IoError),
 }
 
 impl<IoError> DecodeError<IoError> {
-	fn io_err(err: IoError) -> Self {
+	pub fn kind(&self) -> &DecodeErrorKind<IoError> {
+		&self.kind
+	}
+
+	/// Returns the error from the underlying reader, if any.
+	pub fn io_error(&self) -> Option<&IoError> {
+		match &self.kind {
+			DecodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+
+	/// Returns the error from the underlying reader, if any.
+	pub fn into_io_error(self) -> Option<IoError> {
+		match self.kind {
+			DecodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+
+	pub(crate) fn io_err(err: IoError) -> Self {
 		DecodeError {
 			kind: DecodeErrorKind::IoError(err),
 		}
 	}
+
+	// Returns the I/O error, or converts `self` to a `DecodeError` with a
+	// different I/O error type.
+	fn without_io_err<F>(self) -> Result<DecodeError<F>, IoError> {
+		use DecodeErrorKind as K;
+		let kind = match self.kind {
+			K::SizeOverflow(size) => K::SizeOverflow(size),
+			K::TryReserveError(len) => K::TryReserveError(len),
+			K::InvalidString => K::InvalidString,
+			K::InvalidOptionType => K::InvalidOptionType,
+			K::InvalidBool(word) => K::InvalidBool(word),
+			K::InvalidValueType(t) => K::InvalidValueType(t),
+			K::InvalidConstraint(t, c) => K::InvalidConstraint(t, c),
+			K::InvalidValueSize(t, size) => K::InvalidValueSize(t, size),
+			K::InvalidValueCount(t, count) => K::InvalidValueCount(t, count),
+			K::InvalidProcedureNumber(p) => K::InvalidProcedureNumber(p),
+			K::NullPtr => K::NullPtr,
+			K::LimitExceeded(limit, len) => K::LimitExceeded(limit, len),
+			K::IoError(err) => return Err(err),
+		};
+		Ok(DecodeError { kind })
+	}
+}
+
+impl<IoError: fmt::Display> fmt::Display for DecodeError<IoError> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		use DecodeErrorKind as K;
+		match &self.kind {
+			K::SizeOverflow(size) => {
+				write!(f, "size {} exceeds the address space", size)
+			},
+			K::TryReserveError(len) => {
+				write!(f, "failed to allocate {} bytes", len)
+			},
+			K::InvalidString => f.write_str("string is not NUL-terminated"),
+			K::InvalidOptionType => f.write_str("invalid option value type"),
+			K::InvalidBool(word) => {
+				write!(f, "invalid SANE_Bool {:#010X}", word.as_u32())
+			},
+			K::InvalidValueType(value_type) => {
+				write!(f, "invalid value type {:?}", value_type)
+			},
+			K::InvalidConstraint(value_type, constraint_type) => write!(
+				f,
+				"invalid constraint {:?} for value type {:?}",
+				constraint_type, value_type,
+			),
+			K::InvalidValueSize(value_type, size) => {
+				write!(f, "invalid size {} for {:?} value", size, value_type)
+			},
+			K::InvalidValueCount(value_type, count) => {
+				write!(f, "invalid count {} for {:?} value", count, value_type)
+			},
+			K::InvalidProcedureNumber(procedure) => {
+				write!(f, "invalid procedure number {:?}", procedure)
+			},
+			K::NullPtr => f.write_str("unexpected NULL pointer"),
+			K::LimitExceeded(limit, len) => {
+				write!(f, "length {} exceeds {:?} limit", len, limit)
+			},
+			K::IoError(err) => write!(f, "I/O error: {}", err),
+		}
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+impl<IoError> std::error::Error for DecodeError<IoError>
+where
+	IoError: std::error::Error + 'static,
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match &self.kind {
+			DecodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
 }
 
 // }}}
@@ -171,20 +457,79 @@ pub struct EncodeError<IoError> {
 	pub(crate) kind: EncodeErrorKind<IoError>,
 }
 
-#[allow(dead_code)]
+/// The cause of an [`EncodeError`].
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) enum EncodeErrorKind<IoError> {
+#[non_exhaustive]
+pub enum EncodeErrorKind<IoError> {
+	/// A buffer is too long for its size to fit in a `SANE_Word`.
 	SizeOverflow(usize),
+
+	/// An option value has a type that can't hold a value.
 	InvalidOptionType,
+
+	/// An option value has a size that isn't valid for its value type.
+	InvalidValueSize(crate::ValueType, usize),
+
+	/// The underlying writer failed.
 	IoError(IoError),
 }
 
 impl<IoError> EncodeError<IoError> {
-	fn io_err(err: IoError) -> Self {
+	pub(crate) fn io_err(err: IoError) -> Self {
 		EncodeError {
 			kind: EncodeErrorKind::IoError(err),
 		}
 	}
+
+	pub fn kind(&self) -> &EncodeErrorKind<IoError> {
+		&self.kind
+	}
+
+	/// Returns the error from the underlying writer, if any.
+	pub fn io_error(&self) -> Option<&IoError> {
+		match &self.kind {
+			EncodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+
+	/// Returns the error from the underlying writer, if any.
+	pub fn into_io_error(self) -> Option<IoError> {
+		match self.kind {
+			EncodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl<IoError: fmt::Display> fmt::Display for EncodeError<IoError> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match &self.kind {
+			EncodeErrorKind::SizeOverflow(size) => {
+				write!(f, "size {} doesn't fit in a SANE_Word", size)
+			},
+			EncodeErrorKind::InvalidOptionType => {
+				f.write_str("invalid option value type")
+			},
+			EncodeErrorKind::InvalidValueSize(value_type, size) => {
+				write!(f, "invalid size {} for {:?} value", size, value_type)
+			},
+			EncodeErrorKind::IoError(err) => write!(f, "I/O error: {}", err),
+		}
+	}
+}
+
+#[cfg(any(doc, feature = "std"))]
+impl<IoError> std::error::Error for EncodeError<IoError>
+where
+	IoError: std::error::Error + 'static,
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match &self.kind {
+			EncodeErrorKind::IoError(err) => Some(err),
+			_ => None,
+		}
+	}
 }
 
 // }}}
@@ -193,6 +538,35 @@ impl<IoError> EncodeError<IoError> {
 
 pub struct Reader<'a, R> {
 	r: &'a mut R,
+	little_endian: bool,
+	limits: Limits,
+	len: usize,
+}
+
+impl<R> Reader<'_, R> {
+	pub fn byte_order(&self) -> ByteOrder {
+		byte_order(self.little_endian)
+	}
+
+	pub fn limits(&self) -> &Limits {
+		&self.limits
+	}
+
+	pub(crate) fn check_limit(
+		&self,
+		limit: Limit,
+		len: usize,
+	) -> Result<(), DecodeError<R::Error>>
+	where
+		R: Read,
+	{
+		if len <= self.limits.get(limit) {
+			return Ok(());
+		}
+		Err(DecodeError {
+			kind: DecodeErrorKind::LimitExceeded(limit, len),
+		})
+	}
 }
 
 impl<R: Read> Reader<'_, R> {
@@ -200,7 +574,11 @@ impl<R: Read> Reader<'_, R> {
 		&mut self,
 		buf: &mut [u8],
 	) -> Result<(), DecodeError<R::Error>> {
-		self.r.read_exact(buf).map_err(|e| DecodeError::io_err(e))
+		let len = self.len.saturating_add(buf.len());
+		self.check_limit(Limit::MessageLen, len)?;
+		self.r.read_exact(buf).map_err(DecodeError::io_err)?;
+		self.len = len;
+		Ok(())
 	}
 
 	#[cfg(any(doc, feature = "alloc"))]
@@ -208,21 +586,42 @@ impl<R: Read> Reader<'_, R> {
 		&mut self,
 		len: usize,
 	) -> Result<Vec<u8>, DecodeError<R::Error>> {
-		let mut bytes = Vec::new();
-		if len == 0 {
-			return Ok(bytes);
-		}
-		if let Err(_) = bytes.try_reserve(len) {
-			return Err(DecodeError {
-				kind: DecodeErrorKind::TryReserveError(len),
-			});
+		self.check_limit(Limit::MessageLen, self.len.saturating_add(len))?;
+
+		// The buffer is filled in chunks, so that a message with a large
+		// length but no content can't allocate more memory than it sent.
+		let mut bytes = try_vec_for_len(len)?;
+		while bytes.len() < len {
+			let start = bytes.len();
+			let end = start + (len - start).min(PREALLOC_MAX_BYTES);
+			if bytes.try_reserve(end - start).is_err() {
+				return Err(DecodeError {
+					kind: DecodeErrorKind::TryReserveError(len),
+				});
+			}
+			bytes.resize(end, 0u8);
+			self.read_bytes(&mut bytes[start..])?;
 		}
-		bytes.resize(len, 0u8);
-		self.read_bytes(&mut bytes)?;
 		Ok(bytes)
 	}
 
+	// Reads `len` bytes of packed words, returning them in big-endian order
+	// regardless of the codec's byte order. `len` must be a multiple of 4.
 	#[cfg(any(doc, feature = "alloc"))]
+	pub(crate) fn read_word_bytes(
+		&mut self,
+		len: usize,
+	) -> Result<Vec<u8>, DecodeError<R::Error>> {
+		debug_assert_eq!(len % 4, 0);
+		let mut bytes = self.read_vec(len)?;
+		if self.little_endian {
+			for word in bytes.chunks_exact_mut(4) {
+				word.reverse();
+			}
+		}
+		Ok(bytes)
+	}
+
 	pub(crate) fn read_size(&mut self) -> Result<usize, DecodeError<R::Error>> {
 		let size = Word::decode(self)?.as_u32();
 		match usize::try_from(size) {
@@ -233,6 +632,15 @@ impl<R: Read> Reader<'_, R> {
 		}
 	}
 
+	// Reads the size of a string, which is checked against the limits.
+	pub(crate) fn read_string_size(
+		&mut self,
+	) -> Result<usize, DecodeError<R::Error>> {
+		let size = self.read_size()?;
+		self.check_limit(Limit::StringLen, size)?;
+		Ok(size)
+	}
+
 	#[cfg(any(doc, feature = "alloc"))]
 	pub(crate) fn read_ptr<T: Decode>(
 		&mut self
@@ -245,12 +653,38 @@ impl<R: Read> Reader<'_, R> {
 	}
 }
 
+// Lengths decoded from a message are not trusted to preallocate more than
+// this many bytes. Larger vectors grow as their contents are decoded.
+#[cfg(any(doc, feature = "alloc"))]
+const PREALLOC_MAX_BYTES: usize = 64 * 1024;
+
+#[cfg(any(doc, feature = "alloc"))]
+pub(crate) fn try_vec_for_len<T, E>(
+	len: usize,
+) -> Result<Vec<T>, DecodeError<E>> {
+	let max_len = PREALLOC_MAX_BYTES / core::mem::size_of::<T>().max(1);
+	let mut vec = Vec::new();
+	if vec.try_reserve(len.min(max_len)).is_err() {
+		return Err(DecodeError {
+			kind: DecodeErrorKind::TryReserveError(len),
+		});
+	}
+	Ok(vec)
+}
+
 // }}}
 
 // Writer {{{
 
 pub struct Writer<'a, W> {
 	w: &'a mut W,
+	little_endian: bool,
+}
+
+impl<W> Writer<'_, W> {
+	pub fn byte_order(&self) -> ByteOrder {
+		byte_order(self.little_endian)
+	}
 }
 
 impl<W: Write> Writer<'_, W> {
@@ -258,7 +692,23 @@ impl<W: Write> Writer<'_, W> {
 		&mut self,
 		buf: &[u8],
 	) -> Result<(), EncodeError<W::Error>> {
-		self.w.write_all(buf).map_err(|e| EncodeError::io_err(e))
+		self.w.write_all(buf).map_err(EncodeError::io_err)
+	}
+
+	// Writes packed words stored in big-endian order, converting them to the
+	// codec's byte order. The length of `buf` must be a multiple of 4.
+	pub(crate) fn write_word_bytes(
+		&mut self,
+		buf: &[u8],
+	) -> Result<(), EncodeError<W::Error>> {
+		debug_assert_eq!(buf.len() % 4, 0);
+		if !self.little_endian {
+			return self.write_bytes(buf);
+		}
+		for word in buf.chunks_exact(4) {
+			self.write_bytes(&[word[3], word[2], word[1], word[0]])?;
+		}
+		Ok(())
 	}
 
 	pub(crate) fn write_size(
@@ -297,6 +747,9 @@ impl Decode for Word {
 	) -> Result<Self, DecodeError<R::Error>> {
 		let mut bytes = [0u8; 4];
 		r.read_bytes(&mut bytes)?;
+		if r.little_endian {
+			return Ok(Self::new(u32::from_le_bytes(bytes)));
+		}
 		Ok(Self::new(u32::from_be_bytes(bytes)))
 	}
 }
@@ -306,7 +759,11 @@ impl Encode for Word {
 		&self,
 		w: &mut Writer<W>,
 	) -> Result<(), EncodeError<W::Error>> {
-		let bytes = self.as_u32().to_be_bytes();
+		let bytes = if w.little_endian {
+			self.as_u32().to_le_bytes()
+		} else {
+			self.as_u32().to_be_bytes()
+		};
 		w.write_bytes(&bytes)
 	}
 }
@@ -427,7 +884,7 @@ impl Decode for CString {
 fn try_cstring_new<E>() -> Result<CString, DecodeError<E>> {
 	let len = 1;
 	let mut vec = Vec::new();
-	if let Err(_) = vec.try_reserve(len) {
+	if vec.try_reserve(len).is_err() {
 		return Err(DecodeError {
 			kind: DecodeErrorKind::TryReserveError(len),
 		});
@@ -441,7 +898,7 @@ impl Decode for Option<CString> {
 	fn decode<R: Read>(
 		r: &mut Reader<R>,
 	) -> Result<Self, DecodeError<R::Error>> {
-		let bytes_len = r.read_size()?;
+		let bytes_len = r.read_string_size()?;
 		if bytes_len == 0 {
 			return Ok(None);
 		}
@@ -466,3 +923,498 @@ fn cstring_from_vec_until_nul(mut bytes: Vec<u8>) -> Option<CString> {
 }
 
 // }}}
+
+// Decoded {{{
+
+/// The result of decoding a message from a byte slice.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Decoded<T> {
+	/// The slice does not contain a complete message.
+	NeedMoreData,
+
+	/// A message was decoded from the first `consumed` bytes of the slice.
+	Complete { message: T, consumed: usize },
+}
+
+/// Decodes a message from the start of `bytes`, without blocking.
+///
+/// Returns [`Decoded::NeedMoreData`] if `bytes` ends partway through the
+/// message. The caller should retry with a longer slice once more data has
+/// been received.
+pub fn decode_prefix<T: Decode>(
+	bytes: &[u8],
+) -> Result<Decoded<T>, DecodeError<Infallible>> {
+	decode_prefix_with(&Codec::BINARY_V3, bytes, T::decode)
+}
+
+pub(crate) fn decode_prefix_with<'a, T, F>(
+	codec: &Codec,
+	bytes: &'a [u8],
+	decode: F,
+) -> Result<Decoded<T>, DecodeError<Infallible>>
+where
+	F: FnOnce(
+		&mut Reader<SliceReader<'a>>,
+	) -> Result<T, DecodeError<Incomplete>>,
+{
+	Ok(decode_prefix_needed(codec, bytes, decode)?.0)
+}
+
+// Like `decode_prefix_with()`, but if the message is incomplete also returns
+// the length `bytes` must reach before decoding can get any further.
+fn decode_prefix_needed<'a, T, F>(
+	codec: &Codec,
+	bytes: &'a [u8],
+	decode: F,
+) -> Result<(Decoded<T>, usize), DecodeError<Infallible>>
+where
+	F: FnOnce(
+		&mut Reader<SliceReader<'a>>,
+	) -> Result<T, DecodeError<Incomplete>>,
+{
+	let mut slice_reader = SliceReader {
+		bytes,
+		pos: 0,
+		needed: 0,
+	};
+	match decode(&mut codec.reader(&mut slice_reader)) {
+		Ok(message) => Ok((
+			Decoded::Complete {
+				message,
+				consumed: slice_reader.pos,
+			},
+			0,
+		)),
+		Err(err) => match err.without_io_err() {
+			Ok(err) => Err(err),
+			Err(Incomplete) => {
+				Ok((Decoded::NeedMoreData, slice_reader.needed))
+			},
+		},
+	}
+}
+
+/// Reader for messages borrowed from a byte slice by [`DecodeRef`].
+pub struct SliceReader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+	// Length of `bytes` needed by the read that returned `Incomplete`.
+	needed: usize,
+}
+
+/// The byte slice ended partway through a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Incomplete;
+
+impl<'a> SliceReader<'a> {
+	fn read_slice(&mut self, len: usize) -> Result<&'a [u8], Incomplete> {
+		let bytes = &self.bytes[self.pos..];
+		if len > bytes.len() {
+			self.needed = self.pos.saturating_add(len);
+			return Err(Incomplete);
+		}
+		self.pos += len;
+		Ok(&bytes[..len])
+	}
+}
+
+impl Read for SliceReader<'_> {
+	type Error = Incomplete;
+
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Incomplete> {
+		buf.copy_from_slice(self.read_slice(buf.len())?);
+		Ok(())
+	}
+}
+
+// }}}
+
+// DecodeRef {{{
+
+/// Decodes a borrowed message type from a byte slice, without allocating.
+///
+/// Strings and option values of the decoded message point into the slice.
+/// Messages containing lists of devices or option descriptors can't be
+/// borrowed, and must be decoded with [`Decode`].
+pub trait DecodeRef<'a>: Sized {
+	fn decode_ref(
+		reader: &mut Reader<SliceReader<'a>>,
+	) -> Result<Borrowed<'a, Self>, DecodeError<Incomplete>>;
+}
+
+/// A message decoded by [`DecodeRef`], borrowing from the decoded bytes.
+pub struct Borrowed<'a, T> {
+	value: T,
+	_bytes: PhantomData<&'a [u8]>,
+}
+
+impl<'a, T> Borrowed<'a, T> {
+	// The borrowed message types store their references as `'static`, so
+	// the caller must ensure that `value` only references data living for
+	// at least `'a`.
+	pub(crate) unsafe fn new(value: T) -> Borrowed<'a, T> {
+		Borrowed {
+			value,
+			_bytes: PhantomData,
+		}
+	}
+}
+
+impl<'a, T: 'static> Borrowed<'a, T> {
+	// Message types without references to the decoded bytes don't need to
+	// borrow them.
+	pub(crate) fn owned(value: T) -> Borrowed<'a, T> {
+		Borrowed {
+			value,
+			_bytes: PhantomData,
+		}
+	}
+}
+
+impl<T> AsRef<T> for Borrowed<'_, T> {
+	fn as_ref(&self) -> &T {
+		&self.value
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for Borrowed<'_, T> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.value.fmt(f)
+	}
+}
+
+impl<T> core::ops::Deref for Borrowed<'_, T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		&self.value
+	}
+}
+
+/// Decodes a message from the start of `bytes` into a borrowed message
+/// type, without allocating.
+///
+/// As with [`decode_prefix()`], [`Decoded::NeedMoreData`] is returned if
+/// `bytes` ends partway through the message.
+pub fn decode_ref<'a, T: DecodeRef<'a>>(
+	bytes: &'a [u8],
+) -> Result<Decoded<Borrowed<'a, T>>, DecodeError<Infallible>> {
+	decode_ref_with_limits(bytes, Limits::NONE)
+}
+
+/// Like [`decode_ref()`], but fails with [`DecodeErrorKind::LimitExceeded`]
+/// if the message exceeds the given limits.
+pub fn decode_ref_with_limits<'a, T: DecodeRef<'a>>(
+	bytes: &'a [u8],
+	limits: Limits,
+) -> Result<Decoded<Borrowed<'a, T>>, DecodeError<Infallible>> {
+	let codec = Codec::BINARY_V3.with_limits(limits);
+	decode_prefix_with(&codec, bytes, T::decode_ref)
+}
+
+// Readers of borrowed messages are only constructed by `decode_ref()` and
+// `decode_ref_with_limits()`, so words are always in big-endian order and
+// option values can be borrowed without conversion.
+impl<'a> Reader<'_, SliceReader<'a>> {
+	pub(crate) fn read_slice(
+		&mut self,
+		len: usize,
+	) -> Result<&'a [u8], DecodeError<Incomplete>> {
+		let total_len = self.len.saturating_add(len);
+		self.check_limit(Limit::MessageLen, total_len)?;
+		let bytes = self.r.read_slice(len).map_err(DecodeError::io_err)?;
+		self.len = total_len;
+		Ok(bytes)
+	}
+
+	// Reads a string, which is `None` if its size is 0. Any bytes after the
+	// first NUL are ignored.
+	pub(crate) fn read_cstr(
+		&mut self,
+	) -> Result<Option<&'a CStr>, DecodeError<Incomplete>> {
+		let bytes_len = self.read_string_size()?;
+		if bytes_len == 0 {
+			return Ok(None);
+		}
+		let bytes = self.read_slice(bytes_len)?;
+		match bytes.iter().position(|&b| b == 0) {
+			Some(nul_idx) => Ok(Some(unsafe {
+				CStr::from_bytes_with_nul_unchecked(&bytes[..nul_idx + 1])
+			})),
+			None => Err(DecodeError {
+				kind: DecodeErrorKind::InvalidString,
+			}),
+		}
+	}
+}
+
+// }}}
+
+// PushDecoder {{{
+
+/// Incremental decoder for messages received in arbitrary pieces.
+///
+/// Bytes are pushed as they arrive, for example from an event loop or a
+/// completion-based I/O API. If they don't contain a complete message they
+/// are buffered, and decoding is retried when the next bytes are pushed.
+///
+/// Decoding isn't retried until enough bytes have been pushed to get further
+/// into the message. Unless other [`Limits`] are set, messages longer than
+/// 16 MiB are rejected rather than buffered.
+#[cfg(any(doc, feature = "alloc"))]
+pub struct PushDecoder {
+	buf: Vec<u8>,
+	codec: Codec,
+	// Length the buffer must reach before decoding is retried.
+	needed: usize,
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl core::fmt::Debug for PushDecoder {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		f.debug_struct("PushDecoder")
+			.field("buffered", &self.buf.len())
+			.finish()
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl PushDecoder {
+	pub fn new() -> PushDecoder {
+		PushDecoder {
+			buf: Vec::new(),
+			codec: buffered_codec(),
+			needed: 0,
+		}
+	}
+
+	pub fn limits(&self) -> &Limits {
+		self.codec.limits()
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.codec = self.codec.with_limits(limits);
+		self.needed = 0;
+	}
+
+	/// Returns the number of bytes buffered from previous pushes, which are
+	/// the start of an incomplete message.
+	pub fn buffered(&self) -> usize {
+		self.buf.len()
+	}
+
+	/// Discards any buffered bytes.
+	pub fn clear(&mut self) {
+		self.buf.clear();
+		self.needed = 0;
+	}
+
+	/// Pushes `bytes` and tries to decode a message.
+	///
+	/// If the message is complete, `consumed` is the number of bytes used
+	/// from `bytes`, and the remainder should be pushed to decode the next
+	/// message. [`Decoded::NeedMoreData`] means all of `bytes` was buffered.
+	///
+	/// On error the buffer is cleared.
+	pub fn push<T: Decode>(
+		&mut self,
+		bytes: &[u8],
+	) -> Result<Decoded<T>, DecodeError<Infallible>> {
+		self.push_with(bytes, |r| T::decode(r))
+	}
+
+	/// Pushes `bytes` and tries to decode the reply to a request for
+	/// `procedure`, as [`PushDecoder::push`].
+	pub fn push_reply(
+		&mut self,
+		procedure: crate::net::ProcedureNumber,
+		bytes: &[u8],
+	) -> Result<Decoded<crate::net::Reply>, DecodeError<Infallible>> {
+		self.push_with(bytes, |r| {
+			crate::net::Reply::decode_for(procedure, r)
+		})
+	}
+
+	fn push_with<T, F>(
+		&mut self,
+		bytes: &[u8],
+		decode: F,
+	) -> Result<Decoded<T>, DecodeError<Infallible>>
+	where
+		F: FnOnce(
+			&mut Reader<SliceReader>,
+		) -> Result<T, DecodeError<Incomplete>>,
+	{
+		if self.buf.is_empty() {
+			let (decoded, needed) =
+				decode_prefix_needed(&self.codec, bytes, decode)?;
+			if let Decoded::NeedMoreData = decoded {
+				self.buf.extend_from_slice(bytes);
+				self.needed = needed;
+			}
+			return Ok(decoded);
+		}
+
+		let prev_len = self.buf.len();
+		let max_len = self.codec.limits().max_message_len();
+		if prev_len > max_len {
+			self.clear();
+			return Err(DecodeError {
+				kind: DecodeErrorKind::LimitExceeded(
+					Limit::MessageLen,
+					prev_len,
+				),
+			});
+		}
+		self.buf.extend_from_slice(bytes);
+		if self.buf.len() < self.needed {
+			return Ok(Decoded::NeedMoreData);
+		}
+		match decode_prefix_needed(&self.codec, &self.buf, decode) {
+			Ok((Decoded::Complete { message, consumed }, _)) => {
+				self.clear();
+				Ok(Decoded::Complete {
+					message,
+					consumed: consumed - prev_len,
+				})
+			},
+			Ok((Decoded::NeedMoreData, needed)) => {
+				self.needed = needed;
+				Ok(Decoded::NeedMoreData)
+			},
+			Err(err) => {
+				self.clear();
+				Err(err)
+			},
+		}
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl Default for PushDecoder {
+	fn default() -> Self {
+		PushDecoder::new()
+	}
+}
+
+// }}}
+
+// DecodeBuffer {{{
+
+// Bytes read from an async reader are buffered until they contain a complete
+// message.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub(crate) struct DecodeBuffer {
+	buf: Vec<u8>,
+	len: usize,
+	codec: Codec,
+	// Decoding is only retried once `len` reaches this, so that a message
+	// received in many reads isn't decoded from the start after each one.
+	needed: usize,
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+impl DecodeBuffer {
+	const READ_LEN: usize = 4096;
+
+	pub(crate) fn new() -> DecodeBuffer {
+		DecodeBuffer {
+			buf: Vec::new(),
+			len: 0,
+			codec: buffered_codec(),
+			needed: 0,
+		}
+	}
+
+	pub(crate) fn limits(&self) -> &Limits {
+		self.codec.limits()
+	}
+
+	pub(crate) fn set_limits(&mut self, limits: Limits) {
+		self.codec = self.codec.with_limits(limits);
+		self.needed = 0;
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.len
+	}
+
+	// Returns space for the next read, which must be followed by a call to
+	// `filled()` with the number of bytes read.
+	pub(crate) fn unfilled(&mut self) -> &mut [u8] {
+		if self.buf.len() < self.len + Self::READ_LEN {
+			self.buf.resize(self.len + Self::READ_LEN, 0);
+		}
+		&mut self.buf[self.len..]
+	}
+
+	pub(crate) fn filled(&mut self, count: usize) {
+		self.len += count;
+	}
+
+	// Returns `None` if more bytes are needed to decode a `T`.
+	pub(crate) fn try_decode<T: Decode>(
+		&mut self,
+	) -> Result<Option<T>, DecodeError<std::io::Error>> {
+		self.try_decode_with(|r| T::decode(r))
+	}
+
+	pub(crate) fn try_decode_with<T, F>(
+		&mut self,
+		decode: F,
+	) -> Result<Option<T>, DecodeError<std::io::Error>>
+	where
+		F: FnOnce(
+			&mut Reader<SliceReader>,
+		) -> Result<T, DecodeError<Incomplete>>,
+	{
+		if self.len < self.needed {
+			return Ok(None);
+		}
+		let bytes = &self.buf[..self.len];
+		let decoded = match decode_prefix_needed(&self.codec, bytes, decode) {
+			Ok(decoded) => decoded,
+			Err(err) => {
+				return Err(err.without_io_err().unwrap_or_else(|e| match e {}));
+			},
+		};
+		match decoded {
+			(Decoded::Complete { message, consumed }, _) => {
+				self.buf.copy_within(consumed..self.len, 0);
+				self.len -= consumed;
+				self.needed = 0;
+				Ok(Some(message))
+			},
+			(Decoded::NeedMoreData, needed) => {
+				self.needed = needed;
+				Ok(None)
+			},
+		}
+	}
+
+	// Called when the reader reached end-of-file before `try_decode()`
+	// succeeded.
+	pub(crate) fn eof_error() -> DecodeError<std::io::Error> {
+		let err = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+		DecodeError::io_err(err)
+	}
+}
+
+// Encodes `value` to the end of `buf`. Nothing is appended on error.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+pub(crate) fn encode_to_vec<T: Encode + ?Sized>(
+	codec: &Codec,
+	buf: &mut Vec<u8>,
+	value: &T,
+) -> Result<(), EncodeError<std::io::Error>> {
+	let len = buf.len();
+	let mut cursor = std::io::Cursor::new(&mut *buf);
+	cursor.set_position(len as u64);
+	let mut writer = codec.writer(&mut cursor);
+	let result = value.encode(&mut writer);
+	if result.is_err() {
+		buf.truncate(len);
+	}
+	result
+}
+
+// }}}
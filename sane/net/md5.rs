@@ -0,0 +1,119 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+// MD5 message digest (RFC 1321), used by the `$MD5$` authorization scheme.
+
+const S: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+const K: [u32; 64] = [
+	0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE,
+	0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+	0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE,
+	0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+	0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA,
+	0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+	0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED,
+	0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+	0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C,
+	0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+	0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05,
+	0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+	0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039,
+	0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+	0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1,
+	0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391,
+];
+
+pub(crate) struct Md5 {
+	state: [u32; 4],
+	block: [u8; 64],
+	block_len: usize,
+	total_len: u64,
+}
+
+impl Md5 {
+	pub(crate) fn new() -> Md5 {
+		Md5 {
+			state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476],
+			block: [0; 64],
+			block_len: 0,
+			total_len: 0,
+		}
+	}
+
+	pub(crate) fn update(&mut self, mut data: &[u8]) {
+		self.total_len = self.total_len.wrapping_add(data.len() as u64);
+		while !data.is_empty() {
+			let count = data.len().min(64 - self.block_len);
+			self.block[self.block_len..self.block_len + count]
+				.copy_from_slice(&data[..count]);
+			self.block_len += count;
+			data = &data[count..];
+			if self.block_len == 64 {
+				let block = self.block;
+				self.compress(&block);
+				self.block_len = 0;
+			}
+		}
+	}
+
+	pub(crate) fn finish(mut self) -> [u8; 16] {
+		let bit_len = self.total_len.wrapping_mul(8);
+		self.update(&[0x80]);
+		while self.block_len != 56 {
+			self.update(&[0]);
+		}
+		self.update(&bit_len.to_le_bytes());
+
+		let mut digest = [0u8; 16];
+		for (chunk, word) in digest.chunks_mut(4).zip(self.state.iter()) {
+			chunk.copy_from_slice(&word.to_le_bytes());
+		}
+		digest
+	}
+
+	fn compress(&mut self, block: &[u8; 64]) {
+		let mut m = [0u32; 16];
+		for (word, chunk) in m.iter_mut().zip(block.chunks(4)) {
+			let bytes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+			*word = u32::from_le_bytes(bytes);
+		}
+
+		let [mut a, mut b, mut c, mut d] = self.state;
+		for i in 0..64 {
+			let (f, g) = match i / 16 {
+				0 => ((b & c) | (!b & d), i),
+				1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				2 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+			a = d;
+			d = c;
+			c = b;
+			b = b.wrapping_add(f.rotate_left(S[i]));
+		}
+
+		self.state[0] = self.state[0].wrapping_add(a);
+		self.state[1] = self.state[1].wrapping_add(b);
+		self.state[2] = self.state[2].wrapping_add(c);
+		self.state[3] = self.state[3].wrapping_add(d);
+	}
+}
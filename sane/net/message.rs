@@ -0,0 +1,193 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use crate::net;
+use crate::net::io::{self, Decode, DecodeErrorKind};
+
+// Request {{{
+
+/// A request message, prefixed by its procedure number when encoded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Request {
+	Init(net::InitRequestBuf),
+	GetDevices(net::GetDevicesRequestBuf),
+	Open(net::OpenRequestBuf),
+	Close(net::CloseRequestBuf),
+	GetOptionDescriptors(net::GetOptionDescriptorsRequestBuf),
+	ControlOption(net::ControlOptionRequestBuf),
+	GetParameters(net::GetParametersRequestBuf),
+	Start(net::StartRequestBuf),
+	Cancel(net::CancelRequestBuf),
+	Authorize(net::AuthorizeRequestBuf),
+	Exit(net::ExitRequestBuf),
+}
+
+impl Request {
+	pub fn procedure_number(&self) -> net::ProcedureNumber {
+		use net::ProcedureNumber as P;
+		match self {
+			Request::Init(_) => P::INIT,
+			Request::GetDevices(_) => P::GET_DEVICES,
+			Request::Open(_) => P::OPEN,
+			Request::Close(_) => P::CLOSE,
+			Request::GetOptionDescriptors(_) => P::GET_OPTION_DESCRIPTORS,
+			Request::ControlOption(_) => P::CONTROL_OPTION,
+			Request::GetParameters(_) => P::GET_PARAMETERS,
+			Request::Start(_) => P::START,
+			Request::Cancel(_) => P::CANCEL,
+			Request::Authorize(_) => P::AUTHORIZE,
+			Request::Exit(_) => P::EXIT,
+		}
+	}
+}
+
+impl io::Decode for Request {
+	fn decode<R: io::Read>(
+		r: &mut io::Reader<R>,
+	) -> Result<Self, io::DecodeError<R::Error>> {
+		use net::ProcedureNumber as P;
+		Ok(match net::ProcedureNumber::decode(r)? {
+			P::INIT => Request::Init(Decode::decode(r)?),
+			P::GET_DEVICES => Request::GetDevices(Decode::decode(r)?),
+			P::OPEN => Request::Open(Decode::decode(r)?),
+			P::CLOSE => Request::Close(Decode::decode(r)?),
+			P::GET_OPTION_DESCRIPTORS => {
+				Request::GetOptionDescriptors(Decode::decode(r)?)
+			},
+			P::CONTROL_OPTION => Request::ControlOption(Decode::decode(r)?),
+			P::GET_PARAMETERS => Request::GetParameters(Decode::decode(r)?),
+			P::START => Request::Start(Decode::decode(r)?),
+			P::CANCEL => Request::Cancel(Decode::decode(r)?),
+			P::AUTHORIZE => Request::Authorize(Decode::decode(r)?),
+			P::EXIT => Request::Exit(Decode::decode(r)?),
+			procedure => {
+				return Err(io::DecodeError {
+					kind: DecodeErrorKind::InvalidProcedureNumber(procedure),
+				});
+			},
+		})
+	}
+}
+
+impl io::Encode for Request {
+	fn encode<W: io::Write>(
+		&self,
+		w: &mut io::Writer<W>,
+	) -> Result<(), io::EncodeError<W::Error>> {
+		self.procedure_number().encode(w)?;
+		match self {
+			Request::Init(request) => request.encode(w),
+			Request::GetDevices(request) => request.encode(w),
+			Request::Open(request) => request.encode(w),
+			Request::Close(request) => request.encode(w),
+			Request::GetOptionDescriptors(request) => request.encode(w),
+			Request::ControlOption(request) => request.encode(w),
+			Request::GetParameters(request) => request.encode(w),
+			Request::Start(request) => request.encode(w),
+			Request::Cancel(request) => request.encode(w),
+			Request::Authorize(request) => request.encode(w),
+			Request::Exit(request) => request.encode(w),
+		}
+	}
+}
+
+// }}}
+
+// Reply {{{
+
+/// A reply message.
+///
+/// Replies don't include a procedure number, so decoding a reply requires
+/// knowing which procedure it is replying to. `SANE_NET_EXIT` has no reply.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reply {
+	Init(net::InitReplyBuf),
+	GetDevices(net::GetDevicesReplyBuf),
+	Open(net::OpenReplyBuf),
+	Close(net::CloseReplyBuf),
+	GetOptionDescriptors(net::GetOptionDescriptorsReplyBuf),
+	ControlOption(net::ControlOptionReplyBuf),
+	GetParameters(net::GetParametersReplyBuf),
+	Start(net::StartReplyBuf),
+	Cancel(net::CancelReplyBuf),
+	Authorize(net::AuthorizeReplyBuf),
+}
+
+impl Reply {
+	pub fn procedure_number(&self) -> net::ProcedureNumber {
+		use net::ProcedureNumber as P;
+		match self {
+			Reply::Init(_) => P::INIT,
+			Reply::GetDevices(_) => P::GET_DEVICES,
+			Reply::Open(_) => P::OPEN,
+			Reply::Close(_) => P::CLOSE,
+			Reply::GetOptionDescriptors(_) => P::GET_OPTION_DESCRIPTORS,
+			Reply::ControlOption(_) => P::CONTROL_OPTION,
+			Reply::GetParameters(_) => P::GET_PARAMETERS,
+			Reply::Start(_) => P::START,
+			Reply::Cancel(_) => P::CANCEL,
+			Reply::Authorize(_) => P::AUTHORIZE,
+		}
+	}
+
+	/// Decodes the reply to a request with the given procedure number.
+	pub fn decode_for<R: io::Read>(
+		procedure: net::ProcedureNumber,
+		r: &mut io::Reader<R>,
+	) -> Result<Self, io::DecodeError<R::Error>> {
+		use net::ProcedureNumber as P;
+		Ok(match procedure {
+			P::INIT => Reply::Init(Decode::decode(r)?),
+			P::GET_DEVICES => Reply::GetDevices(Decode::decode(r)?),
+			P::OPEN => Reply::Open(Decode::decode(r)?),
+			P::CLOSE => Reply::Close(Decode::decode(r)?),
+			P::GET_OPTION_DESCRIPTORS => {
+				Reply::GetOptionDescriptors(Decode::decode(r)?)
+			},
+			P::CONTROL_OPTION => Reply::ControlOption(Decode::decode(r)?),
+			P::GET_PARAMETERS => Reply::GetParameters(Decode::decode(r)?),
+			P::START => Reply::Start(Decode::decode(r)?),
+			P::CANCEL => Reply::Cancel(Decode::decode(r)?),
+			P::AUTHORIZE => Reply::Authorize(Decode::decode(r)?),
+			_ => {
+				return Err(io::DecodeError {
+					kind: DecodeErrorKind::InvalidProcedureNumber(procedure),
+				});
+			},
+		})
+	}
+}
+
+impl io::Encode for Reply {
+	fn encode<W: io::Write>(
+		&self,
+		w: &mut io::Writer<W>,
+	) -> Result<(), io::EncodeError<W::Error>> {
+		match self {
+			Reply::Init(reply) => reply.encode(w),
+			Reply::GetDevices(reply) => reply.encode(w),
+			Reply::Open(reply) => reply.encode(w),
+			Reply::Close(reply) => reply.encode(w),
+			Reply::GetOptionDescriptors(reply) => reply.encode(w),
+			Reply::ControlOption(reply) => reply.encode(w),
+			Reply::GetParameters(reply) => reply.encode(w),
+			Reply::Start(reply) => reply.encode(w),
+			Reply::Cancel(reply) => reply.encode(w),
+			Reply::Authorize(reply) => reply.encode(w),
+		}
+	}
+}
+
+// }}}
@@ -0,0 +1,380 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::fmt;
+
+use std::ffi::CString;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::net;
+use crate::util;
+use crate::Status;
+
+const NET_PREFIX: &[u8] = b"net:";
+
+// RemoteHost {{{
+
+/// A host running a SANE network server, with an optional port.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RemoteHost {
+	name: String,
+	port: Option<u16>,
+}
+
+impl RemoteHost {
+	pub fn new(name: impl Into<String>, port: Option<u16>) -> RemoteHost {
+		RemoteHost {
+			name: name.into(),
+			port,
+		}
+	}
+
+	/// Parses a host of the form `host`, `host:port`, `[address]`, or
+	/// `[address]:port`. IPv6 addresses without a port may also be written
+	/// without brackets.
+	pub fn parse(host: &str) -> Option<RemoteHost> {
+		if let Some(host) = host.strip_prefix('[') {
+			let (name, rest) = host.split_once(']')?;
+			let port = match rest {
+				"" => None,
+				_ => Some(parse_port(rest.strip_prefix(':')?)?),
+			};
+			return Some(RemoteHost::new(valid_name(name)?, port));
+		}
+		match host.split_once(':') {
+			Some((_, rest)) if rest.contains(':') => {
+				Some(RemoteHost::new(valid_name(host)?, None))
+			},
+			Some((name, port)) => {
+				let port = parse_port(port)?;
+				Some(RemoteHost::new(valid_name(name)?, Some(port)))
+			},
+			None => Some(RemoteHost::new(valid_name(host)?, None)),
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn port(&self) -> Option<u16> {
+		self.port
+	}
+
+	/// The port to connect to, which defaults to [`net::DEFAULT_PORT`].
+	pub fn port_or_default(&self) -> u16 {
+		self.port.unwrap_or(net::DEFAULT_PORT)
+	}
+}
+
+impl fmt::Display for RemoteHost {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.name.contains(':') {
+			write!(f, "[{}]", self.name)?;
+		} else {
+			f.write_str(&self.name)?;
+		}
+		if let Some(port) = self.port {
+			write!(f, ":{}", port)?;
+		}
+		Ok(())
+	}
+}
+
+impl ToSocketAddrs for RemoteHost {
+	type Iter = std::vec::IntoIter<SocketAddr>;
+
+	fn to_socket_addrs(&self) -> std::io::Result<Self::Iter> {
+		(self.name.as_str(), self.port_or_default()).to_socket_addrs()
+	}
+}
+
+fn valid_name(name: &str) -> Option<&str> {
+	if name.is_empty() || name.contains(['[', ']', '/']) {
+		return None;
+	}
+	Some(name)
+}
+
+fn parse_port(port: &str) -> Option<u16> {
+	if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	match port.parse() {
+		Ok(0) | Err(_) => None,
+		Ok(port) => Some(port),
+	}
+}
+
+// }}}
+
+// RemoteDeviceName {{{
+
+/// Name of a device on a remote host, as exposed by the `net` backend.
+///
+/// Names have the form `net:host:device`, where `host` is a hostname or an
+/// address (IPv6 addresses are written in brackets) and `device` is the
+/// name of the device on that host, such as `pixma:04A91749`. If the host
+/// uses a non-default port, it follows the host as `net:host:port:device`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct RemoteDeviceName {
+	host: RemoteHost,
+	device: CString,
+}
+
+impl RemoteDeviceName {
+	pub fn new(host: RemoteHost, device: impl Into<CString>) -> Self {
+		RemoteDeviceName {
+			host,
+			device: device.into(),
+		}
+	}
+
+	/// Parses a `net:` device name, or returns `None` if `name` is not the
+	/// name of a remote device.
+	///
+	/// A numeric field following the host is parsed as a port, since
+	/// backend names are never numeric.
+	pub fn parse(name: &CStr) -> Option<RemoteDeviceName> {
+		let name = name.to_bytes().strip_prefix(NET_PREFIX)?;
+		let (host, rest) = if name.first() == Some(&b'[') {
+			let end = name.iter().position(|&b| b == b']')?;
+			(&name[1..end], &name[end + 1..])
+		} else {
+			match name.iter().position(|&b| b == b':') {
+				Some(idx) => (&name[..idx], &name[idx..]),
+				None => (name, &name[name.len()..]),
+			}
+		};
+		let host = valid_name(core::str::from_utf8(host).ok()?)?;
+		let device = match rest {
+			b"" => rest,
+			_ => rest.strip_prefix(b":")?,
+		};
+
+		let mut port = None;
+		let mut device = device;
+		if let Some(idx) = device.iter().position(|&b| b == b':') {
+			let field = core::str::from_utf8(&device[..idx]).ok();
+			if let Some(field_port) = field.and_then(parse_port) {
+				port = Some(field_port);
+				device = &device[idx + 1..];
+			}
+		}
+		Some(RemoteDeviceName {
+			host: RemoteHost::new(host, port),
+			device: CString::new(device).ok()?,
+		})
+	}
+
+	pub fn host(&self) -> &RemoteHost {
+		&self.host
+	}
+
+	/// The name of the device on the remote host.
+	pub fn device(&self) -> &CStr {
+		&self.device
+	}
+
+	pub fn to_c_string(&self) -> CString {
+		let mut name = format!("net:{}:", self.host).into_bytes();
+		name.extend_from_slice(self.device.to_bytes());
+		// Neither the host nor the device name contain NUL.
+		CString::new(name).unwrap()
+	}
+}
+
+// }}}
+
+// DeviceAggregator {{{
+
+/// Merges the device lists of several SANE network servers, like the `net`
+/// backend.
+///
+/// Each device is listed with a [`RemoteDeviceName`], which can be used to
+/// find the client of its host. Connections are made when first needed.
+/// Hosts that can't be reached are skipped when listing devices.
+pub struct DeviceAggregator {
+	hosts: Vec<AggregatedHost>,
+	connect_timeout: Option<Duration>,
+	init_request: net::InitRequestBuf,
+}
+
+struct AggregatedHost {
+	host: RemoteHost,
+	client: Option<net::Client>,
+}
+
+impl fmt::Debug for DeviceAggregator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let hosts: Vec<_> = self.hosts.iter().map(|h| &h.host).collect();
+		f.debug_struct("DeviceAggregator")
+			.field("hosts", &hosts)
+			.field("connect_timeout", &self.connect_timeout)
+			.finish()
+	}
+}
+
+impl DeviceAggregator {
+	pub fn new() -> DeviceAggregator {
+		DeviceAggregator {
+			hosts: Vec::new(),
+			connect_timeout: None,
+			init_request: net::InitRequestBuf::new(),
+		}
+	}
+
+	/// Adds `host`, unless it has already been added.
+	pub fn add_host(&mut self, host: RemoteHost) {
+		self.host_index(&host);
+	}
+
+	pub fn hosts(&self) -> impl Iterator<Item = &RemoteHost> {
+		self.hosts.iter().map(|entry| &entry.host)
+	}
+
+	pub fn set_connect_timeout(&mut self, timeout: Option<Duration>) {
+		self.connect_timeout = timeout;
+	}
+
+	/// Sets the username sent in `SANE_NET_INIT` requests.
+	pub fn set_username(&mut self, username: impl Into<CString>) {
+		self.init_request.set_username(username);
+	}
+
+	/// Returns the client for `host`, connecting to it if needed. Hosts
+	/// that haven't been added are added, so that the name of any remote
+	/// device can be opened.
+	pub fn client(
+		&mut self,
+		host: &RemoteHost,
+	) -> Result<&mut net::Client, net::ClientError> {
+		let idx = self.host_index(host);
+		self.connect(idx)
+	}
+
+	/// Sends `SANE_NET_GET_DEVICES` to each host, and returns the devices
+	/// of all hosts that replied with their names replaced by
+	/// [`RemoteDeviceName`]s.
+	///
+	/// Hosts that can't be reached, or that reply with an error, are
+	/// skipped. The connection to a host is closed if a request fails.
+	pub fn get_devices(&mut self) -> net::GetDevicesReplyBuf {
+		let mut devices = Vec::new();
+		for idx in 0..self.hosts.len() {
+			let reply = match self.connect(idx) {
+				Ok(client) => client.get_devices(),
+				Err(_) => continue,
+			};
+			let reply = match reply {
+				Ok(reply) => reply,
+				Err(_) => {
+					self.hosts[idx].client = None;
+					continue;
+				},
+			};
+			if reply.status() != Status::GOOD {
+				continue;
+			}
+			let host = &self.hosts[idx].host;
+			for device in reply.devices() {
+				let name = RemoteDeviceName::new(host.clone(), device.name());
+				let mut device = util::DeviceBuf::from(*device);
+				device.set_name(name.to_c_string());
+				devices.push(device);
+			}
+		}
+		let mut reply = net::GetDevicesReplyBuf::new();
+		reply.set_devices(devices);
+		reply
+	}
+
+	fn host_index(&mut self, host: &RemoteHost) -> usize {
+		if let Some(idx) = self.hosts.iter().position(|h| h.host == *host) {
+			return idx;
+		}
+		self.hosts.push(AggregatedHost {
+			host: host.clone(),
+			client: None,
+		});
+		self.hosts.len() - 1
+	}
+
+	fn connect(
+		&mut self,
+		idx: usize,
+	) -> Result<&mut net::Client, net::ClientError> {
+		let entry = &mut self.hosts[idx];
+		let client = match entry.client.take() {
+			Some(client) => client,
+			None => {
+				let stream = connect(&entry.host, self.connect_timeout)?;
+				let mut client = net::Client::new(stream);
+				let reply = client.init(&self.init_request)?;
+				if reply.status() != Status::GOOD {
+					return Err(net::ClientError::Io(std::io::Error::new(
+						std::io::ErrorKind::ConnectionRefused,
+						format!("SANE_NET_INIT failed: {:?}", reply.status()),
+					)));
+				}
+				client
+			},
+		};
+		Ok(entry.client.insert(client))
+	}
+}
+
+impl Default for DeviceAggregator {
+	fn default() -> Self {
+		DeviceAggregator::new()
+	}
+}
+
+impl Drop for DeviceAggregator {
+	fn drop(&mut self) {
+		for entry in self.hosts.drain(..) {
+			if let Some(client) = entry.client {
+				let _ = client.exit();
+			}
+		}
+	}
+}
+
+fn connect(
+	host: &RemoteHost,
+	timeout: Option<Duration>,
+) -> std::io::Result<TcpStream> {
+	let timeout = match timeout {
+		Some(timeout) => timeout,
+		None => return TcpStream::connect(host),
+	};
+	let mut last_err = None;
+	for addr in host.to_socket_addrs()? {
+		match TcpStream::connect_timeout(&addr, timeout) {
+			Ok(stream) => return Ok(stream),
+			Err(err) => last_err = Some(err),
+		}
+	}
+	Err(last_err.unwrap_or_else(|| {
+		std::io::Error::new(
+			std::io::ErrorKind::NotFound,
+			format!("no addresses found for {}", host),
+		)
+	}))
+}
+
+// }}}
@@ -57,6 +57,14 @@ impl InitRequest {
 }
 
 impl<'a> InitRequestInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, InitRequest> {
+		let ptr: *const InitRequestInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(InitRequest { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("version_code", &self.version_code)
@@ -83,6 +91,20 @@ impl io::Encode for InitRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for InitRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let version_code = Word::decode(r)?.as_u32();
+		let username = r.read_cstr()?.unwrap_or(crate::util::CSTR_EMPTY);
+
+		let inner = InitRequestInner { version_code, username };
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // InitRequestBuf {{{
@@ -239,6 +261,20 @@ impl io::Encode for InitReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for InitReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let status = Status::decode(r)?;
+		let version_code = Word::decode(r)?.as_u32();
+
+		let reply = InitReply { status, version_code };
+		Ok(io::Borrowed::owned(reply))
+	}
+}
+
 // }}}
 
 // InitReplyBuf {{{
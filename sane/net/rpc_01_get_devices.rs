@@ -47,6 +47,14 @@ impl io::Encode for GetDevicesRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for GetDevicesRequest {
+	fn decode_ref(
+		_r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		Ok(io::Borrowed::owned(GetDevicesRequest { _p: () }))
+	}
+}
+
 // }}}
 
 // GetDevicesRequestBuf {{{
@@ -328,7 +336,7 @@ impl io::Decode for GetDevicesReplyBuf {
 	) -> Result<Self, io::DecodeError<R::Error>> {
 		let status = Status::decode(r)?;
 		let devices_len = r.read_size()?;
-		let mut devices = Vec::with_capacity(devices_len);
+		let mut devices = io::try_vec_for_len(devices_len)?;
 		for _ii in 0..devices_len {
 			let is_null = Bool::decode(r)?;
 			if is_null == Bool::TRUE {
@@ -52,6 +52,14 @@ impl OpenRequest {
 }
 
 impl<'a> OpenRequestInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, OpenRequest> {
+		let ptr: *const OpenRequestInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(OpenRequest { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("device_name", &self.device_name)
@@ -76,6 +84,16 @@ impl io::Encode for OpenRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for OpenRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		let device_name = r.read_cstr()?.unwrap_or(crate::util::CSTR_EMPTY);
+
+		Ok(OpenRequestInner { device_name }.into_borrowed())
+	}
+}
+
 // }}}
 
 // OpenRequestBuf {{{
@@ -230,6 +248,14 @@ impl OpenReply {
 }
 
 impl<'a> OpenReplyInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, OpenReply> {
+		let ptr: *const OpenReplyInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(OpenReply { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("status", &self.status)
@@ -258,6 +284,21 @@ impl io::Encode for OpenReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for OpenReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let status = Status::decode(r)?;
+		let handle = net::Handle::decode(r)?;
+		let resource = r.read_cstr()?;
+
+		let inner = OpenReplyInner { status, handle, resource };
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // OpenReplyBuf {{{
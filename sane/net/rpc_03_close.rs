@@ -41,6 +41,17 @@ impl io::Encode for CloseRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for CloseRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		Ok(io::Borrowed::owned(CloseRequest { handle }))
+	}
+}
+
 // }}}
 
 // CloseRequestBuf {{{
@@ -173,6 +184,17 @@ impl io::Encode for CloseReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for CloseReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let _dummy = Word::decode(r)?;
+		Ok(io::Borrowed::owned(CloseReply { _p: () }))
+	}
+}
+
 // }}}
 
 // CloseReplyBuf {{{
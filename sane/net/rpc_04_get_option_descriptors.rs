@@ -57,6 +57,17 @@ impl io::Encode for GetOptionDescriptorsRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for GetOptionDescriptorsRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		Ok(io::Borrowed::owned(GetOptionDescriptorsRequest { handle }))
+	}
+}
+
 // }}}
 
 // GetOptionDescriptorsRequestBuf {{{
@@ -348,7 +359,8 @@ impl io::Decode for GetOptionDescriptorsReplyBuf {
 		r: &mut io::Reader<R>,
 	) -> Result<Self, io::DecodeError<R::Error>> {
 		let opt_descs_len = r.read_size()?;
-		let mut opt_descs = Vec::with_capacity(opt_descs_len);
+		r.check_limit(io::Limit::OptionCount, opt_descs_len)?;
+		let mut opt_descs = io::try_vec_for_len(opt_descs_len)?;
 		for _ii in 0..opt_descs_len {
 			let is_null = Bool::decode(r)?;
 			if is_null == Bool::TRUE {
@@ -486,7 +498,9 @@ impl io::Decode for util::OptionDescriptorBuf {
 		match opt_type {
 			ValueType::BOOL => {
 				expect_constraint_none(r)?;
-				assert_eq!(size, 4); // FIXME: return error, not assert
+				if size != 4 {
+					return Err(invalid_size(ValueType::BOOL, size));
+				}
 				Ok(util::BoolOptionBuilder::new(name)
 					.title(title)
 					.description(desc)
@@ -541,6 +555,14 @@ impl io::Decode for util::OptionDescriptorBuf {
 	}
 }
 
+#[cfg(any(doc, feature = "alloc"))]
+fn invalid_size<E>(value_type: ValueType, size: usize) -> io::DecodeError<E> {
+	// `size` was decoded from a `SANE_Word`, so it fits in a `u32`.
+	io::DecodeError {
+		kind: io::DecodeErrorKind::InvalidValueSize(value_type, size as u32),
+	}
+}
+
 #[cfg(any(doc, feature = "alloc"))]
 fn expect_constraint_none<R: io::Read>(
 	r: &mut io::Reader<R>,
@@ -567,9 +589,10 @@ fn read_int_option<R: io::Read>(
 ) -> Result<util::OptionDescriptorBuf, io::DecodeError<R::Error>> {
 	use io::Decode;
 
-	assert_eq!(size % 4, 0); // FIXME: return error, not assert
-	assert!(size >= 4); // FIXME: return error, not assert
 	let count = size / 4;
+	if count == 0 || count * 4 != size {
+		return Err(invalid_size(ValueType::INT, size));
+	}
 	builder = builder.count(count);
 
 	let constraint_type = ConstraintType::decode(r)?;
@@ -612,9 +635,10 @@ fn read_fixed_option<R: io::Read>(
 ) -> Result<util::OptionDescriptorBuf, io::DecodeError<R::Error>> {
 	use io::Decode;
 
-	assert_eq!(size % 4, 0); // FIXME: return error, not assert
-	assert!(size >= 4); // FIXME: return error, not assert
 	let count = size / 4;
+	if count == 0 || count * 4 != size {
+		return Err(invalid_size(ValueType::FIXED, size));
+	}
 	builder = builder.count(count);
 
 	let constraint_type = ConstraintType::decode(r)?;
@@ -680,19 +704,13 @@ fn new_vec_for_array<R: io::Read, T>(
 	r: &mut io::Reader<R>,
 ) -> Result<(usize, Vec<T>), io::DecodeError<R::Error>> {
 	let len = r.read_size()?;
+	r.check_limit(io::Limit::WordListLen, len)?;
 	if len == 0 {
 		// FIXME: return an error, as this is a protocol violation
 		return Ok((len, Vec::new()));
 	}
 
-	let mut vec = Vec::new();
-	if let Err(_) = vec.try_reserve(len-1) {
-		return Err(io::DecodeError {
-			kind: io::DecodeErrorKind::TryReserveError(len),
-		});
-	}
-
-	Ok((len, vec))
+	Ok((len, io::try_vec_for_len(len - 1)?))
 }
 
 #[cfg(any(doc, feature = "alloc"))]
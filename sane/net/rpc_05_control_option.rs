@@ -20,6 +20,7 @@ use alloc::ffi::CString;
 #[cfg(any(doc, feature = "alloc"))]
 use alloc::vec::Vec;
 
+use core::convert::TryFrom;
 use core::ffi::CStr;
 use core::fmt;
 
@@ -85,6 +86,14 @@ impl ControlOptionRequest {
 }
 
 impl<'a> ControlOptionRequestInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, ControlOptionRequest> {
+		let ptr: *const ControlOptionRequestInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(ControlOptionRequest { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("handle", &self.handle)
@@ -119,6 +128,34 @@ impl io::Encode for ControlOptionRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for ControlOptionRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		let option = Word::decode(r)?.as_u32();
+		let action = Action::decode(r)?;
+		let mut value = OptionValue {
+			value_type: ValueType::BOOL,
+			bytes: &[],
+		};
+		if action != Action::SET_AUTO {
+			value = OptionValue::decode_ref(r)?;
+		}
+
+		let inner = ControlOptionRequestInner {
+			handle,
+			option,
+			action,
+			value_type: value.value_type,
+			value: value.bytes,
+		};
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // ControlOptionRequestBuf {{{
@@ -306,6 +343,14 @@ impl ControlOptionReply {
 }
 
 impl<'a> ControlOptionReplyInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, ControlOptionReply> {
+		let ptr: *const ControlOptionReplyInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(ControlOptionReply { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("status", &self.status)
@@ -337,6 +382,28 @@ impl io::Encode for ControlOptionReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for ControlOptionReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let status = Status::decode(r)?;
+		let info = Word::decode(r)?.as_u32();
+		let value = OptionValue::decode_ref(r)?;
+		let resource = r.read_cstr()?;
+
+		let inner = ControlOptionReplyInner {
+			status,
+			info,
+			value_type: value.value_type,
+			value: value.bytes,
+			resource,
+		};
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // ControlOptionReplyBuf {{{
@@ -594,6 +661,72 @@ impl<'a> OptionValue<'a> {
 		Ok(CString::from(self.to_cstr()?))
 	}
 
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<Self, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+		use ValueType as T;
+
+		let value_type = T::decode(r)?;
+		let bytes = match value_type {
+			T::BOOL => {
+				let value_size = Word::decode(r)?.as_u32();
+				let value_count = Word::decode(r)?.as_u32();
+				if value_size != 4 {
+					return Err(invalid_size(value_type, value_size));
+				}
+				if value_count != 1 {
+					return Err(invalid_count(value_type, value_count));
+				}
+
+				let bytes = r.read_slice(4)?;
+				let (word_bytes, _) = util::split_array_ref(bytes);
+				let word = Word::new(u32::from_be_bytes(*word_bytes));
+				if word.as_u32() > 1 {
+					return Err(io::DecodeError {
+						kind: io::DecodeErrorKind::InvalidBool(word),
+					});
+				}
+				bytes
+			},
+			T::INT | T::FIXED => {
+				let value_size = Word::decode(r)?.as_u32();
+				let value_count = Word::decode(r)?.as_u32();
+				let count = value_size / 4;
+				if count * 4 != value_size {
+					return Err(invalid_size(value_type, value_size));
+				}
+				if value_count != count {
+					return Err(invalid_count(value_type, value_count));
+				}
+				r.check_limit(io::Limit::WordListLen, count as usize)?;
+
+				r.read_slice(value_size as usize)?
+			},
+			T::STRING => {
+				let bytes_len = r.read_string_size()?;
+				let bytes = r.read_slice(bytes_len)?;
+				if bytes_len != 0 && !bytes.contains(&0) {
+					return Err(io::DecodeError {
+						kind: io::DecodeErrorKind::InvalidString,
+					});
+				}
+				bytes
+			},
+			T::BUTTON => {
+				let value_size = Word::decode(r)?.as_u32();
+				if value_size != 0 {
+					return Err(invalid_size(value_type, value_size));
+				}
+				&[]
+			},
+			_ => return Err(io::DecodeError {
+				kind: io::DecodeErrorKind::InvalidOptionType,
+			}),
+		};
+		Ok(OptionValue { value_type, bytes })
+	}
+
 	fn encode<W: io::Write>(
 		&self,
 		w: &mut io::Writer<W>,
@@ -601,22 +734,43 @@ impl<'a> OptionValue<'a> {
 		use io::Encode;
 		use ValueType as T;
 
+		let len = self.bytes.len();
+		let invalid_size = || io::EncodeError {
+			kind: io::EncodeErrorKind::InvalidValueSize(self.value_type, len),
+		};
+		let len_u32 = match u32::try_from(len) {
+			Ok(len_u32) => len_u32,
+			Err(_) => {
+				return Err(io::EncodeError {
+					kind: io::EncodeErrorKind::SizeOverflow(len),
+				});
+			},
+		};
+
 		let mut value_size: Option<u32> = None;
 		let mut value_count: Option<u32> = None;
 		match self.value_type {
 			T::BOOL => {
-				assert_eq!(self.bytes.len(), 4);
+				if len != 4 {
+					return Err(invalid_size());
+				}
 				value_size = Some(4);
 				value_count = Some(1);
 			},
 			T::INT | T::FIXED => {
-				assert_eq!(self.bytes.len() % 4, 0);
-				value_size = Some(self.bytes.len() as u32);
-				value_count = Some((self.bytes.len() / 4) as u32);
+				let count = len_u32 / 4;
+				if count * 4 != len_u32 {
+					return Err(invalid_size());
+				}
+				value_size = Some(len_u32);
+				value_count = Some(count);
 			},
 			T::STRING => {
-				assert!(self.bytes.len() > 0);
-				value_size = Some(self.bytes.len() as u32);
+				// Strings are sent with their NUL terminator.
+				if len == 0 {
+					return Err(invalid_size());
+				}
+				value_size = Some(len_u32);
 			},
 			T::BUTTON => {},
 			_ => return Err(io::EncodeError {
@@ -631,8 +785,12 @@ impl<'a> OptionValue<'a> {
 		if let Some(value_count) = value_count {
 			Word::new(value_count).encode(w)?;
 		}
-		if self.bytes.len() > 0 {
-			w.write_bytes(self.bytes)?;
+		if !self.bytes.is_empty() {
+			if self.value_type == T::STRING {
+				w.write_bytes(self.bytes)?;
+			} else {
+				w.write_word_bytes(self.bytes)?;
+			}
 		}
 		Ok(())
 	}
@@ -655,6 +813,13 @@ impl OptionValueBuf {
 		&self.bytes
 	}
 
+	pub fn as_option_value(&self) -> OptionValue<'_> {
+		OptionValue {
+			value_type: self.value_type,
+			bytes: &self.bytes,
+		}
+	}
+
 	pub fn from_bool(value: bool) -> OptionValueBuf {
 		let value = Bool::new(value).as_word().as_u32();
 		OptionValueBuf {
@@ -734,10 +899,12 @@ impl OptionValueBuf {
 			T::BOOL => {
 				let value_size = Word::decode(r)?.as_u32();
 				let value_count = Word::decode(r)?.as_u32();
-
-				// FIXME: decode error instead of assert
-				assert_eq!(value_size, 4);
-				assert_eq!(value_count, 1);
+				if value_size != 4 {
+					return Err(invalid_size(value_type, value_size));
+				}
+				if value_count != 1 {
+					return Err(invalid_count(value_type, value_count));
+				}
 
 				let value = Bool::decode(r)?;
 				Ok(Self::from_bool(value == Bool::TRUE))
@@ -745,15 +912,20 @@ impl OptionValueBuf {
 			T::INT | T::FIXED => {
 				let value_size = Word::decode(r)?.as_u32();
 				let value_count = Word::decode(r)?.as_u32();
+				let count = value_size / 4;
+				if count * 4 != value_size {
+					return Err(invalid_size(value_type, value_size));
+				}
+				if value_count != count {
+					return Err(invalid_count(value_type, value_count));
+				}
+				r.check_limit(io::Limit::WordListLen, count as usize)?;
 
-				// FIXME: decode error instead of assert
-				assert_eq!(value_size, value_count * 4);
-
-				let bytes = r.read_vec(value_size as usize)?;
+				let bytes = r.read_word_bytes(value_size as usize)?;
 				Ok(Self { value_type, bytes })
 			},
 			T::STRING => {
-				let bytes_len = r.read_size()?;
+				let bytes_len = r.read_string_size()?;
 				let bytes = r.read_vec(bytes_len)?;
 				if bytes_len == 0 {
 					return Ok(Self { value_type, bytes });
@@ -767,8 +939,9 @@ impl OptionValueBuf {
 			},
 			T::BUTTON => {
 				let value_size = Word::decode(r)?.as_u32();
-				// FIXME: decode error instead of assert
-				assert_eq!(value_size, 0);
+				if value_size != 0 {
+					return Err(invalid_size(value_type, value_size));
+				}
 				Ok(Self { value_type, bytes: Vec::new() })
 			},
 			_ => Err(io::DecodeError {
@@ -778,6 +951,18 @@ impl OptionValueBuf {
 	}
 }
 
+fn invalid_size<E>(value_type: ValueType, size: u32) -> io::DecodeError<E> {
+	io::DecodeError {
+		kind: io::DecodeErrorKind::InvalidValueSize(value_type, size),
+	}
+}
+
+fn invalid_count<E>(value_type: ValueType, count: u32) -> io::DecodeError<E> {
+	io::DecodeError {
+		kind: io::DecodeErrorKind::InvalidValueCount(value_type, count),
+	}
+}
+
 #[cfg(any(doc, feature = "alloc"))]
 impl From<OptionValue<'_>> for OptionValueBuf {
 	fn from(value: OptionValue) -> Self {
@@ -42,6 +42,17 @@ impl io::Encode for GetParametersRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for GetParametersRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		Ok(io::Borrowed::owned(GetParametersRequest { handle }))
+	}
+}
+
 // }}}
 
 // GetParametersRequestBuf {{{
@@ -180,6 +191,20 @@ impl io::Encode for GetParametersReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for GetParametersReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let status = Status::decode(r)?;
+		let parameters = Parameters::decode(r)?;
+
+		let reply = GetParametersReply { status, parameters };
+		Ok(io::Borrowed::owned(reply))
+	}
+}
+
 // }}}
 
 // GetParametersReplyBuf {{{
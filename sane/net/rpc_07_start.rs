@@ -47,6 +47,17 @@ impl io::Encode for StartRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for StartRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		Ok(io::Borrowed::owned(StartRequest { handle }))
+	}
+}
+
 // }}}
 
 // StartRequestBuf {{{
@@ -197,6 +208,14 @@ impl StartReply {
 }
 
 impl<'a> StartReplyInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, StartReply> {
+		let ptr: *const StartReplyInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(StartReply { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("status", &self.status)
@@ -227,6 +246,29 @@ impl io::Encode for StartReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for StartReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let status = Status::decode(r)?;
+		let port = Word::decode(r)?.as_u32();
+		let byte_order = net::ByteOrder::decode(r)?;
+		let resource = r.read_cstr()?;
+
+		// FIXME: error if port > u16::MAX
+
+		let inner = StartReplyInner {
+			status,
+			port: port as u16,
+			byte_order,
+			resource,
+		};
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // StartReplyBuf {{{
@@ -41,6 +41,17 @@ impl io::Encode for CancelRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for CancelRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let handle = net::Handle::decode(r)?;
+		Ok(io::Borrowed::owned(CancelRequest { handle }))
+	}
+}
+
 // }}}
 
 // CancelRequestBuf {{{
@@ -173,6 +184,17 @@ impl io::Encode for CancelReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for CancelReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let _dummy = Word::decode(r)?;
+		Ok(io::Borrowed::owned(CancelReply { _p: () }))
+	}
+}
+
 // }}}
 
 // CancelReplyBuf {{{
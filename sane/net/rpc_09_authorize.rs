@@ -61,6 +61,14 @@ impl AuthorizeRequest {
 }
 
 impl<'a> AuthorizeRequestInner<'a> {
+	fn into_borrowed(self) -> io::Borrowed<'a, AuthorizeRequest> {
+		let ptr: *const AuthorizeRequestInner = &self;
+		unsafe {
+			let inner = *(ptr.cast());
+			io::Borrowed::new(AuthorizeRequest { inner })
+		}
+	}
+
 	fn fmt(&self, f: &mut fmt::Formatter, struct_name: &str) -> fmt::Result {
 		f.debug_struct(struct_name)
 			.field("resource", &self.resource)
@@ -89,6 +97,23 @@ impl io::Encode for AuthorizeRequest {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for AuthorizeRequest {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		let resource = r.read_cstr()?.unwrap_or(crate::util::CSTR_EMPTY);
+		let username = r.read_cstr()?.unwrap_or(crate::util::CSTR_EMPTY);
+		let password = r.read_cstr()?.unwrap_or(crate::util::CSTR_EMPTY);
+
+		let inner = AuthorizeRequestInner {
+			resource,
+			username,
+			password,
+		};
+		Ok(inner.into_borrowed())
+	}
+}
+
 // }}}
 
 // AuthorizeRequestBuf {{{
@@ -262,6 +287,17 @@ impl io::Encode for AuthorizeReply {
 	}
 }
 
+impl<'a> io::DecodeRef<'a> for AuthorizeReply {
+	fn decode_ref(
+		r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		use io::Decode;
+
+		let _dummy = Word::decode(r)?;
+		Ok(io::Borrowed::owned(AuthorizeReply { _p: () }))
+	}
+}
+
 // }}}
 
 // AuthorizeReplyBuf {{{
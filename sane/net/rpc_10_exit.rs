@@ -0,0 +1,138 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::fmt;
+
+use crate::net::io;
+
+// ExitRequest {{{
+
+#[derive(Eq, PartialEq)]
+pub struct ExitRequest {
+	_p: (),
+}
+
+impl fmt::Debug for ExitRequest {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ExitRequest").finish()
+	}
+}
+
+impl io::Encode for ExitRequest {
+	fn encode<W: io::Write>(
+		&self,
+		_w: &mut io::Writer<W>,
+	) -> Result<(), io::EncodeError<W::Error>> {
+		Ok(())
+	}
+}
+
+impl<'a> io::DecodeRef<'a> for ExitRequest {
+	fn decode_ref(
+		_r: &mut io::Reader<io::SliceReader<'a>>,
+	) -> Result<io::Borrowed<'a, Self>, io::DecodeError<io::Incomplete>> {
+		Ok(io::Borrowed::owned(ExitRequest { _p: () }))
+	}
+}
+
+// }}}
+
+// ExitRequestBuf {{{
+
+#[cfg(any(doc, feature = "alloc"))]
+#[derive(Eq, PartialEq)]
+pub struct ExitRequestBuf {
+	inner: ExitRequest,
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl ExitRequestBuf {
+	pub fn new() -> ExitRequestBuf {
+		ExitRequestBuf {
+			inner: ExitRequest { _p: () },
+		}
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl AsRef<ExitRequest> for ExitRequestBuf {
+	fn as_ref(&self) -> &ExitRequest {
+		&self.inner
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl Clone for ExitRequestBuf {
+	fn clone(&self) -> Self {
+		ExitRequestBuf::new()
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl fmt::Debug for ExitRequestBuf {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("ExitRequestBuf").finish()
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl core::ops::Deref for ExitRequestBuf {
+	type Target = ExitRequest;
+	fn deref(&self) -> &ExitRequest {
+		&self.inner
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl PartialEq<ExitRequest> for ExitRequestBuf {
+	fn eq(&self, _other: &ExitRequest) -> bool {
+		true
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl PartialEq<ExitRequestBuf> for ExitRequest {
+	fn eq(&self, _other: &ExitRequestBuf) -> bool {
+		true
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl From<&ExitRequest> for ExitRequestBuf {
+	fn from(_request: &ExitRequest) -> Self {
+		ExitRequestBuf::new()
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl io::Encode for ExitRequestBuf {
+	fn encode<W: io::Write>(
+		&self,
+		w: &mut io::Writer<W>,
+	) -> Result<(), io::EncodeError<W::Error>> {
+		self.as_ref().encode(w)
+	}
+}
+
+#[cfg(any(doc, feature = "alloc"))]
+impl io::Decode for ExitRequestBuf {
+	fn decode<R: io::Read>(
+		_r: &mut io::Reader<R>,
+	) -> Result<Self, io::DecodeError<R::Error>> {
+		Ok(ExitRequestBuf::new())
+	}
+}
+
+// }}}
@@ -0,0 +1,717 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use core::ffi::CStr;
+use core::fmt;
+
+use std::ffi::CString;
+use std::io::{BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use crate::{Action, Parameters, Status};
+use crate::net;
+use crate::net::data::is_timeout;
+use crate::net::io::{self, Decode, Encode};
+use crate::util;
+
+// Backend {{{
+
+/// Device operations exposed to clients by a [`Server`].
+///
+/// Each connection has its own table of open devices. Devices that are
+/// still open when the connection ends are passed to [`Backend::close`].
+pub trait Backend {
+	type Device;
+
+	/// `sane_get_devices`
+	fn get_devices(&mut self) -> Result<Vec<util::DeviceBuf>, Status>;
+
+	/// `sane_open`
+	fn open(&mut self, device_name: &CStr) -> Result<Self::Device, Status>;
+
+	/// Returns the resource that a client must be authorized for before
+	/// opening `device_name`, or `None` if no authorization is required.
+	///
+	/// Clients are challenged to send their password as an MD5 digest.
+	fn auth_resource(&mut self, device_name: &CStr) -> Option<CString> {
+		let _ = device_name;
+		None
+	}
+
+	/// Returns the password that `username` must provide to access
+	/// `resource`, or `None` if the user may not access it.
+	fn password(
+		&mut self,
+		resource: &CStr,
+		username: &CStr,
+	) -> Option<CString> {
+		let _ = (resource, username);
+		None
+	}
+
+	/// `sane_close`
+	fn close(&mut self, device: Self::Device);
+
+	/// `sane_get_option_descriptor`
+	fn get_option_descriptors(
+		&mut self,
+		device: &mut Self::Device,
+	) -> Vec<util::OptionDescriptorBuf>;
+
+	/// `sane_control_option`
+	///
+	/// The current value is replaced by the option's value after the action
+	/// has been performed. On success, returns the `SANE_INFO_*` flags.
+	fn control_option(
+		&mut self,
+		device: &mut Self::Device,
+		option: u32,
+		action: Action,
+		value: &mut net::OptionValueBuf,
+	) -> Result<u32, Status>;
+
+	/// `sane_get_parameters`
+	fn get_parameters(
+		&mut self,
+		device: &mut Self::Device,
+	) -> Result<Parameters, Status>;
+
+	/// `sane_start`
+	fn start(&mut self, device: &mut Self::Device) -> Result<(), Status>;
+
+	/// `sane_read`
+	///
+	/// Returns `Err(Status::EOF)` when there is no more image data.
+	fn read(
+		&mut self,
+		device: &mut Self::Device,
+		buf: &mut [u8],
+	) -> Result<usize, Status>;
+
+	/// `sane_cancel`
+	///
+	/// If a scan is in progress, subsequent calls to [`Backend::read`] should
+	/// return `Err(Status::CANCELLED)`.
+	fn cancel(&mut self, device: &mut Self::Device);
+}
+
+// }}}
+
+// Server {{{
+
+// Delay before accepting again after `accept()` fails, so that errors such
+// as `EMFILE` don't cause a busy loop.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+const DATA_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A `saned`-compatible server for a [`Backend`].
+///
+/// Connections are served one at a time, on the calling thread. While a
+/// client has its control connection open, other clients wait in the
+/// listener's backlog, so callers that expect concurrent clients should run
+/// a server per connection.
+pub struct Server<B> {
+	backend: B,
+	codec: io::Codec,
+	data_timeout: Duration,
+	data_ports: Option<RangeInclusive<u16>>,
+}
+
+impl<B> fmt::Debug for Server<B> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Server").finish()
+	}
+}
+
+impl<B: Backend> Server<B> {
+	pub fn new(backend: B) -> Server<B> {
+		Server {
+			backend,
+			codec: io::Codec::BINARY_V3,
+			data_timeout: DATA_ACCEPT_TIMEOUT,
+			data_ports: None,
+		}
+	}
+
+	pub fn backend(&self) -> &B {
+		&self.backend
+	}
+
+	pub fn backend_mut(&mut self) -> &mut B {
+		&mut self.backend
+	}
+
+	pub fn into_backend(self) -> B {
+		self.backend
+	}
+
+	/// Sets the limits used when decoding requests.
+	pub fn set_limits(&mut self, limits: io::Limits) {
+		self.codec = self.codec.with_limits(limits);
+	}
+
+	/// Sets how long to wait for the client to open the data connection
+	/// after a successful `SANE_NET_START`. If it doesn't, the scan is
+	/// cancelled.
+	pub fn set_data_timeout(&mut self, timeout: Duration) {
+		self.data_timeout = timeout;
+	}
+
+	/// Sets the range of ports that data connections listen on, such as the
+	/// `data_portrange` option of `saned.conf`. By default, any free port is
+	/// used.
+	pub fn set_data_port_range(&mut self, ports: Option<RangeInclusive<u16>>) {
+		self.data_ports = ports;
+	}
+
+	/// Serves connections accepted from `listener`, one at a time.
+	///
+	/// Errors from accepting or serving an individual connection are not
+	/// returned; the connection is dropped and the server moves on to the
+	/// next one.
+	pub fn serve(&mut self, listener: &TcpListener) -> std::io::Result<()> {
+		for stream in listener.incoming() {
+			match stream {
+				Ok(stream) => {
+					let _ = self.serve_connection(stream);
+				},
+				Err(_) => std::thread::sleep(ACCEPT_RETRY_DELAY),
+			}
+		}
+		Ok(())
+	}
+
+	/// Serves a single connection until the client sends `SANE_NET_EXIT` or
+	/// disconnects.
+	pub fn serve_connection(
+		&mut self,
+		stream: TcpStream,
+	) -> Result<(), ServerError> {
+		let mut conn = Connection {
+			backend: &mut self.backend,
+			codec: &self.codec,
+			data_timeout: self.data_timeout,
+			data_ports: self.data_ports.clone(),
+			stream: BufReader::new(stream),
+			handles: Vec::new(),
+			scan: None,
+		};
+		let result = conn.run();
+		for device in conn.handles.drain(..).flatten() {
+			conn.backend.close(device);
+		}
+		result
+	}
+}
+
+// }}}
+
+// Connection {{{
+
+struct Connection<'a, B: Backend> {
+	backend: &'a mut B,
+	codec: &'a io::Codec,
+	data_timeout: Duration,
+	data_ports: Option<RangeInclusive<u16>>,
+	stream: BufReader<TcpStream>,
+	handles: Vec<Option<B::Device>>,
+	scan: Option<Scan>,
+}
+
+struct Scan {
+	idx: usize,
+	writer: net::DataWriter<TcpStream>,
+	finished: bool,
+}
+
+// How long to wait for the client to accept image data before checking the
+// control connection for new requests.
+const DATA_WRITE_TIMEOUT: Duration = Duration::from_millis(50);
+
+// How long to wait for a request on the control connection when the backend
+// has no image data ready, before reading from it again.
+const IDLE_READ_DELAY: Duration = Duration::from_millis(10);
+
+impl<B: Backend> Connection<'_, B> {
+	fn run(&mut self) -> Result<(), ServerError> {
+		use net::Request as R;
+
+		let request = match self.recv_request()? {
+			None => return Ok(()),
+			Some(R::Init(request)) => request,
+			Some(request) => {
+				let procedure = request.procedure_number();
+				return Err(ServerError::InvalidProcedure(procedure));
+			},
+		};
+		let mut reply = net::InitReplyBuf::new();
+		if !version_compatible(request.version_code()) {
+			reply.set_status(Status::INVAL);
+			return self.send(&reply);
+		}
+		self.send(&reply)?;
+
+		loop {
+			// While a scan is in progress, image data is sent to the client
+			// until the control connection has a request to handle.
+			if self.scan.is_some() && !self.control_ready()? {
+				if !self.send_image_data() {
+					self.wait_control(IDLE_READ_DELAY)?;
+				}
+				continue;
+			}
+			let request = match self.recv_request()? {
+				None => return Ok(()),
+				Some(request) => request,
+			};
+			match request {
+				// `SANE_NET_AUTHORIZE` is only valid in reply to a challenge.
+				R::Init(_) | R::Authorize(_) => {
+					let procedure = request.procedure_number();
+					return Err(ServerError::InvalidProcedure(procedure));
+				},
+				R::GetDevices(_) => {
+					let reply = self.get_devices();
+					self.send(&reply)?;
+				},
+				R::Open(request) => {
+					let reply = self.open(&request)?;
+					self.send(&reply)?;
+				},
+				R::Close(request) => {
+					let reply = self.close(&request);
+					self.send(&reply)?;
+				},
+				R::GetOptionDescriptors(request) => {
+					let reply = self.get_option_descriptors(&request);
+					self.send(&reply)?;
+				},
+				R::ControlOption(request) => {
+					let reply = self.control_option(&request);
+					self.send(&reply)?;
+				},
+				R::GetParameters(request) => {
+					let reply = self.get_parameters(&request);
+					self.send(&reply)?;
+				},
+				R::Start(request) => {
+					self.start(&request)?;
+				},
+				R::Cancel(request) => {
+					let reply = self.cancel(&request);
+					self.send(&reply)?;
+				},
+				R::Exit(_) => return Ok(()),
+			}
+		}
+	}
+
+	fn get_devices(&mut self) -> net::GetDevicesReplyBuf {
+		let mut reply = net::GetDevicesReplyBuf::new();
+		match self.backend.get_devices() {
+			Ok(devices) => reply.set_devices(devices),
+			Err(status) => reply.set_status(status),
+		}
+		reply
+	}
+
+	fn open(
+		&mut self,
+		request: &net::OpenRequest,
+	) -> Result<net::OpenReplyBuf, ServerError> {
+		let mut reply = net::OpenReplyBuf::new();
+		let device_name = request.device_name();
+		if let Some(resource) = self.backend.auth_resource(device_name) {
+			if !self.authorize_open(resource)? {
+				reply.set_status(Status::ACCESS_DENIED);
+				return Ok(reply);
+			}
+		}
+		let device = match self.backend.open(device_name) {
+			Ok(device) => device,
+			Err(status) => {
+				reply.set_status(status);
+				return Ok(reply);
+			},
+		};
+		let idx = match self.handles.iter().position(Option::is_none) {
+			Some(idx) => {
+				self.handles[idx] = Some(device);
+				idx
+			},
+			None => {
+				self.handles.push(Some(device));
+				self.handles.len() - 1
+			},
+		};
+		reply.set_handle(net::Handle(idx as u32));
+		Ok(reply)
+	}
+
+	// The challenge is sent in place of the `SANE_NET_OPEN` reply. The client
+	// responds with `SANE_NET_AUTHORIZE`, and then waits for the real reply.
+	fn authorize_open(
+		&mut self,
+		resource: CString,
+	) -> Result<bool, ServerError> {
+		let salt = md5_salt();
+		let mut challenge = resource.clone().into_bytes();
+		challenge.extend_from_slice(b"$MD5$");
+		challenge.extend_from_slice(salt.as_bytes());
+		let challenge = CString::new(challenge).unwrap();
+
+		let mut reply = net::OpenReplyBuf::new();
+		reply.set_resource(challenge.as_c_str());
+		self.send(&reply)?;
+
+		let request = match self.recv_request()? {
+			Some(net::Request::Authorize(request)) => request,
+			Some(request) => {
+				let procedure = request.procedure_number();
+				return Err(ServerError::InvalidProcedure(procedure));
+			},
+			None => {
+				let err = std::io::ErrorKind::UnexpectedEof;
+				return Err(ServerError::Io(err.into()));
+			},
+		};
+		self.send(&net::AuthorizeReplyBuf::new())?;
+
+		if request.resource() != challenge.as_c_str() {
+			return Ok(false);
+		}
+		let password = self.backend.password(&resource, request.username());
+		Ok(match password {
+			Some(password) => net::verify_md5_password(
+				salt.as_bytes(),
+				password.as_bytes(),
+				request.password(),
+			),
+			None => false,
+		})
+	}
+
+	fn close(&mut self, request: &net::CloseRequest) -> net::CloseReplyBuf {
+		let idx = request.handle().0 as usize;
+		if self.scan.as_ref().map(|scan| scan.idx) == Some(idx) {
+			self.scan = None;
+		}
+		if let Some(device) = self.handles.get_mut(idx).and_then(Option::take) {
+			self.backend.close(device);
+		}
+		net::CloseReplyBuf::new()
+	}
+
+	fn get_option_descriptors(
+		&mut self,
+		request: &net::GetOptionDescriptorsRequest,
+	) -> net::GetOptionDescriptorsReplyBuf {
+		let mut reply = net::GetOptionDescriptorsReplyBuf::new();
+		let idx = request.handle().0 as usize;
+		if let Some(device) = self.handles.get_mut(idx).and_then(Option::as_mut)
+		{
+			let descriptors = self.backend.get_option_descriptors(device);
+			reply.set_option_descriptors(descriptors);
+		}
+		reply
+	}
+
+	fn control_option(
+		&mut self,
+		request: &net::ControlOptionRequest,
+	) -> net::ControlOptionReplyBuf {
+		let mut reply = net::ControlOptionReplyBuf::new();
+		let mut value = net::OptionValueBuf::from(request.value());
+		let idx = request.handle().0 as usize;
+		let result = match self.handles.get_mut(idx).and_then(Option::as_mut) {
+			Some(device) => self.backend.control_option(
+				device,
+				request.option(),
+				request.action(),
+				&mut value,
+			),
+			None => Err(Status::INVAL),
+		};
+		match result {
+			Ok(info) => reply.set_info(info),
+			Err(status) => reply.set_status(status),
+		}
+		reply.set_value(value);
+		reply
+	}
+
+	fn get_parameters(
+		&mut self,
+		request: &net::GetParametersRequest,
+	) -> net::GetParametersReplyBuf {
+		let mut reply = net::GetParametersReplyBuf::new();
+		let idx = request.handle().0 as usize;
+		let result = match self.handles.get_mut(idx).and_then(Option::as_mut) {
+			Some(device) => self.backend.get_parameters(device),
+			None => Err(Status::INVAL),
+		};
+		match result {
+			Ok(parameters) => reply.set_parameters(parameters),
+			Err(status) => reply.set_status(status),
+		}
+		reply
+	}
+
+	fn start(
+		&mut self,
+		request: &net::StartRequest,
+	) -> Result<(), ServerError> {
+		let mut reply = net::StartReplyBuf::new();
+		if self.scan.is_some() {
+			reply.set_status(Status::DEVICE_BUSY);
+			return send(&mut self.stream, self.codec, &reply);
+		}
+		let idx = request.handle().0 as usize;
+		let device = match self.handles.get_mut(idx).and_then(Option::as_mut) {
+			Some(device) => device,
+			None => {
+				reply.set_status(Status::INVAL);
+				return send(&mut self.stream, self.codec, &reply);
+			},
+		};
+		if let Err(status) = self.backend.start(device) {
+			reply.set_status(status);
+			return send(&mut self.stream, self.codec, &reply);
+		}
+
+		let control = self.stream.get_ref();
+		let listener = match &self.data_ports {
+			Some(ports) => {
+				net::DataListener::bind_range(control, ports.clone())
+			},
+			None => net::DataListener::bind(control),
+		};
+		let listener = match listener {
+			Ok(listener) => listener,
+			Err(_) => {
+				self.backend.cancel(device);
+				reply.set_status(Status::IO_ERROR);
+				return send(&mut self.stream, self.codec, &reply);
+			},
+		};
+		reply.set_port(listener.port());
+		reply.set_byte_order(net::ByteOrder::NATIVE);
+		send(&mut self.stream, self.codec, &reply)?;
+
+		// The reply has already been sent, so if the client never connects
+		// the scan is cancelled and it sees the data port as closed.
+		let data_stream = match listener.accept_timeout(self.data_timeout) {
+			Ok(data_stream) => data_stream,
+			Err(err) => {
+				self.backend.cancel(device);
+				if err.kind() == std::io::ErrorKind::TimedOut {
+					return Ok(());
+				}
+				return Err(err.into());
+			},
+		};
+		data_stream.set_write_timeout(Some(DATA_WRITE_TIMEOUT))?;
+		self.scan = Some(Scan {
+			idx,
+			writer: net::DataWriter::new(data_stream),
+			finished: false,
+		});
+		Ok(())
+	}
+
+	// Returns `false` if the backend had no image data ready.
+	fn send_image_data(&mut self) -> bool {
+		let mut scan = match self.scan.take() {
+			Some(scan) => scan,
+			None => return true,
+		};
+		let device = match self.handles.get_mut(scan.idx) {
+			Some(Some(device)) => device,
+			_ => return true,
+		};
+
+		let mut idle = false;
+		let mut result = scan.writer.flush();
+		if result.is_ok() {
+			if scan.finished {
+				return true;
+			}
+			let mut buf = [0u8; 4096];
+			result = match self.backend.read(device, &mut buf) {
+				Ok(0) => {
+					idle = true;
+					Ok(())
+				},
+				Ok(count) => scan.writer.write_record(&buf[..count]),
+				Err(status) => {
+					scan.finished = true;
+					scan.writer.finish(status)
+				},
+			};
+		}
+
+		match result {
+			Ok(()) => {},
+			Err(err) if is_timeout(&err) => {},
+			Err(_) => {
+				// The client has closed the data connection.
+				self.backend.cancel(device);
+				return true;
+			},
+		}
+		self.scan = Some(scan);
+		!idle
+	}
+
+	fn control_ready(&self) -> std::io::Result<bool> {
+		if !self.stream.buffer().is_empty() {
+			return Ok(true);
+		}
+		let stream = self.stream.get_ref();
+		stream.set_nonblocking(true)?;
+		let result = stream.peek(&mut [0u8; 1]);
+		stream.set_nonblocking(false)?;
+		match result {
+			Ok(_) => Ok(true),
+			Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+				Ok(false)
+			},
+			Err(err) => Err(err),
+		}
+	}
+
+	fn wait_control(&self, timeout: Duration) -> std::io::Result<()> {
+		let stream = self.stream.get_ref();
+		stream.set_read_timeout(Some(timeout))?;
+		let result = stream.peek(&mut [0u8; 1]);
+		stream.set_read_timeout(None)?;
+		match result {
+			Ok(_) => Ok(()),
+			Err(err) if is_timeout(&err) => Ok(()),
+			Err(err) => Err(err),
+		}
+	}
+
+	fn cancel(&mut self, request: &net::CancelRequest) -> net::CancelReplyBuf {
+		let idx = request.handle().0 as usize;
+		if let Some(device) = self.handles.get_mut(idx).and_then(Option::as_mut)
+		{
+			self.backend.cancel(device);
+		}
+		net::CancelReplyBuf::new()
+	}
+
+	fn recv_request(&mut self) -> Result<Option<net::Request>, ServerError> {
+		let mut reader = self.codec.reader(&mut self.stream);
+		match net::Request::decode(&mut reader) {
+			Ok(request) => Ok(Some(request)),
+			Err(io::DecodeError {
+				kind: io::DecodeErrorKind::IoError(err),
+			}) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+			Err(err) => Err(ServerError::Decode(err)),
+		}
+	}
+
+	fn send<Reply: Encode>(
+		&mut self,
+		reply: &Reply,
+	) -> Result<(), ServerError> {
+		send(&mut self.stream, self.codec, reply)
+	}
+}
+
+// Salts must not be reused, so that a recorded response can't be replayed.
+fn md5_salt() -> String {
+	use std::collections::hash_map::RandomState;
+	use std::hash::{BuildHasher, Hasher};
+	use std::time::{SystemTime, UNIX_EPOCH};
+
+	let time = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |time| time.as_nanos());
+	let mut hasher = RandomState::new().build_hasher();
+	hasher.write_u128(time);
+	format!("{}{:016x}", std::process::id(), hasher.finish())
+}
+
+fn send<Reply: Encode>(
+	stream: &mut BufReader<TcpStream>,
+	codec: &io::Codec,
+	reply: &Reply,
+) -> Result<(), ServerError> {
+	let mut bytes = Vec::new();
+	let mut cursor = std::io::Cursor::new(&mut bytes);
+	let mut writer = codec.writer(&mut cursor);
+	reply.encode(&mut writer).map_err(ServerError::Encode)?;
+	stream.get_mut().write_all(&bytes).map_err(ServerError::Io)
+}
+
+fn version_compatible(version_code: u32) -> bool {
+	let major = version_code >> 24;
+	let build = version_code & 0xFFFF;
+	major == net::VERSION_CODE >> 24 && build == net::VERSION_CODE & 0xFFFF
+}
+
+// }}}
+
+// ServerError {{{
+
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum ServerError {
+	Io(std::io::Error),
+	Encode(io::EncodeError<std::io::Error>),
+	Decode(io::DecodeError<std::io::Error>),
+	InvalidProcedure(net::ProcedureNumber),
+}
+
+impl fmt::Display for ServerError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ServerError::Io(err) => write!(f, "I/O error: {}", err),
+			ServerError::Encode(err) => {
+				write!(f, "error encoding reply: {}", err)
+			},
+			ServerError::Decode(err) => {
+				write!(f, "error decoding request: {}", err)
+			},
+			ServerError::InvalidProcedure(procedure) => {
+				write!(f, "unexpected procedure {:?}", procedure)
+			},
+		}
+	}
+}
+
+impl std::error::Error for ServerError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ServerError::Io(err) => Some(err),
+			ServerError::Encode(err) => Some(err),
+			ServerError::Decode(err) => Some(err),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for ServerError {
+	fn from(err: std::io::Error) -> ServerError {
+		ServerError::Io(err)
+	}
+}
+
+// }}}
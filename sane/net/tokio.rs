@@ -0,0 +1,515 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+//! Asynchronous network protocol I/O, using [`tokio`].
+
+use core::fmt;
+
+use std::io;
+
+use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use ::tokio::net::{tcp, TcpStream, ToSocketAddrs};
+
+use crate::net;
+use crate::net::client::{AuthAttempts, AuthReply, AuthStep};
+use crate::net::io::{
+	encode_to_vec,
+	Codec,
+	Decode,
+	DecodeBuffer,
+	DecodeError,
+	Encode,
+	EncodeError,
+	Limits,
+};
+use crate::net::{ClientError, CredentialProvider};
+use crate::{Status, Word};
+
+const RECORD_END: u32 = 0xFFFFFFFF;
+
+// Chunks returned by `DataReader::next_chunk()` are at most this long, so
+// that a large record doesn't need to be buffered in full.
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+
+// Decoder {{{
+
+/// Decodes messages from an [`AsyncRead`].
+///
+/// Bytes are read into a buffer until a complete message can be decoded.
+/// Any bytes following the message are kept for the next call.
+///
+/// Unless other [`Limits`] are set, messages longer than 16 MiB are rejected
+/// rather than buffered.
+pub struct Decoder<R> {
+	reader: R,
+	buf: DecodeBuffer,
+}
+
+impl<R> fmt::Debug for Decoder<R> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Decoder")
+			.field("buffered", &self.buf.len())
+			.finish()
+	}
+}
+
+impl<R> Decoder<R> {
+	pub fn new(reader: R) -> Decoder<R> {
+		Decoder {
+			reader,
+			buf: DecodeBuffer::new(),
+		}
+	}
+
+	pub fn get_ref(&self) -> &R {
+		&self.reader
+	}
+
+	pub fn get_mut(&mut self) -> &mut R {
+		&mut self.reader
+	}
+
+	/// Returns the reader. Any buffered bytes are lost.
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+
+	pub fn limits(&self) -> &Limits {
+		self.buf.limits()
+	}
+
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.buf.set_limits(limits);
+	}
+}
+
+impl<R: AsyncRead + Unpin> Decoder<R> {
+	pub async fn decode<T: Decode>(
+		&mut self,
+	) -> Result<T, DecodeError<io::Error>> {
+		loop {
+			if let Some(value) = self.buf.try_decode()? {
+				return Ok(value);
+			}
+			self.fill_buf().await?;
+		}
+	}
+
+	/// Decodes the reply to a request for `procedure`.
+	pub async fn decode_reply(
+		&mut self,
+		procedure: net::ProcedureNumber,
+	) -> Result<net::Reply, DecodeError<io::Error>> {
+		loop {
+			let reply = self
+				.buf
+				.try_decode_with(|r| net::Reply::decode_for(procedure, r))?;
+			if let Some(reply) = reply {
+				return Ok(reply);
+			}
+			self.fill_buf().await?;
+		}
+	}
+
+	async fn fill_buf(&mut self) -> Result<(), DecodeError<io::Error>> {
+		let count = self
+			.reader
+			.read(self.buf.unfilled())
+			.await
+			.map_err(DecodeError::io_err)?;
+		if count == 0 {
+			return Err(DecodeBuffer::eof_error());
+		}
+		self.buf.filled(count);
+		Ok(())
+	}
+}
+
+// }}}
+
+// Encoder {{{
+
+/// Encodes messages to an [`AsyncWrite`].
+///
+/// Messages are buffered by [`Encoder::encode`] until they are sent by
+/// [`Encoder::flush`], so that a request can be sent with a single write.
+pub struct Encoder<W> {
+	writer: W,
+	codec: Codec,
+	buf: Vec<u8>,
+}
+
+impl<W> fmt::Debug for Encoder<W> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Encoder")
+			.field("buffered", &self.buf.len())
+			.finish()
+	}
+}
+
+impl<W> Encoder<W> {
+	pub fn new(writer: W) -> Encoder<W> {
+		Encoder {
+			writer,
+			codec: Codec::BINARY_V3,
+			buf: Vec::new(),
+		}
+	}
+
+	pub fn get_ref(&self) -> &W {
+		&self.writer
+	}
+
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.writer
+	}
+
+	/// Returns the writer. Any buffered bytes are lost.
+	pub fn into_inner(self) -> W {
+		self.writer
+	}
+
+	/// Encodes `value` into the buffer.
+	pub fn encode<T: Encode + ?Sized>(
+		&mut self,
+		value: &T,
+	) -> Result<(), EncodeError<io::Error>> {
+		encode_to_vec(&self.codec, &mut self.buf, value)
+	}
+}
+
+impl<W: AsyncWrite + Unpin> Encoder<W> {
+	/// Writes the buffered messages.
+	pub async fn flush(&mut self) -> io::Result<()> {
+		let result = self.writer.write_all(&self.buf).await;
+		self.buf.clear();
+		result?;
+		self.writer.flush().await
+	}
+
+	/// Encodes `value` and writes it, along with any buffered messages.
+	pub async fn send<T: Encode + ?Sized>(
+		&mut self,
+		value: &T,
+	) -> Result<(), EncodeError<io::Error>> {
+		self.encode(value)?;
+		self.flush().await.map_err(EncodeError::io_err)
+	}
+}
+
+// }}}
+
+// Client {{{
+
+/// Asynchronous client for the SANE network protocol.
+///
+/// Requests are the same as for [`net::Client`], including authorization
+/// with a [`CredentialProvider`].
+pub struct Client {
+	reader: Decoder<tcp::OwnedReadHalf>,
+	writer: Encoder<tcp::OwnedWriteHalf>,
+	credentials: Option<Box<dyn CredentialProvider + Send>>,
+}
+
+impl fmt::Debug for Client {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Client")
+			.field("reader", &self.reader)
+			.field("writer", &self.writer)
+			.finish()
+	}
+}
+
+impl Client {
+	pub async fn connect(addr: impl ToSocketAddrs) -> io::Result<Client> {
+		Ok(Client::new(TcpStream::connect(addr).await?))
+	}
+
+	pub fn new(stream: TcpStream) -> Client {
+		let (reader, writer) = stream.into_split();
+		Client {
+			reader: Decoder::new(reader),
+			writer: Encoder::new(writer),
+			credentials: None,
+		}
+	}
+
+	pub fn set_credential_provider(
+		&mut self,
+		provider: impl CredentialProvider + Send + 'static,
+	) {
+		self.credentials = Some(Box::new(provider));
+	}
+
+	/// Sets the limits used when decoding replies.
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.reader.set_limits(limits);
+	}
+
+	/// `SANE_NET_INIT`
+	pub async fn init(
+		&mut self,
+		request: &net::InitRequest,
+	) -> Result<net::InitReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::INIT, request).await
+	}
+
+	/// `SANE_NET_GET_DEVICES`
+	pub async fn get_devices(
+		&mut self,
+	) -> Result<net::GetDevicesReplyBuf, ClientError> {
+		let request = net::GetDevicesRequestBuf::new();
+		self.call(net::ProcedureNumber::GET_DEVICES, &request).await
+	}
+
+	/// `SANE_NET_OPEN`
+	pub async fn open(
+		&mut self,
+		request: &net::OpenRequest,
+	) -> Result<net::OpenReplyBuf, ClientError> {
+		self.call_authorized(net::ProcedureNumber::OPEN, request).await
+	}
+
+	/// `SANE_NET_CLOSE`
+	pub async fn close(
+		&mut self,
+		request: &net::CloseRequest,
+	) -> Result<net::CloseReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::CLOSE, request).await
+	}
+
+	/// `SANE_NET_GET_OPTION_DESCRIPTORS`
+	pub async fn get_option_descriptors(
+		&mut self,
+		request: &net::GetOptionDescriptorsRequest,
+	) -> Result<net::GetOptionDescriptorsReplyBuf, ClientError> {
+		let procedure = net::ProcedureNumber::GET_OPTION_DESCRIPTORS;
+		self.call(procedure, request).await
+	}
+
+	/// `SANE_NET_CONTROL_OPTION`
+	pub async fn control_option(
+		&mut self,
+		request: &net::ControlOptionRequest,
+	) -> Result<net::ControlOptionReplyBuf, ClientError> {
+		let procedure = net::ProcedureNumber::CONTROL_OPTION;
+		self.call_authorized(procedure, request).await
+	}
+
+	/// `SANE_NET_GET_PARAMETERS`
+	pub async fn get_parameters(
+		&mut self,
+		request: &net::GetParametersRequest,
+	) -> Result<net::GetParametersReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::GET_PARAMETERS, request).await
+	}
+
+	/// `SANE_NET_START`
+	pub async fn start(
+		&mut self,
+		request: &net::StartRequest,
+	) -> Result<net::StartReplyBuf, ClientError> {
+		self.call_authorized(net::ProcedureNumber::START, request).await
+	}
+
+	/// Connects to the data port returned by `SANE_NET_START`.
+	pub async fn data_reader(
+		&self,
+		reply: &net::StartReply,
+	) -> io::Result<DataReader<TcpStream>> {
+		let mut addr = self.reader.get_ref().peer_addr()?;
+		addr.set_port(reply.port());
+		Ok(DataReader::new(TcpStream::connect(addr).await?))
+	}
+
+	/// `SANE_NET_CANCEL`
+	pub async fn cancel(
+		&mut self,
+		request: &net::CancelRequest,
+	) -> Result<net::CancelReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::CANCEL, request).await
+	}
+
+	/// `SANE_NET_AUTHORIZE`
+	pub async fn authorize(
+		&mut self,
+		request: &net::AuthorizeRequest,
+	) -> Result<net::AuthorizeReplyBuf, ClientError> {
+		self.call(net::ProcedureNumber::AUTHORIZE, request).await
+	}
+
+	/// `SANE_NET_EXIT`
+	pub async fn exit(mut self) -> Result<(), ClientError> {
+		// The server closes the connection without sending a reply.
+		let request = net::ExitRequestBuf::new();
+		self.send(net::ProcedureNumber::EXIT, &request).await?;
+		let _ = self.writer.get_mut().shutdown().await;
+		Ok(())
+	}
+
+	async fn call<Request, Reply>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<Reply, ClientError>
+	where
+		Request: Encode + ?Sized,
+		Reply: Decode,
+	{
+		self.send(procedure, request).await?;
+		self.recv().await
+	}
+
+	async fn call_authorized<Request, Reply>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<Reply, ClientError>
+	where
+		Request: Encode + ?Sized,
+		Reply: AuthReply,
+	{
+		let mut auth = AuthAttempts::new();
+		let mut reply: Reply = self.call(procedure, request).await?;
+		loop {
+			let provider = self
+				.credentials
+				.as_deref_mut()
+				.map(|provider| provider as &mut dyn CredentialProvider);
+			match auth.next_step(&reply, provider) {
+				AuthStep::Authorize(auth_request) => {
+					let _: net::AuthorizeReplyBuf = self
+						.call(net::ProcedureNumber::AUTHORIZE, &auth_request)
+						.await?;
+					reply = self.recv().await?;
+				},
+				AuthStep::Resend => {
+					reply = self.call(procedure, request).await?;
+				},
+				AuthStep::Deny(auth_request) => {
+					let _: net::AuthorizeReplyBuf = self
+						.call(net::ProcedureNumber::AUTHORIZE, &auth_request)
+						.await?;
+					let _: Reply = self.recv().await?;
+					return Ok(Reply::access_denied());
+				},
+				AuthStep::Done => return Ok(reply),
+			}
+		}
+	}
+
+	async fn send<Request: Encode + ?Sized>(
+		&mut self,
+		procedure: net::ProcedureNumber,
+		request: &Request,
+	) -> Result<(), ClientError> {
+		self.writer.encode(&procedure).map_err(ClientError::Encode)?;
+		self.writer.encode(request).map_err(ClientError::Encode)?;
+		self.writer.flush().await.map_err(ClientError::Io)
+	}
+
+	async fn recv<Reply: Decode>(&mut self) -> Result<Reply, ClientError> {
+		self.reader.decode().await.map_err(ClientError::Decode)
+	}
+}
+
+// }}}
+
+// DataReader {{{
+
+/// Asynchronous reader for the image data stream of a `SANE_NET_START`
+/// connection.
+///
+/// Image data is returned as a sequence of chunks, each containing part of
+/// a record sent by the server.
+pub struct DataReader<R> {
+	reader: R,
+	record_len: u32,
+	status: Option<Status>,
+}
+
+impl<R> fmt::Debug for DataReader<R> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("DataReader")
+			.field("record_len", &self.record_len)
+			.field("status", &self.status)
+			.finish()
+	}
+}
+
+impl<R> DataReader<R> {
+	pub fn new(reader: R) -> DataReader<R> {
+		DataReader {
+			reader,
+			record_len: 0,
+			status: None,
+		}
+	}
+
+	pub fn get_ref(&self) -> &R {
+		&self.reader
+	}
+
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+
+	/// Returns the status sent after the last record, or `None` if the end
+	/// of the stream has not been reached yet.
+	pub fn status(&self) -> Option<Status> {
+		self.status
+	}
+}
+
+impl<R: AsyncRead + Unpin> DataReader<R> {
+	/// Returns the next chunk of image data, or `None` at the end of the
+	/// stream.
+	pub async fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+		while self.record_len == 0 {
+			if self.status.is_some() {
+				return Ok(None);
+			}
+			self.next_record().await?;
+		}
+		let len = (self.record_len as usize).min(MAX_CHUNK_LEN);
+		let mut chunk = vec![0u8; len];
+		self.reader.read_exact(&mut chunk).await?;
+		self.record_len -= len as u32;
+		Ok(Some(chunk))
+	}
+
+	/// Reads and discards any remaining image data, then returns the final
+	/// status of the scan.
+	pub async fn finish(&mut self) -> io::Result<Status> {
+		while self.next_chunk().await?.is_some() {}
+		match self.status {
+			Some(status) => Ok(status),
+			None => Err(io::ErrorKind::UnexpectedEof.into()),
+		}
+	}
+
+	async fn next_record(&mut self) -> io::Result<()> {
+		let len = self.reader.read_u32().await?;
+		if len != RECORD_END {
+			self.record_len = len;
+			return Ok(());
+		}
+		let status = self.reader.read_u8().await?;
+		self.status = Some(Status::from_word(Word::new(u32::from(status))));
+		Ok(())
+	}
+}
+
+// }}}
@@ -0,0 +1,205 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use sane::net::{self, ProcedureNumber};
+use sane::net::futures_io::{Decoder, Encoder};
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+	fn wake(self: Arc<Self>) {}
+}
+
+// The test readers and writers wake immediately, so polling in a loop is
+// enough to drive them without an async runtime.
+fn block_on<F: Future>(future: F) -> F::Output {
+	let waker = Waker::from(Arc::new(NoopWaker));
+	let mut cx = Context::from_waker(&waker);
+	let mut future = Box::pin(future);
+	loop {
+		if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+			return output;
+		}
+	}
+}
+
+// Returns `Poll::Pending` before each byte, and reads or writes one byte at a
+// time.
+struct Trickle {
+	bytes: Vec<u8>,
+	pos: usize,
+	ready: bool,
+}
+
+impl Trickle {
+	fn new(bytes: &[u8]) -> Trickle {
+		Trickle {
+			bytes: bytes.to_vec(),
+			pos: 0,
+			ready: false,
+		}
+	}
+
+	fn poll_ready(&mut self, cx: &mut Context) -> Poll<()> {
+		self.ready = !self.ready;
+		if self.ready {
+			return Poll::Ready(());
+		}
+		cx.waker().wake_by_ref();
+		Poll::Pending
+	}
+}
+
+impl AsyncRead for Trickle {
+	fn poll_read(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context,
+		buf: &mut [u8],
+	) -> Poll<io::Result<usize>> {
+		if self.poll_ready(cx).is_pending() {
+			return Poll::Pending;
+		}
+		if buf.is_empty() || self.pos == self.bytes.len() {
+			return Poll::Ready(Ok(0));
+		}
+		buf[0] = self.bytes[self.pos];
+		self.pos += 1;
+		Poll::Ready(Ok(1))
+	}
+}
+
+impl AsyncWrite for Trickle {
+	fn poll_write(
+		mut self: Pin<&mut Self>,
+		cx: &mut Context,
+		buf: &[u8],
+	) -> Poll<io::Result<usize>> {
+		if self.poll_ready(cx).is_pending() {
+			return Poll::Pending;
+		}
+		if buf.is_empty() {
+			return Poll::Ready(Ok(0));
+		}
+		self.bytes.push(buf[0]);
+		Poll::Ready(Ok(1))
+	}
+
+	fn poll_flush(
+		self: Pin<&mut Self>,
+		_cx: &mut Context,
+	) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_close(
+		self: Pin<&mut Self>,
+		_cx: &mut Context,
+	) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+#[test]
+fn decoder_requests() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 2, // SANE_NET_OPEN
+		0, 0, 0, 4, // device_name.len
+		b'a', b'b', b'c', 0,
+		0, 0, 0, 3, // SANE_NET_CLOSE
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 10, // SANE_NET_EXIT
+	];
+
+	let mut decoder = Decoder::new(Trickle::new(BYTES));
+	block_on(async {
+		let request: net::Request = decoder.decode().await.unwrap();
+		let net::Request::Open(request) = request else {
+			panic!("expected OPEN request, got {:?}", request);
+		};
+		assert_eq!(request.device_name().to_bytes(), b"abc");
+
+		let request: net::Request = decoder.decode().await.unwrap();
+		let net::Request::Close(request) = request else {
+			panic!("expected CLOSE request, got {:?}", request);
+		};
+		assert_eq!(request.handle(), net::Handle(0x11223344));
+
+		let request: net::Request = decoder.decode().await.unwrap();
+		assert!(matches!(request, net::Request::Exit(_)));
+
+		let err = decoder.decode::<net::Request>().await.unwrap_err();
+		assert!(format!("{:?}", err).contains("UnexpectedEof"));
+	});
+}
+
+#[test]
+fn decoder_replies() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 0, // resource
+		0, 0, 0, 0, // Status::GOOD
+		0, 0, 0x18, 0xE6, // port
+		0, 0, 0, 0, // byte_order
+		0, 0, 0, 0, // resource
+	];
+
+	let mut decoder = Decoder::new(Trickle::new(BYTES));
+	block_on(async {
+		let reply: net::OpenReplyBuf = decoder.decode().await.unwrap();
+		assert_eq!(reply.handle(), net::Handle(0x11223344));
+
+		let reply = decoder.decode_reply(ProcedureNumber::START).await;
+		let net::Reply::Start(reply) = reply.unwrap() else {
+			panic!("expected START reply");
+		};
+		assert_eq!(reply.port(), 6374);
+	});
+}
+
+#[test]
+fn decoder_invalid() {
+	const BYTES: &[u8] = &[0, 0, 0, 2];
+
+	let mut decoder = Decoder::new(BYTES);
+	let err = block_on(decoder.decode::<sane::Bool>()).unwrap_err();
+	assert!(format!("{:?}", err).contains("InvalidBool"));
+}
+
+#[test]
+fn encoder() {
+	let mut encoder = Encoder::new(Trickle::new(b""));
+	let mut request = net::CloseRequestBuf::new();
+	request.set_handle(net::Handle(0x11223344));
+	encoder.encode(&ProcedureNumber::CLOSE).unwrap();
+	block_on(async {
+		encoder.send(&request).await.unwrap();
+		encoder.send(&ProcedureNumber::EXIT).await.unwrap();
+		encoder.close().await.unwrap();
+	});
+	assert_eq!(encoder.get_ref().bytes, &[
+		0, 0, 0, 3, // SANE_NET_CLOSE
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 10, // SANE_NET_EXIT
+	]);
+}
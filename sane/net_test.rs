@@ -158,6 +158,32 @@ fn sane_net_byte_order() {
 	);
 }
 
+#[test]
+fn sane_net_byte_order_u16_samples() {
+	let native = if cfg!(target_endian = "big") {
+		ByteOrder::BIG_ENDIAN
+	} else {
+		ByteOrder::LITTLE_ENDIAN
+	};
+	assert_eq!(ByteOrder::NATIVE, native);
+
+	let mut be_bytes = [0x11, 0x22, 0x33, 0x44, 0x55];
+	ByteOrder::BIG_ENDIAN.u16_samples_to_native(&mut be_bytes);
+	assert_eq!(u16::from_ne_bytes([be_bytes[0], be_bytes[1]]), 0x1122);
+	assert_eq!(u16::from_ne_bytes([be_bytes[2], be_bytes[3]]), 0x3344);
+	assert_eq!(be_bytes[4], 0x55);
+
+	let mut le_bytes = [0x22, 0x11, 0x44, 0x33];
+	ByteOrder::LITTLE_ENDIAN.u16_samples_to_native(&mut le_bytes);
+	assert_eq!(u16::from_ne_bytes([le_bytes[0], le_bytes[1]]), 0x1122);
+	assert_eq!(u16::from_ne_bytes([le_bytes[2], le_bytes[3]]), 0x3344);
+
+	let mut bytes = [0x11, 0x22];
+	let unknown = ByteOrder::from_word(Word::new(0));
+	unknown.u16_samples_to_native(&mut bytes);
+	assert_eq!(bytes, [0x11, 0x22]);
+}
+
 #[test]
 fn sane_net_procedure_number() {
 	assert_eq!(
@@ -1354,6 +1380,254 @@ fn option_value_string_size_extend() {
 	assert_eq!(value, decoded);
 }
 
+#[test]
+fn option_value_invalid() {
+	use net::io::DecodeErrorKind as K;
+	use sane::ValueType as T;
+
+	fn decode_err(value_bytes: &[u8]) -> net::io::DecodeError<std::io::Error> {
+		let mut request_bytes = vec![0u8; 12];
+		request_bytes.extend_from_slice(value_bytes);
+		decode_err!(net::ControlOptionRequestBuf, request_bytes)
+	}
+
+	let err = decode_err(&concat_bytes_!(
+		[0, 0, 0, 0], // BOOL
+		[0, 0, 0, 8], // value_size
+		[0, 0, 0, 1], // values[-1]: word list length
+	));
+	assert!(matches!(err.kind(), K::InvalidValueSize(T::BOOL, 8)));
+
+	let err = decode_err(&concat_bytes_!(
+		[0, 0, 0, 0], // BOOL
+		[0, 0, 0, 4], // value_size
+		[0, 0, 0, 2], // values[-1]: word list length
+	));
+	assert!(matches!(err.kind(), K::InvalidValueCount(T::BOOL, 2)));
+
+	let err = decode_err(&concat_bytes_!(
+		[0, 0, 0, 1], // INT
+		[0, 0, 0, 6], // value_size
+		[0, 0, 0, 1], // values[-1]: word list length
+	));
+	assert!(matches!(err.kind(), K::InvalidValueSize(T::INT, 6)));
+
+	let err = decode_err(&concat_bytes_!(
+		[0, 0, 0, 2], // FIXED
+		[0, 0, 0, 8], // value_size
+		[0x40, 0, 0, 0], // values[-1]: word list length
+	));
+	assert!(matches!(
+		err.kind(),
+		K::InvalidValueCount(T::FIXED, 0x40000000),
+	));
+
+	let err = decode_err(&concat_bytes_!(
+		[0, 0, 0, 4], // BUTTON
+		[0, 0, 0, 4], // value_size
+	));
+	assert!(matches!(err.kind(), K::InvalidValueSize(T::BUTTON, 4)));
+}
+
+#[test]
+fn option_value_empty_string() {
+	use sane::ValueType as T;
+
+	// An empty STRING value is accepted from peers, but can't be encoded
+	// because strings are sent with their NUL terminator.
+	let bytes = concat_bytes_!(
+		[0, 0, 0, 0], // status
+		[0, 0, 0, 0], // info
+		[0, 0, 0, 3], // value_type: STRING
+		[0, 0, 0, 0], // value_size
+		[0, 0, 0, 0], // resource
+	);
+	let reply: net::ControlOptionReplyBuf = decode_ok!(bytes);
+	assert_eq!(reply.value_type(), T::STRING);
+	assert_eq!(reply.value().as_bytes(), b"");
+	match net::io::decode_ref::<net::ControlOptionReply>(&bytes) {
+		Ok(net::io::Decoded::Complete { message, .. }) => {
+			assert_eq!(*message, reply);
+		},
+		decoded => panic!("{:?}", decoded),
+	}
+
+	let mut encoded = Vec::new();
+	let mut cursor = std::io::Cursor::new(&mut encoded);
+	let mut writer = net::io::Codec::BINARY_V3.writer(&mut cursor);
+	let err = net::io::Encode::encode(&reply, &mut writer).unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		net::io::EncodeErrorKind::InvalidValueSize(T::STRING, 0),
+	));
+}
+
+#[test]
+fn option_descriptor_invalid_size() {
+	use net::io::DecodeErrorKind as K;
+	use sane::ValueType as T;
+
+	let err = decode_err!(util::OptionDescriptorBuf, concat_bytes_!(
+		[0, 0, 0, 0], // name
+		[0, 0, 0, 0], // title
+		[0, 0, 0, 0], // desc
+		[0, 0, 0, 1], // ValueType::INT
+		[0, 0, 0, 0], // Unit::NONE
+		[0, 0, 0, 6], // size
+		[0, 0, 0, 0], // capabilities
+		[0, 0, 0, 0], // ConstraintType::NONE
+	));
+	assert!(matches!(err.kind(), K::InvalidValueSize(T::INT, 6)));
+}
+
+#[test]
+fn decode_large_size() {
+	// A size that is much larger than the message shouldn't be allocated
+	// before the message has been read.
+	let err = decode_err!(CString, b"\xFF\xFF\xFF\xF0abc");
+	let io_err = err.into_io_error().unwrap();
+	assert_eq!(io_err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+	let err = decode_err!(net::GetDevicesReplyBuf, concat_bytes_!(
+		[0, 0, 0, 0],             // Status::GOOD
+		[0xFF, 0xFF, 0xFF, 0xF0], // devices.len
+	));
+	assert!(err.io_error().is_some());
+}
+
+#[test]
+fn decode_limits() {
+	use net::io::{DecodeErrorKind as K, Limit};
+
+	fn decode_err_with<T: std::fmt::Debug + net::io::Decode>(
+		limits: net::io::Limits,
+		bytes: &[u8],
+	) -> net::io::DecodeError<std::io::Error> {
+		let mut cursor = std::io::Cursor::new(bytes);
+		let codec = net::io::Codec::BINARY_V3.with_limits(limits);
+		let mut reader = codec.reader(&mut cursor);
+		T::decode(&mut reader).unwrap_err()
+	}
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_string_len(4);
+	let err = decode_err_with::<CString>(limits, &concat_bytes_!(
+		[0, 0, 0, 5], // len
+		b"abcd\x00",
+	));
+	assert!(matches!(err.kind(), K::LimitExceeded(Limit::StringLen, 5)));
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_option_count(1);
+	let err = decode_err_with::<net::GetOptionDescriptorsReplyBuf>(
+		limits,
+		&[0, 0, 0, 2], // option_descriptors.len
+	);
+	assert!(matches!(err.kind(), K::LimitExceeded(Limit::OptionCount, 2)));
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_word_list_len(1);
+	let err = decode_err_with::<net::ControlOptionRequestBuf>(
+		limits,
+		&concat_bytes_!(
+			[0, 0, 0, 0], // handle
+			[0, 0, 0, 0], // option
+			[0, 0, 0, 1], // Action::SET_VALUE
+			[0, 0, 0, 1], // INT
+			[0, 0, 0, 8], // value_size
+			[0, 0, 0, 2], // values[-1]: word list length
+		),
+	);
+	assert!(matches!(err.kind(), K::LimitExceeded(Limit::WordListLen, 2)));
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_message_len(8);
+	let err = decode_err_with::<net::OpenRequestBuf>(limits, &concat_bytes_!(
+		[0, 0, 0, 12], // device_name.len
+		b"device-name\x00",
+	));
+	assert!(matches!(err.kind(), K::LimitExceeded(Limit::MessageLen, 16)));
+	assert!(err.to_string().contains("MessageLen"));
+}
+
+#[test]
+fn codec_byte_order() {
+	use net::io::{Codec, Decode, Encode};
+
+	assert_eq!(Codec::BINARY_V3.byte_order(), ByteOrder::BIG_ENDIAN);
+	let codec = Codec::BINARY_V3_LITTLE_ENDIAN;
+	assert_eq!(codec.byte_order(), ByteOrder::LITTLE_ENDIAN);
+	assert_eq!(
+		Codec::BINARY_V3
+			.with_byte_order(ByteOrder::LITTLE_ENDIAN)
+			.unwrap()
+			.byte_order(),
+		ByteOrder::LITTLE_ENDIAN,
+	);
+	assert!(Codec::BINARY_V3
+		.with_byte_order(ByteOrder::from_word(Word::new(0)))
+		.is_none());
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_string_len(4);
+	let codec = codec.with_limits(limits);
+	assert_eq!(codec.byte_order(), ByteOrder::LITTLE_ENDIAN);
+	assert_eq!(codec.limits(), &limits);
+
+	let mut request_buf = net::ControlOptionRequestBuf::new();
+	request_buf.set_handle(net::Handle(0x11223344));
+	request_buf.set_option(2);
+	request_buf.set_action(sane::Action::SET_VALUE);
+	request_buf.set_value(net::OptionValueBuf::from_i32_list(&[
+		0x11223344,
+		0x55667788,
+	]));
+
+	let mut bytes = Vec::new();
+	let mut cursor = std::io::Cursor::new(&mut bytes);
+	let mut writer = codec.writer(&mut cursor);
+	assert_eq!(writer.byte_order(), ByteOrder::LITTLE_ENDIAN);
+	request_buf.encode(&mut writer).unwrap();
+	assert_eq!(bytes, concat_bytes_!(
+		[0x44, 0x33, 0x22, 0x11], // handle
+		[2, 0, 0, 0],             // option
+		[1, 0, 0, 0],             // Action::SET_VALUE
+		[1, 0, 0, 0],             // INT
+		[8, 0, 0, 0],             // value_size
+		[2, 0, 0, 0],             // values[-1]: word list length
+		[0x44, 0x33, 0x22, 0x11], // values[0]
+		[0x88, 0x77, 0x66, 0x55], // values[1]
+	));
+
+	let mut cursor = std::io::Cursor::new(bytes);
+	let mut reader = codec.reader(&mut cursor);
+	assert_eq!(reader.byte_order(), ByteOrder::LITTLE_ENDIAN);
+	let decoded = net::ControlOptionRequestBuf::decode(&mut reader).unwrap();
+	assert_eq!(request_buf, decoded);
+	assert_eq!(decoded.value().as_bytes(), concat_bytes_!(
+		[0x11, 0x22, 0x33, 0x44],
+		[0x55, 0x66, 0x77, 0x88],
+	));
+}
+
+#[test]
+fn push_decoder_limits() {
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_string_len(4);
+
+	let mut decoder = net::io::PushDecoder::new();
+	decoder.set_limits(limits);
+	assert_eq!(decoder.limits(), &limits);
+
+	let decoded = decoder.push::<CString>(&[0, 0, 0]).unwrap();
+	assert_eq!(decoded, net::io::Decoded::NeedMoreData);
+	let err = decoder.push::<CString>(&[5]).unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		net::io::DecodeErrorKind::LimitExceeded(net::io::Limit::StringLen, 5),
+	));
+}
+
 #[test]
 fn get_parameters_request() {
 	let mut request_buf = net::GetParametersRequestBuf::new();
@@ -1556,3 +1830,1470 @@ fn authorize_reply() {
 	let decoded: net::AuthorizeReplyBuf = decode_ok!(bytes);
 	assert_eq!(reply_buf, decoded);
 }
+
+#[test]
+fn auth_resource() {
+	let resource = net::AuthResource::parse(cstr(b"test$MD5$1234\x00"));
+	assert_eq!(resource.name(), b"test");
+	assert_eq!(resource.md5_salt(), Some(&b"1234"[..]));
+	assert_eq!(resource.as_c_str(), cstr(b"test$MD5$1234\x00"));
+
+	let request = resource.md5_authorize_request(cstr(b"user\x00"), b"secret");
+	let request = request.unwrap();
+	assert_eq!(request.resource(), cstr(b"test$MD5$1234\x00"));
+	assert_eq!(request.username(), cstr(b"user\x00"));
+	assert_eq!(
+		request.password(),
+		cstr(b"$MD5$10b168cd4f742410888c3c110f7a7e71\x00"),
+	);
+
+	// Passwords are never sent in plaintext.
+	let resource = net::AuthResource::parse(cstr(b"test\x00"));
+	assert_eq!(resource.name(), b"test");
+	assert_eq!(resource.md5_salt(), None);
+	let request = resource.md5_authorize_request(cstr(b"user\x00"), b"secret");
+	assert!(request.is_none());
+}
+
+#[test]
+fn md5_password() {
+	assert_eq!(
+		net::md5_password(b"", b""),
+		CString::new("$MD5$d41d8cd98f00b204e9800998ecf8427e").unwrap(),
+	);
+	assert_eq!(
+		net::md5_password(b"1234", b"secret"),
+		CString::new("$MD5$10b168cd4f742410888c3c110f7a7e71").unwrap(),
+	);
+
+	// Like saned, the salt and password are truncated to 128 bytes.
+	assert_eq!(
+		net::md5_password(&[b'a'; 200], &[b'b'; 200]),
+		CString::new("$MD5$125c1527bc810493e04a94c8fc617c5c").unwrap(),
+	);
+
+	let response = net::md5_password(b"1234", b"secret");
+	assert!(net::verify_md5_password(b"1234", b"secret", &response));
+	assert!(!net::verify_md5_password(b"1234", b"wrong", &response));
+	assert!(!net::verify_md5_password(b"5678", b"secret", &response));
+	assert!(!net::verify_md5_password(b"1234", b"secret", cstr(b"secret\x00")));
+}
+
+#[test]
+fn exit_request() {
+	let request_buf = net::ExitRequestBuf::new();
+	let request = request_buf.as_ref();
+
+	assert_eq!(
+		format!("{:#?}", request),
+		"ExitRequest",
+	);
+
+	let bytes = encode_ok!(request);
+	assert_eq!(bytes, &[]);
+
+	let decoded: net::ExitRequestBuf = decode_ok!(bytes);
+	assert_eq!(request_buf, decoded);
+}
+
+#[test]
+fn request() {
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let request = net::Request::Open(open_request);
+	assert_eq!(request.procedure_number(), ProcedureNumber::OPEN);
+
+	let bytes = encode_ok!(request);
+	assert_eq!(bytes, concat_bytes_!(
+		[0, 0, 0, 2],  // SANE_NET_OPEN
+		[0, 0, 0, 12], // device_name.len
+		b"device-name\x00",
+	));
+	assert_decode_eq!(request, bytes);
+
+	let request = net::Request::Exit(net::ExitRequestBuf::new());
+	assert_encode_eq!(request, &[0, 0, 0, 10]);
+	assert_decode_eq!(request, &[0, 0, 0, 10]);
+
+	let err = decode_err!(net::Request, b"\x00\x00\x00\x0B");
+	assert!(format!("{:?}", err).contains("InvalidProcedureNumber"));
+}
+
+#[test]
+fn reply() {
+	let mut open_reply = net::OpenReplyBuf::new();
+	open_reply.set_handle(net::Handle(1));
+	let reply = net::Reply::Open(open_reply);
+	assert_eq!(reply.procedure_number(), ProcedureNumber::OPEN);
+
+	let bytes = encode_ok!(reply);
+	assert_eq!(bytes, concat_bytes_!(
+		[0, 0, 0, 0], // status
+		[0, 0, 0, 1], // handle
+		[0, 0, 0, 0], // resource.len
+	));
+
+	let mut cursor = std::io::Cursor::new(&bytes);
+	let mut reader = net::io::Codec::BINARY_V3.reader(&mut cursor);
+	let decoded = net::Reply::decode_for(ProcedureNumber::OPEN, &mut reader);
+	assert_eq!(decoded.unwrap(), reply);
+
+	let mut cursor = std::io::Cursor::new(&bytes);
+	let mut reader = net::io::Codec::BINARY_V3.reader(&mut cursor);
+	let decoded = net::Reply::decode_for(ProcedureNumber::EXIT, &mut reader);
+	let err = decoded.unwrap_err();
+	assert!(format!("{:?}", err).contains("InvalidProcedureNumber"));
+}
+
+#[test]
+fn decode_prefix() {
+	use net::io::Decoded;
+
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3],             // SANE_NET_CLOSE
+		[0x11, 0x22, 0x33, 0x44], // handle
+		[0, 0],
+	);
+
+	let decoded = net::io::decode_prefix::<net::Request>(bytes).unwrap();
+	let mut expect = net::CloseRequestBuf::new();
+	expect.set_handle(net::Handle(0x11223344));
+	assert_eq!(decoded, Decoded::Complete {
+		message: net::Request::Close(expect),
+		consumed: 8,
+	});
+
+	for len in 0..8 {
+		let decoded = net::io::decode_prefix::<net::Request>(&bytes[..len]);
+		assert_eq!(decoded.unwrap(), Decoded::NeedMoreData);
+	}
+
+	let decoded = net::io::decode_prefix::<sane::Bool>(&[0, 0, 0, 2]);
+	let err = decoded.unwrap_err();
+	assert!(format!("{:?}", err).contains("InvalidBool"));
+}
+
+fn assert_decode_ref<'a, T, Expect>(bytes: &'a [u8], expect: &Expect)
+where
+	T: net::io::DecodeRef<'a> + std::fmt::Debug + PartialEq<Expect>,
+	Expect: std::fmt::Debug,
+{
+	use net::io::Decoded;
+
+	match net::io::decode_ref::<T>(bytes).unwrap() {
+		Decoded::Complete { message, consumed } => {
+			assert_eq!(*message, *expect);
+			assert_eq!(consumed, bytes.len());
+		},
+		Decoded::NeedMoreData => panic!("decode_ref: NeedMoreData"),
+	}
+
+	for len in 0..bytes.len() {
+		let decoded = net::io::decode_ref::<T>(&bytes[..len]).unwrap();
+		assert!(matches!(decoded, Decoded::NeedMoreData));
+	}
+}
+
+#[test]
+fn decode_ref() {
+	let mut init_request = net::InitRequestBuf::new();
+	init_request.set_username(CSTR_EMPTY);
+	let bytes = encode_ok!(&init_request);
+	assert_decode_ref::<net::InitRequest, _>(&bytes, &init_request);
+	init_request.set_username(cstr(b"username\x00"));
+	let bytes = encode_ok!(&init_request);
+	assert_decode_ref::<net::InitRequest, _>(&bytes, &init_request);
+
+	let mut open_reply = net::OpenReplyBuf::new();
+	open_reply.set_handle(net::Handle(0x11223344));
+	let bytes = encode_ok!(&open_reply);
+	assert_decode_ref::<net::OpenReply, _>(&bytes, &open_reply);
+	open_reply.set_resource(cstr(b"resource\x00"));
+	let bytes = encode_ok!(&open_reply);
+	assert_decode_ref::<net::OpenReply, _>(&bytes, &open_reply);
+
+	let mut control_request = net::ControlOptionRequestBuf::new();
+	control_request.set_handle(net::Handle(0x11223344));
+	control_request.set_option(2);
+	control_request.set_action(sane::Action::SET_AUTO);
+	let bytes = encode_ok!(&control_request);
+	assert_decode_ref::<net::ControlOptionRequest, _>(
+		&bytes,
+		&control_request,
+	);
+	control_request.set_action(sane::Action::SET_VALUE);
+	control_request.set_value(net::OptionValueBuf::from_i32_list(&[1, 2]));
+	let bytes = encode_ok!(&control_request);
+	assert_decode_ref::<net::ControlOptionRequest, _>(
+		&bytes,
+		&control_request,
+	);
+
+	let mut control_reply = net::ControlOptionReplyBuf::new();
+	control_reply.set_value(net::OptionValueBuf::from_bool(true));
+	control_reply.set_resource(cstr(b"resource\x00"));
+	let bytes = encode_ok!(&control_reply);
+	assert_decode_ref::<net::ControlOptionReply, _>(&bytes, &control_reply);
+	control_reply.set_value(net::OptionValueBuf::from_cstring(CSTR_OPT_NAME));
+	let bytes = encode_ok!(&control_reply);
+	assert_decode_ref::<net::ControlOptionReply, _>(&bytes, &control_reply);
+
+	let mut params_reply = net::GetParametersReplyBuf::new();
+	let mut params = sane::Parameters::new();
+	params.lines = sane::Int::new(100);
+	params_reply.set_parameters(params);
+	let bytes = encode_ok!(&params_reply);
+	assert_decode_ref::<net::GetParametersReply, _>(&bytes, &params_reply);
+
+	let mut start_reply = net::StartReplyBuf::new();
+	start_reply.set_port(1234);
+	start_reply.set_byte_order(ByteOrder::BIG_ENDIAN);
+	start_reply.set_resource(cstr(b"resource\x00"));
+	let bytes = encode_ok!(&start_reply);
+	assert_decode_ref::<net::StartReply, _>(&bytes, &start_reply);
+
+	let mut authorize_request = net::AuthorizeRequestBuf::new();
+	authorize_request.set_resource(cstr(b"resource\x00"));
+	authorize_request.set_username(cstr(b"username\x00"));
+	authorize_request.set_password(cstr(b"password\x00"));
+	let bytes = encode_ok!(&authorize_request);
+	assert_decode_ref::<net::AuthorizeRequest, _>(&bytes, &authorize_request);
+
+	let close_request = net::CloseRequestBuf::new();
+	let bytes = encode_ok!(&close_request);
+	assert_decode_ref::<net::CloseRequest, _>(&bytes, &close_request);
+	let cancel_reply = net::CancelReplyBuf::new();
+	let bytes = encode_ok!(&cancel_reply);
+	assert_decode_ref::<net::CancelReply, _>(&bytes, &cancel_reply);
+	let exit_request = net::ExitRequestBuf::new();
+	assert_decode_ref::<net::ExitRequest, _>(&[], &exit_request);
+}
+
+#[test]
+fn decode_ref_borrows() {
+	use net::io::Decoded;
+
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 12], // device_name.len
+		b"device-name\x00",
+		[0, 0], // trailing bytes
+	);
+	let decoded = net::io::decode_ref::<net::OpenRequest>(bytes).unwrap();
+	let (request, consumed) = match decoded {
+		Decoded::Complete { message, consumed } => (message, consumed),
+		Decoded::NeedMoreData => panic!("decode_ref: NeedMoreData"),
+	};
+	assert_eq!(consumed, 16);
+	assert_eq!(request.device_name(), CSTR_DEV_NAME);
+	assert_eq!(request.device_name().as_ptr(), bytes[4..].as_ptr().cast());
+}
+
+#[test]
+fn decode_ref_errors() {
+	use net::io::DecodeErrorKind as K;
+
+	let err = net::io::decode_ref::<net::OpenRequest>(&concat_bytes_!(
+		[0, 0, 0, 3], // device_name.len
+		b"abc",
+	)).unwrap_err();
+	assert_eq!(err.kind(), &K::InvalidString);
+
+	let err = net::io::decode_ref::<net::ControlOptionReply>(&concat_bytes_!(
+		[0, 0, 0, 0], // status
+		[0, 0, 0, 0], // info
+		[0, 0, 0, 0], // BOOL
+		[0, 0, 0, 4], // value_size
+		[0, 0, 0, 1], // values[-1]: word list length
+		[0, 0, 0, 2], // values[0]
+	)).unwrap_err();
+	assert_eq!(err.kind(), &K::InvalidBool(Word::new(2)));
+
+	let err = net::io::decode_ref::<net::ControlOptionReply>(&concat_bytes_!(
+		[0, 0, 0, 0], // status
+		[0, 0, 0, 0], // info
+		[0, 0, 0, 1], // INT
+		[0, 0, 0, 6], // value_size
+		[0, 0, 0, 1], // values[-1]: word list length
+	)).unwrap_err();
+	assert_eq!(
+		err.kind(),
+		&K::InvalidValueSize(sane::ValueType::INT, 6),
+	);
+
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_string_len(3);
+	let bytes = concat_bytes_!(
+		[0, 0, 0, 4], // device_name.len
+		b"abc\x00",
+	);
+	let err = net::io::decode_ref_with_limits::<net::OpenRequest>(
+		&bytes,
+		limits,
+	).unwrap_err();
+	assert_eq!(err.kind(), &K::LimitExceeded(net::io::Limit::StringLen, 4));
+	limits.set_max_string_len(4);
+	let decoded = net::io::decode_ref_with_limits::<net::OpenRequest>(
+		&bytes,
+		limits,
+	);
+	assert!(matches!(decoded, Ok(net::io::Decoded::Complete { .. })));
+}
+
+#[test]
+fn push_decoder() {
+	use net::io::Decoded;
+
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 2],       // SANE_NET_OPEN
+		[0, 0, 0, 12],      // device_name.len
+		b"device-name\x00",
+		[0, 0, 0, 10],      // SANE_NET_EXIT
+	);
+
+	let mut decoder = net::io::PushDecoder::new();
+	for (ii, byte) in bytes[..19].iter().enumerate() {
+		let decoded = decoder.push::<net::Request>(&[*byte]).unwrap();
+		assert_eq!(decoded, Decoded::NeedMoreData);
+		assert_eq!(decoder.buffered(), ii + 1);
+	}
+
+	let decoded = decoder.push::<net::Request>(&bytes[19..]).unwrap();
+	let mut expect = net::OpenRequestBuf::new();
+	expect.set_device_name(CSTR_DEV_NAME);
+	assert_eq!(decoded, Decoded::Complete {
+		message: net::Request::Open(expect),
+		consumed: 1,
+	});
+	assert_eq!(decoder.buffered(), 0);
+
+	let decoded = decoder.push::<net::Request>(&bytes[20..]).unwrap();
+	assert_eq!(decoded, Decoded::Complete {
+		message: net::Request::Exit(net::ExitRequestBuf::new()),
+		consumed: 4,
+	});
+}
+
+#[test]
+fn push_decoder_large_message() {
+	use net::io::Decoded;
+
+	let mut bytes = vec![
+		0, 0, 0, 2, // SANE_NET_OPEN
+		0, 0, 0x4E, 0x21, // device_name.len
+	];
+	bytes.extend_from_slice(&[b'a'; 20000]);
+	bytes.push(0);
+
+	let mut decoder = net::io::PushDecoder::default();
+	let (last, chunks) = bytes.split_last().unwrap();
+	for chunk in chunks.chunks(100) {
+		let decoded = decoder.push::<net::Request>(chunk).unwrap();
+		assert_eq!(decoded, Decoded::NeedMoreData);
+	}
+	assert_eq!(decoder.buffered(), bytes.len() - 1);
+
+	let decoded = decoder.push::<net::Request>(&[*last]).unwrap();
+	let request = match decoded {
+		Decoded::Complete {
+			message: net::Request::Open(request),
+			consumed: 1,
+		} => request,
+		_ => panic!("{:?}", decoded),
+	};
+	assert_eq!(request.device_name().to_bytes(), &[b'a'; 20000]);
+	assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn push_decoder_max_message_len() {
+	let mut decoder = net::io::PushDecoder::new();
+	assert_eq!(decoder.limits().max_message_len(), 16 * 1024 * 1024);
+
+	// A length past the limit is rejected without buffering the message.
+	let err = decoder.push::<CString>(&[0x02, 0, 0, 0]).unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		net::io::DecodeErrorKind::LimitExceeded(
+			net::io::Limit::MessageLen,
+			_,
+		),
+	));
+
+	// Bytes buffered before the limit was lowered are also rejected.
+	let decoded = decoder.push::<CString>(&[0, 0, 0, 16, 0, 0]).unwrap();
+	assert_eq!(decoded, net::io::Decoded::NeedMoreData);
+	let mut limits = net::io::Limits::NONE;
+	limits.set_max_message_len(4);
+	decoder.set_limits(limits);
+	let err = decoder.push::<CString>(&[0]).unwrap_err();
+	assert!(matches!(
+		err.kind(),
+		net::io::DecodeErrorKind::LimitExceeded(
+			net::io::Limit::MessageLen,
+			6,
+		),
+	));
+	assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn push_decoder_reply() {
+	use net::io::Decoded;
+
+	let mut decoder = net::io::PushDecoder::new();
+	let decoded = decoder.push_reply(ProcedureNumber::CLOSE, &[0, 0]);
+	assert_eq!(decoded.unwrap(), Decoded::NeedMoreData);
+	let decoded = decoder.push_reply(ProcedureNumber::CLOSE, &[0, 0, 1]);
+	assert_eq!(decoded.unwrap(), Decoded::Complete {
+		message: net::Reply::Close(net::CloseReplyBuf::new()),
+		consumed: 2,
+	});
+}
+
+#[test]
+fn push_decoder_error() {
+	let mut decoder = net::io::PushDecoder::new();
+	let decoded = decoder.push::<net::Request>(&[0xFF, 0xFF]).unwrap();
+	assert_eq!(decoded, net::io::Decoded::NeedMoreData);
+	let err = decoder.push::<net::Request>(&[0xFF, 0xFF]).unwrap_err();
+	assert!(format!("{:?}", err).contains("InvalidProcedureNumber"));
+	assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn decode_error() {
+	use std::error::Error;
+
+	let err = decode_err!(Bool, b"\x00\x00\x00\x02");
+	assert!(matches!(
+		err.kind(),
+		net::io::DecodeErrorKind::InvalidBool(word) if word.as_u32() == 2,
+	));
+	assert!(err.io_error().is_none());
+	assert!(err.source().is_none());
+	assert_eq!(err.to_string(), "invalid SANE_Bool 0x00000002");
+
+	let err = decode_err!(Bool, b"\x00\x00");
+	assert!(matches!(err.kind(), net::io::DecodeErrorKind::IoError(_)));
+	assert!(err.source().is_some());
+	let io_err = err.into_io_error().unwrap();
+	assert_eq!(io_err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn encode_error() {
+	use std::error::Error;
+
+	use sane::net::io::Encode;
+
+	struct ClosedWriter;
+
+	impl net::io::Write for ClosedWriter {
+		type Error = std::io::Error;
+
+		fn write_all(&mut self, _buf: &[u8]) -> std::io::Result<()> {
+			Err(std::io::ErrorKind::BrokenPipe.into())
+		}
+	}
+
+	let mut writer = ClosedWriter;
+	let mut writer = net::io::Codec::BINARY_V3.writer(&mut writer);
+	let err = Bool::TRUE.encode(&mut writer).unwrap_err();
+	assert!(matches!(err.kind(), net::io::EncodeErrorKind::IoError(_)));
+	assert!(err.source().is_some());
+	assert!(err.to_string().starts_with("I/O error: "));
+	let io_err = err.into_io_error().unwrap();
+	assert_eq!(io_err.kind(), std::io::ErrorKind::BrokenPipe);
+}
+
+fn fake_server(
+	exchanges: Vec<(&'static [u8], &'static [u8])>,
+) -> (std::net::SocketAddr, std::thread::JoinHandle<()>) {
+	use std::io::{Read, Write};
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	let thread = std::thread::spawn(move || {
+		let (mut stream, _) = listener.accept().unwrap();
+		for (expect_request, reply) in exchanges {
+			let mut request = vec![0u8; expect_request.len()];
+			stream.read_exact(&mut request).unwrap();
+			assert_eq!(request, expect_request);
+			stream.write_all(reply).unwrap();
+		}
+	});
+	(addr, thread)
+}
+
+#[test]
+fn client_session() {
+	const INIT_REQUEST: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 0],             // SANE_NET_INIT
+		[0x01, 0x01, 0x00, 0x03], // version_code
+		[0, 0, 0, 4],             // username.len
+		b"aaa\x00",
+	);
+	const INIT_REPLY: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 0],             // Status::GOOD
+		[0x01, 0x01, 0x00, 0x03], // version_code
+	);
+	const OPEN_REQUEST: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 2],       // SANE_NET_OPEN
+		[0, 0, 0, 12],      // device_name.len
+		b"device-name\x00",
+	);
+	const OPEN_REPLY: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 0],             // Status::GOOD
+		[0x11, 0x22, 0x33, 0x44], // handle
+		[0, 0, 0, 0],             // resource
+	);
+	const CLOSE_REQUEST: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3],             // SANE_NET_CLOSE
+		[0x11, 0x22, 0x33, 0x44], // handle
+	);
+	const CLOSE_REPLY: &[u8] = &[0, 0, 0, 0];
+	const EXIT_REQUEST: &[u8] = &[0, 0, 0, 10];
+
+	let (addr, server) = fake_server(vec![
+		(INIT_REQUEST, INIT_REPLY),
+		(OPEN_REQUEST, OPEN_REPLY),
+		(CLOSE_REQUEST, CLOSE_REPLY),
+		(EXIT_REQUEST, b""),
+	]);
+
+	let mut client = net::Client::connect(addr).unwrap();
+
+	let mut init_request = net::InitRequestBuf::new();
+	init_request.set_username(cstr(b"aaa\x00"));
+	let init_reply = client.init(&init_request).unwrap();
+	assert_eq!(init_reply.status(), sane::Status::GOOD);
+	assert_eq!(init_reply.version_code(), net::VERSION_CODE);
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::GOOD);
+	assert_eq!(open_reply.handle(), net::Handle(0x11223344));
+	assert_eq!(open_reply.resource(), None);
+
+	let mut close_request = net::CloseRequestBuf::new();
+	close_request.set_handle(open_reply.handle());
+	client.close(&close_request).unwrap();
+
+	client.exit().unwrap();
+	server.join().unwrap();
+}
+
+#[test]
+fn client_connection_closed() {
+	const GET_DEVICES_REQUEST: &[u8] = &[0, 0, 0, 1];
+	const GET_DEVICES_REPLY_PARTIAL: &[u8] = &[0, 0, 0, 0];
+
+	let (addr, server) = fake_server(vec![
+		(GET_DEVICES_REQUEST, GET_DEVICES_REPLY_PARTIAL),
+	]);
+
+	let mut client = net::Client::connect(addr).unwrap();
+	let err = client.get_devices().unwrap_err();
+	assert!(matches!(err, net::ClientError::Decode(_)));
+
+	server.join().unwrap();
+}
+
+#[test]
+fn client_authorize_loop() {
+	const OPEN_REQUEST: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 2],       // SANE_NET_OPEN
+		[0, 0, 0, 12],      // device_name.len
+		b"device-name\x00",
+	);
+	const OPEN_REPLY: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 0], // Status::GOOD
+		[0, 0, 0, 0], // handle
+		[0, 0, 0, 5], // resource.len
+		b"test\x00",
+	);
+	const AUTHORIZE_REQUEST: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 9], // SANE_NET_AUTHORIZE
+		[0, 0, 0, 5], // resource.len
+		b"test\x00",
+		[0, 0, 0, 1], // username.len
+		b"\x00",
+		[0, 0, 0, 1], // password.len
+		b"\x00",
+	);
+	const AUTHORIZE_REPLY: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 0], // AuthorizeReply
+		[0, 0, 0, 0], // OpenReply: Status::GOOD
+		[0, 0, 0, 0], // OpenReply: handle
+		[0, 0, 0, 5], // OpenReply: resource.len
+		b"test\x00",
+	);
+
+	// The server asks for authorization after every attempt. Once the
+	// client gives up, the request is denied.
+	let (addr, server) = fake_server(vec![
+		(OPEN_REQUEST, OPEN_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+	]);
+
+	let mut client = net::Client::connect(addr).unwrap();
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::ACCESS_DENIED);
+	assert_eq!(open_reply.resource(), None);
+
+	server.join().unwrap();
+}
+
+#[test]
+fn data_reader() {
+	use std::io::Read;
+
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		b"abc",
+		[0, 0, 0, 0], // record.len
+		[0, 0, 0, 2], // record.len
+		b"de",
+		[0xFF, 0xFF, 0xFF, 0xFF], // end of records
+		[5],                      // Status::EOF
+	);
+	let mut reader = net::DataReader::new(bytes);
+	assert_eq!(reader.status(), None);
+
+	let mut buf = [0u8; 2];
+	assert_eq!(reader.read(&mut buf).unwrap(), 2);
+	assert_eq!(&buf, b"ab");
+
+	let mut data = Vec::new();
+	reader.read_to_end(&mut data).unwrap();
+	assert_eq!(data, b"cde");
+	assert_eq!(reader.status(), Some(sane::Status::EOF));
+	assert_eq!(reader.finish().unwrap(), sane::Status::EOF);
+}
+
+#[test]
+fn data_reader_finish() {
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		b"abc",
+		[0xFF, 0xFF, 0xFF, 0xFF], // end of records
+		[2],                      // Status::CANCELLED
+	);
+	let mut reader = net::DataReader::new(bytes);
+	assert_eq!(reader.finish().unwrap(), sane::Status::CANCELLED);
+}
+
+#[test]
+fn data_reader_truncated() {
+	use std::io::Read;
+
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		b"abc",
+	);
+	let mut reader = net::DataReader::new(bytes);
+	let mut data = Vec::new();
+	let err = reader.read_to_end(&mut data).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+	assert_eq!(data, b"abc");
+	assert_eq!(reader.status(), None);
+}
+
+#[test]
+fn data_reader_u16_samples() {
+	let bytes: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		[0x11, 0x22, 0x33],
+		[0, 0, 0, 1], // record.len
+		[0x44],
+		[0xFF, 0xFF, 0xFF, 0xFF], // end of records
+		[5],                      // Status::EOF
+	);
+
+	let mut reader = net::DataReader::new(bytes);
+	let count = reader.read_u16_samples(ByteOrder::BIG_ENDIAN, &mut []);
+	assert_eq!(count.unwrap(), 0);
+	assert_eq!(reader.status(), None);
+
+	let mut samples = [0u16; 4];
+	let count = reader
+		.read_u16_samples(ByteOrder::BIG_ENDIAN, &mut samples)
+		.unwrap();
+	assert_eq!(&samples[..count], &[0x1122, 0x3344]);
+	let count = reader
+		.read_u16_samples(ByteOrder::BIG_ENDIAN, &mut samples)
+		.unwrap();
+	assert_eq!(count, 0);
+	assert_eq!(reader.status(), Some(sane::Status::EOF));
+
+	let mut reader = net::DataReader::new(bytes);
+	let mut samples = [0u16; 1];
+	let count = reader
+		.read_u16_samples(ByteOrder::LITTLE_ENDIAN, &mut samples)
+		.unwrap();
+	assert_eq!(&samples[..count], &[0x2211]);
+	let count = reader
+		.read_u16_samples(ByteOrder::LITTLE_ENDIAN, &mut samples)
+		.unwrap();
+	assert_eq!(&samples[..count], &[0x4433]);
+
+	let mut reader = net::DataReader::new(bytes);
+	let unknown = ByteOrder::from_word(Word::new(0));
+	let err = reader.read_u16_samples(unknown, &mut samples).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+	let truncated: &[u8] = &concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		[0x11, 0x22, 0x33],
+		[0xFF, 0xFF, 0xFF, 0xFF], // end of records
+		[5],                      // Status::EOF
+	);
+	let mut reader = net::DataReader::new(truncated);
+	let mut samples = [0u16; 4];
+	let err = reader
+		.read_u16_samples(ByteOrder::BIG_ENDIAN, &mut samples)
+		.unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+struct TestBackend {
+	closed: u32,
+	reads: u32,
+	endless: bool,
+	// Reads return no data, as from a non-blocking backend.
+	idle: bool,
+	password: Option<&'static CStr>,
+}
+
+struct TestDevice {
+	resolution: i32,
+	image: &'static [u8],
+	cancelled: bool,
+}
+
+const TEST_IMAGE: &[u8] = b"image data";
+
+impl net::Backend for TestBackend {
+	type Device = TestDevice;
+
+	fn get_devices(&mut self) -> Result<Vec<util::DeviceBuf>, sane::Status> {
+		let mut device = util::DeviceBuf::new(CSTR_DEV_NAME);
+		device.set_vendor(CSTR_DEV_VENDOR);
+		Ok(vec![device])
+	}
+
+	fn open(&mut self, device_name: &CStr) -> Result<TestDevice, sane::Status> {
+		if device_name != CSTR_DEV_NAME {
+			return Err(sane::Status::INVAL);
+		}
+		Ok(TestDevice {
+			resolution: 75,
+			image: &[],
+			cancelled: false,
+		})
+	}
+
+	fn auth_resource(&mut self, _device_name: &CStr) -> Option<CString> {
+		self.password?;
+		Some(CString::from(cstr(b"test\x00")))
+	}
+
+	fn password(
+		&mut self,
+		resource: &CStr,
+		username: &CStr,
+	) -> Option<CString> {
+		if resource.to_bytes() != b"test" || username.to_bytes() != b"user" {
+			return None;
+		}
+		self.password.map(CString::from)
+	}
+
+	fn close(&mut self, _device: TestDevice) {
+		self.closed += 1;
+	}
+
+	fn get_option_descriptors(
+		&mut self,
+		_device: &mut TestDevice,
+	) -> Vec<util::OptionDescriptorBuf> {
+		Vec::new()
+	}
+
+	fn control_option(
+		&mut self,
+		device: &mut TestDevice,
+		option: u32,
+		action: sane::Action,
+		value: &mut net::OptionValueBuf,
+	) -> Result<u32, sane::Status> {
+		if option != 1 {
+			return Err(sane::Status::INVAL);
+		}
+		if action == sane::Action::SET_VALUE {
+			let resolution = value.as_option_value().to_i32();
+			device.resolution = resolution.map_err(|_| sane::Status::INVAL)?;
+		}
+		*value = net::OptionValueBuf::from_i32(device.resolution);
+		Ok(0)
+	}
+
+	fn get_parameters(
+		&mut self,
+		device: &mut TestDevice,
+	) -> Result<sane::Parameters, sane::Status> {
+		let mut params = sane::Parameters::new();
+		params.pixels_per_line = Int::new(device.resolution);
+		Ok(params)
+	}
+
+	fn start(&mut self, device: &mut TestDevice) -> Result<(), sane::Status> {
+		device.image = TEST_IMAGE;
+		device.cancelled = false;
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		device: &mut TestDevice,
+		buf: &mut [u8],
+	) -> Result<usize, sane::Status> {
+		self.reads += 1;
+		if device.cancelled {
+			return Err(sane::Status::CANCELLED);
+		}
+		if self.idle {
+			return Ok(0);
+		}
+		if device.image.is_empty() {
+			if !self.endless {
+				return Err(sane::Status::EOF);
+			}
+			device.image = TEST_IMAGE;
+		}
+		let count = buf.len().min(device.image.len()).min(4);
+		buf[..count].copy_from_slice(&device.image[..count]);
+		device.image = &device.image[count..];
+		Ok(count)
+	}
+
+	fn cancel(&mut self, device: &mut TestDevice) {
+		device.cancelled = true;
+	}
+}
+
+fn test_server(
+	backend: TestBackend,
+) -> (
+	std::net::SocketAddr,
+	std::thread::JoinHandle<Result<TestBackend, net::ServerError>>,
+) {
+	test_server_with(backend, |_server| {})
+}
+
+fn test_server_with(
+	backend: TestBackend,
+	configure: impl FnOnce(&mut net::Server<TestBackend>) + Send + 'static,
+) -> (
+	std::net::SocketAddr,
+	std::thread::JoinHandle<Result<TestBackend, net::ServerError>>,
+) {
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	let thread = std::thread::spawn(move || {
+		let (stream, _) = listener.accept().unwrap();
+		let mut server = net::Server::new(backend);
+		configure(&mut server);
+		server.serve_connection(stream)?;
+		Ok(server.into_backend())
+	});
+	(addr, thread)
+}
+
+#[test]
+fn server_session() {
+	use std::io::Read;
+
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: None,
+	});
+	let mut client = net::Client::connect(addr).unwrap();
+
+	let mut init_request = net::InitRequestBuf::new();
+	init_request.set_username(cstr(b"aaa\x00"));
+	let init_reply = client.init(&init_request).unwrap();
+	assert_eq!(init_reply.status(), sane::Status::GOOD);
+
+	let devices_reply = client.get_devices().unwrap();
+	assert_eq!(devices_reply.devices().len(), 1);
+	assert_eq!(devices_reply.devices()[0].name(), CSTR_DEV_NAME);
+	assert_eq!(devices_reply.devices()[0].vendor(), CSTR_DEV_VENDOR);
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_TYPE);
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::INVAL);
+
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::GOOD);
+	let handle = open_reply.handle();
+
+	let mut control_request = net::ControlOptionRequestBuf::new();
+	control_request.set_handle(handle);
+	control_request.set_option(1);
+	control_request.set_action(sane::Action::SET_VALUE);
+	control_request.set_value(net::OptionValueBuf::from_i32(300));
+	let control_reply = client.control_option(&control_request).unwrap();
+	assert_eq!(control_reply.status(), sane::Status::GOOD);
+	assert_eq!(control_reply.value().to_i32(), Ok(300));
+
+	let mut params_request = net::GetParametersRequestBuf::new();
+	params_request.set_handle(handle);
+	let params_reply = client.get_parameters(&params_request).unwrap();
+	assert_eq!(params_reply.status(), sane::Status::GOOD);
+	assert_eq!(params_reply.parameters().pixels_per_line, Int::new(300));
+
+	let mut start_request = net::StartRequestBuf::new();
+	start_request.set_handle(handle);
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+
+	let mut data = client.data_reader(&start_reply).unwrap();
+	let mut image = Vec::new();
+	data.read_to_end(&mut image).unwrap();
+	assert_eq!(image, TEST_IMAGE);
+	assert_eq!(data.status(), Some(sane::Status::EOF));
+
+	client.exit().unwrap();
+
+	// The device was not closed by the client, so it is closed when the
+	// connection ends.
+	let backend = server.join().unwrap().unwrap();
+	assert_eq!(backend.closed, 1);
+}
+
+#[test]
+fn server_version_mismatch() {
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: None,
+	});
+	let mut client = net::Client::connect(addr).unwrap();
+
+	let mut init_request = net::InitRequestBuf::new();
+	init_request.set_version_code(0x02000003);
+	let init_reply = client.init(&init_request).unwrap();
+	assert_eq!(init_reply.status(), sane::Status::INVAL);
+
+	server.join().unwrap().unwrap();
+}
+
+// Sends a request, and returns the first reply. If the server requires
+// authorization, this reply will contain the challenge.
+fn call_raw(
+	stream: &mut std::net::TcpStream,
+	request: net::Request,
+) -> net::Reply {
+	use std::io::Write;
+
+	stream.write_all(&encode_ok!(request)).unwrap();
+	let mut reader = net::io::Codec::BINARY_V3.reader(stream);
+	net::Reply::decode_for(request.procedure_number(), &mut reader).unwrap()
+}
+
+fn open_raw(
+	stream: &mut std::net::TcpStream,
+	device_name: &CStr,
+) -> net::OpenReplyBuf {
+	let mut request = net::OpenRequestBuf::new();
+	request.set_device_name(device_name);
+	match call_raw(stream, net::Request::Open(request)) {
+		net::Reply::Open(reply) => reply,
+		reply => panic!("unexpected reply {:?}", reply),
+	}
+}
+
+fn authorize_open_raw(
+	stream: &mut std::net::TcpStream,
+	challenge: &net::OpenReply,
+	password: &[u8],
+) -> net::OpenReplyBuf {
+	use net::io::Decode;
+
+	let resource = net::AuthResource::parse(challenge.resource().unwrap());
+	let request = resource.md5_authorize_request(cstr(b"user\x00"), password);
+	call_raw(stream, net::Request::Authorize(request.unwrap()));
+
+	// The real reply is sent after the authorization.
+	let mut reader = net::io::Codec::BINARY_V3.reader(stream);
+	net::OpenReplyBuf::decode(&mut reader).unwrap()
+}
+
+#[test]
+fn server_authorize() {
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: Some(cstr(b"secret\x00")),
+	});
+	let mut stream = std::net::TcpStream::connect(addr).unwrap();
+	call_raw(&mut stream, net::Request::Init(net::InitRequestBuf::new()));
+
+	let challenge = open_raw(&mut stream, CSTR_DEV_NAME);
+	let resource = net::AuthResource::parse(challenge.resource().unwrap());
+	assert_eq!(resource.name(), b"test");
+	assert!(resource.md5_salt().is_some());
+
+	let open_reply = authorize_open_raw(&mut stream, &challenge, b"secret");
+	assert_eq!(open_reply.status(), sane::Status::GOOD);
+	assert_eq!(open_reply.resource(), None);
+
+	// Each challenge has a new salt.
+	let challenge_2 = open_raw(&mut stream, CSTR_DEV_NAME);
+	assert_ne!(challenge_2.resource(), challenge.resource());
+
+	let open_reply = authorize_open_raw(&mut stream, &challenge_2, b"wrong");
+	assert_eq!(open_reply.status(), sane::Status::ACCESS_DENIED);
+
+	drop(stream);
+	server.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_unsolicited_authorize() {
+	use net::ProcedureNumber as P;
+	use std::io::Write;
+
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: None,
+	});
+	let mut stream = std::net::TcpStream::connect(addr).unwrap();
+	call_raw(&mut stream, net::Request::Init(net::InitRequestBuf::new()));
+
+	let request = net::Request::Authorize(net::AuthorizeRequestBuf::new());
+	stream.write_all(&encode_ok!(request)).unwrap();
+	let err = server.join().unwrap().err();
+	assert!(matches!(
+		err,
+		Some(net::ServerError::InvalidProcedure(P::AUTHORIZE)),
+	));
+}
+
+#[test]
+fn client_authorize() {
+	use std::cell::Cell;
+	use std::rc::Rc;
+
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: Some(cstr(b"secret\x00")),
+	});
+	let mut client = net::Client::connect(addr).unwrap();
+	client.init(&net::InitRequestBuf::new()).unwrap();
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+
+	// Without credentials, the request is denied.
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::ACCESS_DENIED);
+
+	// Rejected credentials are retried.
+	let attempts = Rc::new(Cell::new(0));
+	client.set_credential_provider({
+		let attempts = attempts.clone();
+		move |resource: &net::AuthResource| {
+			assert_eq!(resource.name(), b"test");
+			attempts.set(attempts.get() + 1);
+			let password = match attempts.get() {
+				1 => "wrong",
+				_ => "secret",
+			};
+			Some((
+				CString::new("user").unwrap(),
+				CString::new(password).unwrap(),
+			))
+		}
+	});
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::GOOD);
+	assert_eq!(open_reply.resource(), None);
+	assert_eq!(attempts.get(), 2);
+
+	// Repeated failures are reported as `SANE_STATUS_ACCESS_DENIED`.
+	let attempts = Rc::new(Cell::new(0));
+	client.set_credential_provider({
+		let attempts = attempts.clone();
+		move |_: &net::AuthResource| {
+			attempts.set(attempts.get() + 1);
+			Some((CString::new("user").unwrap(), CString::new("").unwrap()))
+		}
+	});
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::ACCESS_DENIED);
+	assert_eq!(attempts.get(), 3);
+
+	client.exit().unwrap();
+	server.join().unwrap().unwrap();
+}
+
+#[test]
+fn remote_host() {
+	let host = net::RemoteHost::parse("scanner").unwrap();
+	assert_eq!(host, net::RemoteHost::new("scanner", None));
+	assert_eq!(host.port_or_default(), net::DEFAULT_PORT);
+	assert_eq!(host.to_string(), "scanner");
+
+	let host = net::RemoteHost::parse("scanner:1234").unwrap();
+	assert_eq!(host, net::RemoteHost::new("scanner", Some(1234)));
+	assert_eq!(host.to_string(), "scanner:1234");
+
+	let host = net::RemoteHost::parse("[::1]").unwrap();
+	assert_eq!(host, net::RemoteHost::new("::1", None));
+	assert_eq!(host.to_string(), "[::1]");
+
+	let host = net::RemoteHost::parse("[::1]:1234").unwrap();
+	assert_eq!(host, net::RemoteHost::new("::1", Some(1234)));
+	assert_eq!(host.to_string(), "[::1]:1234");
+
+	let host = net::RemoteHost::parse("2001:db8::1").unwrap();
+	assert_eq!(host, net::RemoteHost::new("2001:db8::1", None));
+
+	assert_eq!(net::RemoteHost::parse(""), None);
+	assert_eq!(net::RemoteHost::parse("scanner:"), None);
+	assert_eq!(net::RemoteHost::parse("scanner:0"), None);
+	assert_eq!(net::RemoteHost::parse("scanner:port"), None);
+	assert_eq!(net::RemoteHost::parse("[::1"), None);
+	assert_eq!(net::RemoteHost::parse("[::1]1234"), None);
+}
+
+#[test]
+fn remote_device_name() {
+	let name = net::RemoteDeviceName::parse(cstr(
+		b"net:scanner:pixma:04A91749\x00",
+	))
+	.unwrap();
+	assert_eq!(name.host(), &net::RemoteHost::new("scanner", None));
+	assert_eq!(name.device(), cstr(b"pixma:04A91749\x00"));
+	assert_eq!(
+		name.to_c_string().as_c_str(),
+		cstr(b"net:scanner:pixma:04A91749\x00"),
+	);
+
+	let name = net::RemoteDeviceName::parse(cstr(
+		b"net:[::1]:1234:pixma:04A91749\x00",
+	))
+	.unwrap();
+	assert_eq!(name.host(), &net::RemoteHost::new("::1", Some(1234)));
+	assert_eq!(name.device(), cstr(b"pixma:04A91749\x00"));
+	assert_eq!(
+		name.to_c_string().as_c_str(),
+		cstr(b"net:[::1]:1234:pixma:04A91749\x00"),
+	);
+
+	let name = net::RemoteDeviceName::parse(cstr(b"net:[::1]\x00")).unwrap();
+	assert_eq!(name.host(), &net::RemoteHost::new("::1", None));
+	assert_eq!(name.device(), CSTR_EMPTY);
+
+	let name =
+		net::RemoteDeviceName::parse(cstr(b"net:scanner:test\x00")).unwrap();
+	assert_eq!(name.host(), &net::RemoteHost::new("scanner", None));
+	assert_eq!(name.device(), cstr(b"test\x00"));
+
+	assert_eq!(net::RemoteDeviceName::parse(cstr(b"pixma:dev\x00")), None);
+	assert_eq!(net::RemoteDeviceName::parse(cstr(b"net:\x00")), None);
+	assert_eq!(net::RemoteDeviceName::parse(cstr(b"net:[::1\x00")), None);
+	assert_eq!(net::RemoteDeviceName::parse(cstr(b"net:[::1]x\x00")), None);
+}
+
+#[test]
+fn device_aggregator() {
+	let new_backend = || TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: false,
+		password: None,
+	};
+	let (addr1, server1) = test_server(new_backend());
+	let (addr2, server2) = test_server(new_backend());
+	let host1 = net::RemoteHost::new("127.0.0.1", Some(addr1.port()));
+	let host2 = net::RemoteHost::new("127.0.0.1", Some(addr2.port()));
+
+	// Unreachable hosts are skipped.
+	let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let unused_port = unused.local_addr().unwrap().port();
+	drop(unused);
+
+	let mut aggregator = net::DeviceAggregator::new();
+	aggregator.set_connect_timeout(Some(std::time::Duration::from_secs(5)));
+	aggregator.add_host(host1.clone());
+	aggregator.add_host(net::RemoteHost::new("127.0.0.1", Some(unused_port)));
+	aggregator.add_host(host2.clone());
+	aggregator.add_host(host1.clone());
+	assert_eq!(aggregator.hosts().count(), 3);
+
+	let reply = aggregator.get_devices();
+	assert_eq!(reply.status(), sane::Status::GOOD);
+	let names: Vec<&CStr> = reply.devices().iter().map(|d| d.name()).collect();
+	let name1 = format!("net:127.0.0.1:{}:device-name", addr1.port());
+	let name2 = format!("net:127.0.0.1:{}:device-name", addr2.port());
+	assert_eq!(names, [
+		CString::new(name1).unwrap().as_c_str(),
+		CString::new(name2).unwrap().as_c_str(),
+	]);
+	assert_eq!(reply.devices()[0].vendor(), CSTR_DEV_VENDOR);
+
+	// Device names are routed to the client of their host.
+	let name = net::RemoteDeviceName::parse(reply.devices()[1].name());
+	let name = name.unwrap();
+	assert_eq!(name.host(), &host2);
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(name.device());
+	let client = aggregator.client(name.host()).unwrap();
+	let open_reply = client.open(&open_request).unwrap();
+	assert_eq!(open_reply.status(), sane::Status::GOOD);
+
+	drop(aggregator);
+	assert_eq!(server1.join().unwrap().unwrap().closed, 0);
+	assert_eq!(server2.join().unwrap().unwrap().closed, 1);
+}
+
+#[test]
+fn server_cancel_scan() {
+	use std::io::Read;
+
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: true,
+		idle: false,
+		password: None,
+	});
+	let mut client = net::Client::connect(addr).unwrap();
+	client.init(&net::InitRequestBuf::new()).unwrap();
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let handle = client.open(&open_request).unwrap().handle();
+
+	let mut start_request = net::StartRequestBuf::new();
+	start_request.set_handle(handle);
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+
+	let mut data = client.data_reader(&start_reply).unwrap();
+	let mut buf = [0u8; 4];
+	data.read_exact(&mut buf).unwrap();
+	assert_eq!(&buf, b"imag");
+
+	let busy_reply = client.start(&start_request).unwrap();
+	assert_eq!(busy_reply.status(), sane::Status::DEVICE_BUSY);
+	data.read_exact(&mut buf).unwrap();
+
+	let mut cancel_request = net::CancelRequestBuf::new();
+	cancel_request.set_handle(handle);
+	client.cancel(&cancel_request).unwrap();
+	assert_eq!(data.finish().unwrap(), sane::Status::CANCELLED);
+
+	let mut close_request = net::CloseRequestBuf::new();
+	close_request.set_handle(handle);
+	client.close(&close_request).unwrap();
+	client.exit().unwrap();
+
+	let backend = server.join().unwrap().unwrap();
+	assert_eq!(backend.closed, 1);
+}
+
+#[test]
+fn server_idle_backend() {
+	let (addr, server) = test_server(TestBackend {
+		closed: 0,
+		reads: 0,
+		endless: false,
+		idle: true,
+		password: None,
+	});
+	let mut client = net::Client::connect(addr).unwrap();
+	client.init(&net::InitRequestBuf::new()).unwrap();
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let handle = client.open(&open_request).unwrap().handle();
+
+	let mut start_request = net::StartRequestBuf::new();
+	start_request.set_handle(handle);
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+
+	// While the backend has no data, the server waits between reads instead
+	// of polling it in a busy loop.
+	let mut data = client.data_reader(&start_reply).unwrap();
+	std::thread::sleep(std::time::Duration::from_millis(200));
+
+	let mut cancel_request = net::CancelRequestBuf::new();
+	cancel_request.set_handle(handle);
+	client.cancel(&cancel_request).unwrap();
+	assert_eq!(data.finish().unwrap(), sane::Status::CANCELLED);
+	client.exit().unwrap();
+
+	let backend = server.join().unwrap().unwrap();
+	assert!(backend.reads < 100, "{} reads", backend.reads);
+}
+
+#[test]
+fn server_data_timeout() {
+	use std::io::Read;
+
+	let (addr, server) = test_server_with(
+		TestBackend {
+			closed: 0,
+			reads: 0,
+			endless: false,
+			idle: false,
+			password: None,
+		},
+		|server| server.set_data_timeout(std::time::Duration::from_millis(50)),
+	);
+	let mut client = net::Client::connect(addr).unwrap();
+	client.init(&net::InitRequestBuf::new()).unwrap();
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let handle = client.open(&open_request).unwrap().handle();
+
+	// The data connection is never opened, so the server gives up on the
+	// scan and goes back to handling requests.
+	let mut start_request = net::StartRequestBuf::new();
+	start_request.set_handle(handle);
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+	std::thread::sleep(std::time::Duration::from_millis(100));
+	assert!(client.data_reader(&start_reply).is_err());
+
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+	let mut data = client.data_reader(&start_reply).unwrap();
+	let mut image = Vec::new();
+	data.read_to_end(&mut image).unwrap();
+	assert_eq!(image, TEST_IMAGE);
+
+	client.exit().unwrap();
+	server.join().unwrap().unwrap();
+}
+
+#[test]
+fn server_data_port_range() {
+	use std::io::Read;
+
+	let port = unused_port();
+	let (addr, server) = test_server_with(
+		TestBackend {
+			closed: 0,
+			reads: 0,
+			endless: false,
+			idle: false,
+			password: None,
+		},
+		move |server| server.set_data_port_range(Some(port..=port)),
+	);
+	let mut client = net::Client::connect(addr).unwrap();
+	client.init(&net::InitRequestBuf::new()).unwrap();
+
+	let mut open_request = net::OpenRequestBuf::new();
+	open_request.set_device_name(CSTR_DEV_NAME);
+	let handle = client.open(&open_request).unwrap().handle();
+
+	let mut start_request = net::StartRequestBuf::new();
+	start_request.set_handle(handle);
+	let start_reply = client.start(&start_request).unwrap();
+	assert_eq!(start_reply.status(), sane::Status::GOOD);
+	assert_eq!(start_reply.port(), port);
+
+	let mut data = client.data_reader(&start_reply).unwrap();
+	let mut image = Vec::new();
+	data.read_to_end(&mut image).unwrap();
+	assert_eq!(image, TEST_IMAGE);
+
+	client.exit().unwrap();
+	server.join().unwrap().unwrap();
+}
+
+#[test]
+fn data_listener_bind_range() {
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let control = std::net::TcpStream::connect(listener.local_addr().unwrap());
+	let control = control.unwrap();
+
+	let port = unused_port();
+	let data = net::DataListener::bind_range(&control, port..=port).unwrap();
+	assert_eq!(data.port(), port);
+
+	// Binding fails if every port in the range is in use.
+	let err = net::DataListener::bind_range(&control, port..=port);
+	assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+}
+
+// Returns a port that was free at the time of the call.
+fn unused_port() -> u16 {
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	listener.local_addr().unwrap().port()
+}
+
+#[test]
+fn data_writer() {
+	use std::io::Write;
+
+	let mut writer = net::DataWriter::new(Vec::new());
+	writer.write_record(b"abc").unwrap();
+	writer.write_record(b"").unwrap();
+	writer.write_chunks([b"de", b"fg"]).unwrap();
+	writer.copy_from(&mut &b"hij"[..]).unwrap();
+	writer.write_all(b"k").unwrap();
+	writer.finish(sane::Status::JAMMED).unwrap();
+
+	let bytes = writer.into_inner();
+	assert_eq!(bytes, concat_bytes_!(
+		[0, 0, 0, 3], // record.len
+		b"abc",
+		[0, 0, 0, 2], // record.len
+		b"de",
+		[0, 0, 0, 2], // record.len
+		b"fg",
+		[0, 0, 0, 3], // record.len
+		b"hij",
+		[0, 0, 0, 1], // record.len
+		b"k",
+		[0xFF, 0xFF, 0xFF, 0xFF], // end of records
+		[6],                      // Status::JAMMED
+	));
+
+	let mut reader = net::DataReader::new(bytes.as_slice());
+	let mut data = Vec::new();
+	std::io::Read::read_to_end(&mut reader, &mut data).unwrap();
+	assert_eq!(data, b"abcdefghijk");
+	assert_eq!(reader.status(), Some(sane::Status::JAMMED));
+}
@@ -0,0 +1,337 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+use std::ffi::CStr;
+use std::future::Future;
+
+use sane::net::{self, ProcedureNumber};
+use sane::net::tokio as net_tokio;
+
+const fn cstr(bytes: &[u8]) -> &CStr {
+	unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_io()
+		.build()
+		.unwrap()
+		.block_on(future)
+}
+
+fn fake_server(
+	exchanges: Vec<(&'static [u8], &'static [u8])>,
+) -> (std::net::SocketAddr, std::thread::JoinHandle<()>) {
+	use std::io::{Read, Write};
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	let thread = std::thread::spawn(move || {
+		let (mut stream, _) = listener.accept().unwrap();
+		for (expect_request, reply) in exchanges {
+			let mut request = vec![0u8; expect_request.len()];
+			stream.read_exact(&mut request).unwrap();
+			assert_eq!(request, expect_request);
+			stream.write_all(reply).unwrap();
+		}
+	});
+	(addr, thread)
+}
+
+#[test]
+fn decoder_split_input() {
+	use tokio::io::AsyncWriteExt;
+
+	// Two replies, written one byte at a time.
+	const BYTES: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 0, // resource
+		0, 0, 0, 0, // Status::GOOD
+	];
+
+	block_on(async {
+		let (mut client, server) = tokio::io::duplex(1);
+		let writer = tokio::spawn(async move {
+			client.write_all(BYTES).await.unwrap();
+		});
+
+		let mut decoder = net_tokio::Decoder::new(server);
+		let open_reply: net::OpenReplyBuf = decoder.decode().await.unwrap();
+		assert_eq!(open_reply.handle(), net::Handle(0x11223344));
+		let _: net::CloseReplyBuf = decoder.decode().await.unwrap();
+
+		let err = decoder.decode::<net::CloseReplyBuf>().await;
+		assert!(err.is_err());
+		writer.await.unwrap();
+	});
+}
+
+#[test]
+fn decoder_pipelined() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 3, // SANE_NET_CLOSE
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 10, // SANE_NET_EXIT
+	];
+
+	block_on(async {
+		let mut decoder = net_tokio::Decoder::new(BYTES);
+		let procedure: ProcedureNumber = decoder.decode().await.unwrap();
+		assert_eq!(procedure, ProcedureNumber::CLOSE);
+		let request: net::CloseRequestBuf = decoder.decode().await.unwrap();
+		assert_eq!(request.handle(), net::Handle(0x11223344));
+		let procedure: ProcedureNumber = decoder.decode().await.unwrap();
+		assert_eq!(procedure, ProcedureNumber::EXIT);
+	});
+}
+
+#[test]
+fn decoder_large_message() {
+	// An OPEN reply with a resource longer than a single read.
+	let mut bytes = vec![
+		0, 0, 0, 0, // Status::GOOD
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0x4E, 0x21, // resource.len
+	];
+	bytes.extend_from_slice(&[b'a'; 20000]);
+	bytes.push(0);
+
+	block_on(async {
+		let mut decoder = net_tokio::Decoder::new(&bytes[..]);
+		let reply: net::OpenReplyBuf = decoder.decode().await.unwrap();
+		assert_eq!(reply.handle(), net::Handle(0x11223344));
+		assert_eq!(reply.resource().unwrap().to_bytes(), &[b'a'; 20000]);
+	});
+}
+
+#[test]
+fn decoder_default_limits() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0x11, 0x22, 0x33, 0x44, // handle
+		0x02, 0, 0, 0, // resource.len
+	];
+
+	block_on(async {
+		let mut decoder = net_tokio::Decoder::new(BYTES);
+		assert_eq!(decoder.limits().max_message_len(), 16 * 1024 * 1024);
+		let err = decoder.decode::<net::OpenReplyBuf>().await.unwrap_err();
+		assert!(matches!(
+			err.kind(),
+			net::io::DecodeErrorKind::LimitExceeded(
+				net::io::Limit::MessageLen,
+				_,
+			),
+		));
+	});
+}
+
+#[test]
+fn encoder_send() {
+	let mut encoder = net_tokio::Encoder::new(Vec::new());
+	let mut request = net::CloseRequestBuf::new();
+	request.set_handle(net::Handle(0x11223344));
+	encoder.encode(&ProcedureNumber::CLOSE).unwrap();
+	block_on(encoder.send(&request)).unwrap();
+	assert_eq!(encoder.get_ref().as_slice(), &[
+		0, 0, 0, 3, // SANE_NET_CLOSE
+		0x11, 0x22, 0x33, 0x44, // handle
+	]);
+}
+
+#[test]
+fn client_session() {
+	const INIT_REQUEST: &[u8] = &[
+		0, 0, 0, 0, // SANE_NET_INIT
+		0x01, 0x01, 0x00, 0x03, // version_code
+		0, 0, 0, 4, // username.len
+		b'a', b'a', b'a', 0,
+	];
+	const INIT_REPLY: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0x01, 0x01, 0x00, 0x03, // version_code
+	];
+	const OPEN_REQUEST: &[u8] = &[
+		0, 0, 0, 2, // SANE_NET_OPEN
+		0, 0, 0, 12, // device_name.len
+		b'd', b'e', b'v', b'i', b'c', b'e', b'-', b'n', b'a', b'm', b'e', 0,
+	];
+	const OPEN_REPLY: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0x11, 0x22, 0x33, 0x44, // handle
+		0, 0, 0, 0, // resource
+	];
+	const CLOSE_REQUEST: &[u8] = &[
+		0, 0, 0, 3, // SANE_NET_CLOSE
+		0x11, 0x22, 0x33, 0x44, // handle
+	];
+	const CLOSE_REPLY: &[u8] = &[0, 0, 0, 0];
+	const EXIT_REQUEST: &[u8] = &[0, 0, 0, 10];
+
+	let (addr, server) = fake_server(vec![
+		(INIT_REQUEST, INIT_REPLY),
+		(OPEN_REQUEST, OPEN_REPLY),
+		(CLOSE_REQUEST, CLOSE_REPLY),
+		(EXIT_REQUEST, b""),
+	]);
+
+	block_on(async {
+		let mut client = net_tokio::Client::connect(addr).await.unwrap();
+
+		let mut init_request = net::InitRequestBuf::new();
+		init_request.set_username(cstr(b"aaa\x00"));
+		let init_reply = client.init(&init_request).await.unwrap();
+		assert_eq!(init_reply.status(), sane::Status::GOOD);
+		assert_eq!(init_reply.version_code(), net::VERSION_CODE);
+
+		let mut open_request = net::OpenRequestBuf::new();
+		open_request.set_device_name(cstr(b"device-name\x00"));
+		let open_reply = client.open(&open_request).await.unwrap();
+		assert_eq!(open_reply.status(), sane::Status::GOOD);
+		assert_eq!(open_reply.handle(), net::Handle(0x11223344));
+		assert_eq!(open_reply.resource(), None);
+
+		let mut close_request = net::CloseRequestBuf::new();
+		close_request.set_handle(open_reply.handle());
+		client.close(&close_request).await.unwrap();
+
+		client.exit().await.unwrap();
+	});
+	server.join().unwrap();
+}
+
+#[test]
+fn client_authorize_loop() {
+	const OPEN_REQUEST: &[u8] = &[
+		0, 0, 0, 2, // SANE_NET_OPEN
+		0, 0, 0, 12, // device_name.len
+		b'd', b'e', b'v', b'i', b'c', b'e', b'-', b'n', b'a', b'm', b'e', 0,
+	];
+	const OPEN_REPLY: &[u8] = &[
+		0, 0, 0, 0, // Status::GOOD
+		0, 0, 0, 0, // handle
+		0, 0, 0, 5, // resource.len
+		b't', b'e', b's', b't', 0,
+	];
+	const AUTHORIZE_REQUEST: &[u8] = &[
+		0, 0, 0, 9, // SANE_NET_AUTHORIZE
+		0, 0, 0, 5, // resource.len
+		b't', b'e', b's', b't', 0,
+		0, 0, 0, 1, // username.len
+		0,
+		0, 0, 0, 1, // password.len
+		0,
+	];
+	const AUTHORIZE_REPLY: &[u8] = &[
+		0, 0, 0, 0, // AuthorizeReply
+		0, 0, 0, 0, // OpenReply: Status::GOOD
+		0, 0, 0, 0, // OpenReply: handle
+		0, 0, 0, 5, // OpenReply: resource.len
+		b't', b'e', b's', b't', 0,
+	];
+
+	// The server asks for authorization after every attempt. Once the
+	// client gives up, the request is denied.
+	let (addr, server) = fake_server(vec![
+		(OPEN_REQUEST, OPEN_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+		(AUTHORIZE_REQUEST, AUTHORIZE_REPLY),
+	]);
+
+	block_on(async {
+		let mut client = net_tokio::Client::connect(addr).await.unwrap();
+		let mut open_request = net::OpenRequestBuf::new();
+		open_request.set_device_name(cstr(b"device-name\x00"));
+		let open_reply = client.open(&open_request).await.unwrap();
+		assert_eq!(open_reply.status(), sane::Status::ACCESS_DENIED);
+		assert_eq!(open_reply.resource(), None);
+	});
+	server.join().unwrap();
+}
+
+#[test]
+fn client_connection_closed() {
+	const GET_DEVICES_REQUEST: &[u8] = &[0, 0, 0, 1];
+	const GET_DEVICES_REPLY_PARTIAL: &[u8] = &[0, 0, 0, 0];
+
+	let (addr, server) = fake_server(vec![
+		(GET_DEVICES_REQUEST, GET_DEVICES_REPLY_PARTIAL),
+	]);
+
+	block_on(async {
+		let mut client = net_tokio::Client::connect(addr).await.unwrap();
+		let err = client.get_devices().await.unwrap_err();
+		assert!(matches!(err, net::ClientError::Decode(_)));
+	});
+	server.join().unwrap();
+}
+
+#[test]
+fn data_reader() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 3, // record.len
+		b'a', b'b', b'c',
+		0, 0, 0, 0, // record.len
+		0, 0, 0, 2, // record.len
+		b'd', b'e',
+		0xFF, 0xFF, 0xFF, 0xFF, // end of records
+		5, // Status::EOF
+	];
+
+	block_on(async {
+		let mut reader = net_tokio::DataReader::new(BYTES);
+		assert_eq!(reader.status(), None);
+		assert_eq!(reader.next_chunk().await.unwrap().unwrap(), b"abc");
+		assert_eq!(reader.next_chunk().await.unwrap().unwrap(), b"de");
+		assert_eq!(reader.next_chunk().await.unwrap(), None);
+		assert_eq!(reader.status(), Some(sane::Status::EOF));
+		assert_eq!(reader.finish().await.unwrap(), sane::Status::EOF);
+	});
+}
+
+#[test]
+fn data_reader_large_record() {
+	let mut bytes = vec![0x00, 0x01, 0x80, 0x00]; // record.len
+	bytes.resize(bytes.len() + 0x18000, 0xAB);
+	bytes.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 2]); // CANCELLED
+
+	block_on(async {
+		let mut reader = net_tokio::DataReader::new(bytes.as_slice());
+		let chunk = reader.next_chunk().await.unwrap().unwrap();
+		assert_eq!(chunk.len(), 0x10000);
+		let chunk = reader.next_chunk().await.unwrap().unwrap();
+		assert_eq!(chunk.len(), 0x8000);
+		assert_eq!(reader.finish().await.unwrap(), sane::Status::CANCELLED);
+	});
+}
+
+#[test]
+fn data_reader_truncated() {
+	const BYTES: &[u8] = &[
+		0, 0, 0, 3, // record.len
+		b'a', b'b', b'c',
+	];
+
+	block_on(async {
+		let mut reader = net_tokio::DataReader::new(BYTES);
+		assert_eq!(reader.next_chunk().await.unwrap().unwrap(), b"abc");
+		let err = reader.next_chunk().await.unwrap_err();
+		assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+		assert_eq!(reader.status(), None);
+	});
+}
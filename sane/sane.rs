@@ -24,6 +24,14 @@ use core::mem;
 use core::mem::size_of;
 use core::ptr;
 
+#[cfg(any(doc, feature = "std"))]
+pub mod backend;
+#[cfg(any(doc, feature = "std"))]
+pub mod config;
+#[cfg(any(doc, feature = "dlopen"))]
+pub mod dlopen;
+#[cfg(any(doc, feature = "alloc"))]
+pub mod frontend;
 pub mod net;
 pub mod util;
 
@@ -993,3 +1001,25 @@ pub type GetSelectFdFn = unsafe extern "C" fn(
 ) -> Status;
 
 // }}}
+
+// Vtable {{{
+
+/// Entry points of a SANE backend.
+#[derive(Clone, Copy, Debug)]
+pub struct Vtable {
+	pub init: InitFn,
+	pub exit: ExitFn,
+	pub get_devices: GetDevicesFn,
+	pub open: OpenFn,
+	pub close: CloseFn,
+	pub get_option_descriptor: GetOptionDescriptorFn,
+	pub control_option: ControlOptionFn,
+	pub get_parameters: GetParametersFn,
+	pub start: StartFn,
+	pub read: ReadFn,
+	pub cancel: CancelFn,
+	pub set_io_mode: SetIoModeFn,
+	pub get_select_fd: GetSelectFdFn,
+}
+
+// }}}
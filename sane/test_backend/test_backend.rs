@@ -0,0 +1,151 @@
+// Copyright (c) 2023 John Millikin <john@john-millikin.com>
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+//
+// SPDX-License-Identifier: 0BSD
+
+// SANE backend used by tests of the dynamic loader. Entry points are
+// exported with the versioned `sane_test_*` and `sane_test_x_*` names only,
+// so the library must be loaded as `libsane-test.so` or `libsane-test-x.so`.
+
+use core::ffi::CStr;
+
+use sane::backend::Backend;
+use sane::util;
+use sane::{Action, AuthCallback, Bool, Int, Parameters, Status};
+
+const fn cstr(bytes: &[u8]) -> &CStr {
+	unsafe { CStr::from_bytes_with_nul_unchecked(bytes) }
+}
+
+const CSTR_EMPTY: &CStr = cstr(b"\x00");
+const CSTR_DEV_NAME: &CStr = cstr(b"test-device\x00");
+const CSTR_OPT_RESOLUTION: &CStr = cstr(b"resolution\x00");
+
+const TEST_IMAGE: &[u8] = b"image data";
+
+struct TestBackend;
+
+struct TestDevice {
+	resolution: i32,
+	image_pos: Option<usize>,
+}
+
+impl Backend for TestBackend {
+	type Device = TestDevice;
+
+	fn init(_authorize: Option<AuthCallback>) -> Result<Self, Status> {
+		Ok(TestBackend)
+	}
+
+	fn version_code(&self) -> u32 {
+		sane::version_code(1, 0, 1)
+	}
+
+	fn get_devices(
+		&mut self,
+		_local_only: bool,
+	) -> Result<Vec<util::DeviceBuf>, Status> {
+		Ok(vec![util::DeviceBuf::new(CSTR_DEV_NAME)])
+	}
+
+	fn open(&mut self, device_name: &CStr) -> Result<TestDevice, Status> {
+		if device_name != CSTR_DEV_NAME {
+			return Err(Status::INVAL);
+		}
+		Ok(TestDevice {
+			resolution: 300,
+			image_pos: None,
+		})
+	}
+
+	fn close(&mut self, _device: TestDevice) {}
+
+	fn get_option_descriptors(
+		&mut self,
+		_device: &mut TestDevice,
+	) -> Vec<util::OptionDescriptorBuf> {
+		vec![
+			util::IntOptionBuilder::new(CSTR_EMPTY).build(),
+			util::IntOptionBuilder::new(CSTR_OPT_RESOLUTION)
+				.range(75, 600, 1)
+				.build(),
+		]
+	}
+
+	fn control_option(
+		&mut self,
+		device: &mut TestDevice,
+		option: u32,
+		action: Action,
+		value: &mut [u8],
+	) -> Result<u32, Status> {
+		let mut word = [0u8; 4];
+		word.copy_from_slice(value);
+		let value_i32 = i32::from_ne_bytes(word);
+		let new_value = match (option, action) {
+			(0, Action::GET_VALUE) => 2,
+			(1, Action::GET_VALUE) => device.resolution,
+			(1, Action::SET_VALUE) => {
+				device.resolution = value_i32.clamp(75, 600);
+				device.resolution
+			},
+			_ => return Err(Status::INVAL),
+		};
+		value.copy_from_slice(&new_value.to_ne_bytes());
+		if new_value != value_i32 && action == Action::SET_VALUE {
+			return Ok(sane::INFO_INEXACT);
+		}
+		Ok(0)
+	}
+
+	fn get_parameters(
+		&mut self,
+		_device: &mut TestDevice,
+	) -> Result<Parameters, Status> {
+		let mut params = Parameters::new();
+		params.last_frame = Bool::TRUE;
+		params.bytes_per_line = Int::new(TEST_IMAGE.len() as i32);
+		params.pixels_per_line = Int::new(TEST_IMAGE.len() as i32);
+		params.lines = Int::new(1);
+		params.depth = Int::new(8);
+		Ok(params)
+	}
+
+	fn start(&mut self, device: &mut TestDevice) -> Result<(), Status> {
+		device.image_pos = Some(0);
+		Ok(())
+	}
+
+	fn read(
+		&mut self,
+		device: &mut TestDevice,
+		buf: &mut [u8],
+	) -> Result<usize, Status> {
+		let pos = device.image_pos.ok_or(Status::CANCELLED)?;
+		let remaining = &TEST_IMAGE[pos..];
+		if remaining.is_empty() {
+			return Err(Status::EOF);
+		}
+		let count = remaining.len().min(buf.len());
+		buf[..count].copy_from_slice(&remaining[..count]);
+		device.image_pos = Some(pos + count);
+		Ok(count)
+	}
+
+	fn cancel(&mut self, device: &mut TestDevice) {
+		device.image_pos = None;
+	}
+}
+
+sane::export_backend!(TestBackend, "test");
+sane::export_backend!(TestBackend, "test_x");